@@ -0,0 +1,364 @@
+use std::any::Any;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::{
+    auth::User,
+    http::{Header, Request, Response},
+    server::WebServer
+};
+
+
+/// # JWT validation
+/// The key and the claims checks used by `jwt_required` to validate JSON Web Tokens.
+/// The signature and the `exp` claim are always checked, `nbf` is checked if present.
+/// ## Example
+/// ```rust
+/// let jwt = Jwt::hs256(b"secret")
+///     .issuer("https://auth.example.com")
+///     .audience("reports")
+///     .require_claim("scope", "reports:read");
+/// ```
+pub struct Jwt {
+    key: DecodingKey,
+    validation: Validation,
+    claims: Vec<(String, Value)>
+}
+
+
+impl Jwt {
+    fn new(key: DecodingKey, algorithm: Algorithm) -> Self {
+        let mut validation = Validation::new(algorithm);
+        validation.validate_nbf = true;
+        Self {
+            key,
+            validation,
+            claims: Vec::new()
+        }
+    }
+
+    /// Validate tokens signed with HMAC-SHA256 and a shared secret
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self::new(DecodingKey::from_secret(secret), Algorithm::HS256)
+    }
+
+    /// Validate tokens signed with RSA-SHA256, using a PEM encoded public key
+    pub fn rs256(public_key: &[u8]) -> Result<Self, jsonwebtoken::errors::Error> {
+        Ok(Self::new(DecodingKey::from_rsa_pem(public_key)?, Algorithm::RS256))
+    }
+
+    /// Only accept tokens with the given `iss` claim
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    /// Only accept tokens with the given `aud` claim
+    pub fn audience(mut self, audience: &str) -> Self {
+        self.validation.set_audience(&[audience]);
+        self
+    }
+
+    /// The number of seconds the `exp` and `nbf` claims may be off by (60 by default)
+    pub fn leeway(mut self, seconds: u64) -> Self {
+        self.validation.leeway = seconds;
+        self
+    }
+
+    /// Only accept tokens, which contain a claim with the given value
+    pub fn require_claim(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.claims.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// # Decode a token
+    /// Checks the signature and the claims of a token, and deserializes its claims.
+    /// ## Returns
+    /// - `Some(claims)` if the token is valid
+    /// - `None` if the token is invalid, expired, or its claims do not match
+    pub fn decode<C: DeserializeOwned>(&self, token: &str) -> Option<C> {
+        let claims = match decode::<Value>(token, &self.key, &self.validation) {
+            Ok(data) => data.claims,
+            Err(_) => return None
+        };
+
+        let matches = self.claims
+            .iter()
+            .all(|(name, value)| claims.get(name) == Some(value));
+        match matches {
+            true => serde_json::from_value(claims).ok(),
+            false => None
+        }
+    }
+}
+
+
+/// Get the credentials of the `Authorization` header, if it uses the given scheme
+fn authorization<'a>(request: &'a Request, scheme: &str) -> Option<&'a str> {
+    let (name, credentials) = request.headers.authorization()?.trim().split_once(' ')?;
+    match name.eq_ignore_ascii_case(scheme) {
+        true => Some(credentials.trim()),
+        false => None
+    }
+}
+
+
+/// Store the result of a credential lookup in the request
+fn set_principal<T: Clone + 'static>(request: &Request, principal: T) {
+    // Let `request.user()` and `permission_required` work with the built-in user table as well
+    if let Some(user) = (&principal as &dyn Any).downcast_ref::<User>() {
+        *request.user.borrow_mut() = Some(user.clone());
+    }
+    *request.principal.borrow_mut() = Some(Box::new(principal));
+}
+
+
+/// Respond with `401 Unauthorized` and a `WWW-Authenticate` challenge
+fn challenge(server: &WebServer, request: &Request, challenge: String) -> Response {
+    let mut response = server.unauthorized_error(request);
+    response.set_header(Header::new(String::from("WWW-Authenticate"), challenge));
+    response
+}
+
+
+/// # Basic authentication required
+/// Wraps a handler function, so only clients sending valid HTTP Basic credentials can reach it.
+/// The username and the password are passed to the lookup, and the value it returns
+/// is available to the handler through `request.principal()`.
+/// Everyone else receives a `401 Unauthorized` response with a `WWW-Authenticate` challenge for the realm.
+/// ## Example
+/// ```rust
+/// server.add_path("/api/export", basic_auth_required("api", |server, username, password| {
+///     auth::authenticate(server, username, password)
+/// }, export));
+/// ```
+pub fn basic_auth_required<T, L, F>(realm: &str, lookup: L, handler: F) -> impl Fn(&WebServer, &Request) -> Response + 'static
+where
+    T: Clone + 'static,
+    L: Fn(&WebServer, &str, &str) -> Option<T> + 'static,
+    F: Fn(&WebServer, &Request) -> Response + 'static
+{
+    let realm = format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm.replace('"', "'"));
+    move |server: &WebServer, request: &Request| {
+        let credentials = authorization(request, "Basic")
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+
+        let principal = credentials.as_deref()
+            .and_then(|credentials| credentials.split_once(':'))
+            .and_then(|(username, password)| lookup(server, username, password));
+
+        match principal {
+            Some(principal) => {
+                set_principal(request, principal);
+                handler(server, request)
+            },
+            None => challenge(server, request, realm.clone())
+        }
+    }
+}
+
+
+/// # Bearer token required
+/// Wraps a handler function, so only clients sending a valid `Authorization: Bearer <token>` header can reach it.
+/// The token is passed to the lookup, and the value it returns is available to the handler through `request.principal()`.
+/// Everyone else receives a `401 Unauthorized` response with a `WWW-Authenticate: Bearer` challenge.
+/// ## Example
+/// ```rust
+/// server.add_path("/api/reports", bearer_required(|server, token| {
+///     ApiToken::get(server.get_database(), token).ok().flatten()
+/// }, reports));
+/// ```
+pub fn bearer_required<T, L, F>(lookup: L, handler: F) -> impl Fn(&WebServer, &Request) -> Response + 'static
+where
+    T: Clone + 'static,
+    L: Fn(&WebServer, &str) -> Option<T> + 'static,
+    F: Fn(&WebServer, &Request) -> Response + 'static
+{
+    move |server: &WebServer, request: &Request| {
+        let Some(token) = authorization(request, "Bearer") else {
+            return challenge(server, request, String::from("Bearer"));
+        };
+
+        match lookup(server, token) {
+            Some(principal) => {
+                set_principal(request, principal);
+                handler(server, request)
+            },
+            None => challenge(server, request, String::from("Bearer error=\"invalid_token\""))
+        }
+    }
+}
+
+
+/// # JWT required
+/// Wraps a handler function, so only clients sending a valid JSON Web Token as a bearer token can reach it.
+/// The claims of the token are available to the handler through `request.principal::<C>()`.
+/// To look up the subject of the token, use `bearer_required` with `Jwt::decode` instead.
+/// ## Example
+/// ```rust
+/// #[derive(Clone, Deserialize)]
+/// struct Claims {
+///     sub: String
+/// }
+///
+/// server.add_path("/api/reports", jwt_required::<Claims, _>(Jwt::hs256(b"secret").audience("reports"), reports));
+/// ```
+pub fn jwt_required<C, F>(jwt: Jwt, handler: F) -> impl Fn(&WebServer, &Request) -> Response + 'static
+where
+    C: DeserializeOwned + Clone + 'static,
+    F: Fn(&WebServer, &Request) -> Response + 'static
+{
+    bearer_required(move |_, token| jwt.decode::<C>(token), handler)
+}
+
+
+/// # API key required
+/// Wraps a handler function, so only clients sending a valid API key in the given header can reach it.
+/// The key is passed to the lookup, and the value it returns is available to the handler through `request.principal()`.
+/// Everyone else receives a `401 Unauthorized` response.
+/// ## Example
+/// ```rust
+/// server.add_path("/api/reports", api_key_required("X-API-Key", |server, key| {
+///     ApiKey::get(server.get_database(), key).ok().flatten()
+/// }, reports));
+/// ```
+pub fn api_key_required<T, L, F>(header: &str, lookup: L, handler: F) -> impl Fn(&WebServer, &Request) -> Response + 'static
+where
+    T: Clone + 'static,
+    L: Fn(&WebServer, &str) -> Option<T> + 'static,
+    F: Fn(&WebServer, &Request) -> Response + 'static
+{
+    let header = header.to_string();
+    move |server: &WebServer, request: &Request| {
+        let principal = request.header(&header)
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .and_then(|key| lookup(server, key));
+
+        match principal {
+            Some(principal) => {
+                set_principal(request, principal);
+                handler(server, request)
+            },
+            None => server.unauthorized_error(request)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use jsonwebtoken::{encode, EncodingKey, Header as JwtHeader};
+    use serde::Deserialize;
+    use serde_json::json;
+    use crate::http::Status;
+    use super::*;
+
+    fn request(headers: &str) -> Request {
+        Request::from_bytes(format!("GET /api HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n").as_bytes())
+    }
+
+    /// A handler, which responds with the principal it received
+    fn echo(_server: &WebServer, request: &Request) -> Response {
+        Response::new(Status::OK, request.principal::<String>().unwrap_or_default())
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn token(secret: &[u8], claims: Value) -> String {
+        encode(&JwtHeader::default(), &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    #[derive(Clone, Deserialize)]
+    struct Claims {
+        sub: String
+    }
+
+    #[test]
+    fn basic_auth() {
+        let server = WebServer::new();
+        let handler = basic_auth_required("api \"v1\"", |_, username, password| {
+            (username == "bob" && password == "p:ss").then(|| username.to_string())
+        }, echo);
+
+        let response = handler(&server, &request(&format!("Authorization: basic {}\r\n", STANDARD.encode("bob:p:ss"))));
+        assert_eq!(response.status, Status::OK);
+        assert_eq!(response.body, b"bob");
+
+        for headers in [
+            String::new(),
+            format!("Authorization: Basic {}\r\n", STANDARD.encode("bob:wrong")),
+            String::from("Authorization: Basic not-base64!\r\n"),
+            format!("Authorization: Bearer {}\r\n", STANDARD.encode("bob:p:ss"))
+        ] {
+            let response = handler(&server, &request(&headers));
+            assert_eq!(response.status, Status::Unauthorized);
+            assert_eq!(response.header("WWW-Authenticate"), Some("Basic realm=\"api 'v1'\", charset=\"UTF-8\""));
+        }
+    }
+
+    #[test]
+    fn bearer_tokens() {
+        let server = WebServer::new();
+        let handler = bearer_required(|_, token| (token == "t0ken").then(|| String::from("client")), echo);
+
+        let response = handler(&server, &request("Authorization: Bearer t0ken\r\n"));
+        assert_eq!(response.body, b"client");
+
+        let response = handler(&server, &request(""));
+        assert_eq!(response.status, Status::Unauthorized);
+        assert_eq!(response.header("WWW-Authenticate"), Some("Bearer"));
+
+        let response = handler(&server, &request("Authorization: Bearer wrong\r\n"));
+        assert_eq!(response.status, Status::Unauthorized);
+        assert_eq!(response.header("WWW-Authenticate"), Some("Bearer error=\"invalid_token\""));
+    }
+
+    #[test]
+    fn api_keys() {
+        let server = WebServer::new();
+        let handler = api_key_required("X-API-Key", |_, key| (key == "k3y").then(|| String::from("client")), echo);
+        assert_eq!(handler(&server, &request("x-api-key:  k3y \r\n")).body, b"client");
+        assert_eq!(handler(&server, &request("X-API-Key: other\r\n")).status, Status::Unauthorized);
+        assert_eq!(handler(&server, &request("X-API-Key: \r\n")).status, Status::Unauthorized);
+    }
+
+    #[test]
+    fn jwt_signature_and_expiry() {
+        let server = WebServer::new();
+        let handler = jwt_required::<Claims, _>(Jwt::hs256(b"secret").audience("reports"), |_, request| {
+            Response::new(Status::OK, request.principal::<Claims>().unwrap().sub)
+        });
+        let claims = |exp: u64| json!({"sub": "bob", "aud": "reports", "exp": exp});
+        let status = |token: String| handler(&server, &request(&format!("Authorization: Bearer {token}\r\n"))).status;
+
+        let response = handler(&server, &request(&format!("Authorization: Bearer {}\r\n", token(b"secret", claims(now() + 60)))));
+        assert_eq!(response.status, Status::OK);
+        assert_eq!(response.body, b"bob");
+
+        assert_eq!(status(token(b"other", claims(now() + 60))), Status::Unauthorized);
+        assert_eq!(status(token(b"secret", claims(now() - 120))), Status::Unauthorized);
+        assert_eq!(status(token(b"secret", json!({"sub": "bob", "aud": "billing", "exp": now() + 60}))), Status::Unauthorized);
+        assert_eq!(status(token(b"secret", json!({"sub": "bob", "aud": "reports", "exp": now() + 600, "nbf": now() + 300}))), Status::Unauthorized);
+
+        // A token with its signature replaced
+        let valid = token(b"secret", claims(now() + 60));
+        let (unsigned, _) = valid.rsplit_once('.').unwrap();
+        assert_eq!(status(format!("{unsigned}.AAAA")), Status::Unauthorized);
+    }
+
+    #[test]
+    fn jwt_required_claims() {
+        let jwt = Jwt::hs256(b"secret").require_claim("scope", "reports:read").leeway(0);
+        let valid = token(b"secret", json!({"sub": "bob", "scope": "reports:read", "exp": now() + 60}));
+        let other = token(b"secret", json!({"sub": "bob", "scope": "reports:write", "exp": now() + 60}));
+        assert_eq!(jwt.decode::<Claims>(&valid).map(|claims| claims.sub), Some(String::from("bob")));
+        assert!(jwt.decode::<Claims>(&other).is_none());
+        assert!(jwt.decode::<Claims>("not.a.token").is_none());
+    }
+}
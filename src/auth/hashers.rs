@@ -0,0 +1,86 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+use crate::crypto::{constant_time_eq, random_string};
+
+
+/// The name of the hashing algorithm, stored in front of every hash
+pub const ALGORITHM: &str = "pbkdf2_sha256";
+
+/// The number of PBKDF2 iterations used for new hashes (the same as Django).
+/// Tests use fewer, since the hashes of unoptimized builds take seconds.
+pub const ITERATIONS: u32 = if cfg!(test) { 1_000 } else { 600_000 };
+
+
+/// # Hash a password
+/// Hashes a password with PBKDF2-SHA256 and a random salt.
+/// The result is stored in Django's format: `pbkdf2_sha256$<iterations>$<salt>$<hash>`,
+/// so it can be checked with `check_password`, even after the default iteration count changed.
+pub fn make_password(password: &str) -> String {
+    let salt: String = random_string(22);
+    encode(password, &salt, ITERATIONS)
+}
+
+
+/// # Check a password
+/// Tests whether a password matches a hash created with `make_password`.
+/// The hashes are compared in constant time.
+pub fn check_password(password: &str, encoded: &str) -> bool {
+    let mut parts = encoded.splitn(4, '$');
+    let (Some(algorithm), Some(iterations), Some(salt), Some(_)) =
+        (parts.next(), parts.next(), parts.next(), parts.next()) else { return false };
+
+    if algorithm != ALGORITHM {
+        return false;
+    }
+    let Ok(iterations) = iterations.parse::<u32>() else { return false };
+
+    constant_time_eq(encode(password, salt, iterations).as_bytes(), encoded.as_bytes())
+}
+
+
+/// A hash, which no password matches. Useful for users who may not log in with a password.
+pub fn unusable_password() -> String {
+    String::from("!")
+}
+
+
+fn encode(password: &str, salt: &str, iterations: u32) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), iterations, &mut hash);
+    format!("{ALGORITHM}${iterations}${salt}${}", STANDARD.encode(hash))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_django_hashes() {
+        // Created with Python's hashlib.pbkdf2_hmac("sha256", b"hunter2", b"seasalt", 1000)
+        let encoded = "pbkdf2_sha256$1000$seasalt$aZOLUDnbVq4qfmIhIFCkAqvDNHspRzj9l43SgVe7GOM=";
+        assert!(check_password("hunter2", encoded));
+        assert!(!check_password("hunter3", encoded));
+        assert!(!check_password("hunter2", &encoded.replace("1000", "1001")));
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        let first = make_password("correct horse");
+        let second = make_password("correct horse");
+        assert!(first.starts_with(&format!("{ALGORITHM}${ITERATIONS}$")));
+        assert_ne!(first, second);
+        assert!(check_password("correct horse", &first));
+        assert!(check_password("correct horse", &second));
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        assert!(!check_password("", &unusable_password()));
+        assert!(!check_password("hunter2", "md5$1000$seasalt$aZOLUDnbVq4qfmIhIFCkAqvDNHspRzj9l43SgVe7GOM="));
+        assert!(!check_password("hunter2", "pbkdf2_sha256$many$seasalt$aZOLUDnbVq4qfmIhIFCkAqvDNHspRzj9l43SgVe7GOM="));
+        assert!(!check_password("hunter2", "pbkdf2_sha256$1000$seasalt"));
+    }
+}
@@ -0,0 +1,397 @@
+mod guards;
+mod hashers;
+mod permissions;
+
+pub use guards::{Jwt, basic_auth_required, bearer_required, jwt_required, api_key_required};
+pub use hashers::{make_password, check_password, unusable_password};
+pub use permissions::{
+    Permission, Group, UserGroup, UserPermission, GroupPermission,
+    add_permission_tables, permission_required, has_perm_function
+};
+
+use std::collections::HashSet;
+use minijinja::{context, Value};
+use table_macro::table;
+use crate::{
+    http::{Request, Response},
+    middleware::{rotate_csrf_token, Middleware},
+    server::WebServer,
+    sql::{self, Database}
+};
+
+
+/// The session key, under which the ID of the logged in user is stored
+pub const SESSION_KEY: &str = "_auth_user_id";
+
+
+/// # User
+/// The built-in user table of Aerielle, stored as `aerielle_user`.
+/// Add it to the database before using authentication:
+/// ```rust
+/// database.add_table::<User>();
+/// ```
+/// The password is never stored in plain text, only as a salted PBKDF2 hash.
+#[table(name = "aerielle_user")]
+pub struct User {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[unique]
+    #[not_null]
+    username: Text,
+
+    #[not_null]
+    password: Text,
+
+    #[not_null]
+    #[default = ""]
+    email: Text,
+
+    #[not_null]
+    #[default = "true"]
+    is_active: Boolean,
+
+    #[not_null]
+    #[default = "false"]
+    is_superuser: Boolean
+}
+
+
+impl User {
+    /// Hash and set the password of the user (the user still has to be saved)
+    pub fn set_password(&mut self, password: &str) {
+        self.password = make_password(password);
+    }
+
+    /// Test whether the given password matches the password of the user
+    pub fn check_password(&self, password: &str) -> bool {
+        check_password(password, &self.password)
+    }
+
+    /// # Create a user
+    /// Inserts a new, active user with a hashed password into the database.
+    /// ## Example
+    /// ```rust
+    /// let user = User::create(server.get_database(), "bob", "correct horse battery staple").unwrap();
+    /// ```
+    pub fn create(database: &Database, username: &str, password: &str) -> Result<User, sqlx::Error> {
+        let query = format!(
+            "INSERT INTO {table} (username, password, email, is_active, is_superuser) VALUES ({p1}, {p2}, {p3}, {p4}, {p5})",
+            table=Self::table_name(),
+            p1=database.placeholder(1),
+            p2=database.placeholder(2),
+            p3=database.placeholder(3),
+            p4=database.placeholder(4),
+            p5=database.placeholder(5)
+        );
+        let (name, hash) = (username.to_string(), make_password(password));
+
+        database.run(move |pool| async move {
+            sqlx::query(&query)
+                .bind(name)
+                .bind(hash)
+                .bind("")
+                .bind(true)
+                .bind(false)
+                .execute(&pool)
+                .await
+        })?;
+
+        Self::get_by_username(database, username)?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// Get a user by their ID
+    pub fn get(database: &Database, id: i32) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            "SELECT * FROM {table} WHERE id = {p1}",
+            table=Self::table_name(),
+            p1=database.placeholder(1)
+        );
+        database.run(move |pool| async move {
+            sqlx::query_as::<_, User>(&query)
+                .bind(id)
+                .fetch_optional(&pool)
+                .await
+        })
+    }
+
+    /// Get a user by their username
+    pub fn get_by_username(database: &Database, username: &str) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            "SELECT * FROM {table} WHERE username = {p1}",
+            table=Self::table_name(),
+            p1=database.placeholder(1)
+        );
+        let username = username.to_string();
+        database.run(move |pool| async move {
+            sqlx::query_as::<_, User>(&query)
+                .bind(username)
+                .fetch_optional(&pool)
+                .await
+        })
+    }
+}
+
+
+/// # Authenticate a user
+/// Looks up a user by their username, and checks their password.
+/// ## Returns
+/// This function returns an `Option<User>`:
+/// - `Some(User)` if the user exists, is active, and the password is correct
+/// - `None` otherwise
+pub fn authenticate(server: &WebServer, username: &str, password: &str) -> Option<User> {
+    match User::get_by_username(server.get_database(), username) {
+        Ok(Some(user)) => match user.is_active && user.check_password(password) {
+            true => Some(user),
+            false => None
+        },
+        Ok(None) => {
+            // Hash anyway, so the response time does not reveal whether the username exists
+            make_password(password);
+            None
+        },
+        Err(e) => {
+            eprintln!("Unable to authenticate user \"{username}\": {e}");
+            None
+        }
+    }
+}
+
+
+/// # Log in a user
+/// Stores the user in the session of the request, rotating the session key and the CSRF token.
+/// ## Example
+/// ```rust
+/// fn login_view(server: &WebServer, request: &Request) -> Response {
+///     let form = match request.form() {
+///         Ok(form) => form,
+///         Err(response) => return response
+///     };
+///     let username = form.get("username").unwrap_or_default();
+///     let password = form.get("password").unwrap_or_default();
+///
+///     match auth::authenticate(server, username, password) {
+///         Some(user) => {
+///             auth::login(request, &user);
+///             Response::redirect("/")
+///         },
+///         None => server.render("login.html", context!(error => "Invalid credentials"))
+///     }
+/// }
+/// ```
+pub fn login(request: &Request, user: &User) {
+    let session = request.session();
+
+    // A different user was logged in before, so drop their data
+    match session.get::<i32>(SESSION_KEY) {
+        Some(id) if id != user.id => session.flush(),
+        _ => session.cycle_key()
+    }
+
+    session.set(SESSION_KEY, user.id);
+    rotate_csrf_token(request);
+    *request.user.borrow_mut() = Some(user.clone());
+}
+
+
+/// # Log out the user
+/// Removes every value from the session of the request, and deletes the session.
+pub fn logout(request: &Request) {
+    request.session().flush();
+    *request.user.borrow_mut() = None;
+    request.permissions.borrow_mut().clear();
+}
+
+
+/// # Login required
+/// Wraps a handler function, so only logged in users can reach it.
+/// Anonymous users are redirected to the login URL of the server (`WebServer::set_login_url`),
+/// with the requested URL in the `next` query parameter.
+/// ## Example
+/// ```rust
+/// server.add_path("/profile", login_required(profile));
+/// ```
+pub fn login_required<F>(handler: F) -> impl Fn(&WebServer, &Request) -> Response + 'static
+where
+    F: Fn(&WebServer, &Request) -> Response + 'static
+{
+    move |server: &WebServer, request: &Request| {
+        if request.is_authenticated() {
+            return handler(server, request);
+        }
+
+        let next = serde_urlencoded::to_string([("next", request.url.as_str())]).unwrap_or_default();
+        Response::redirect(&format!("{}?{next}", server.get_login_url()))
+    }
+}
+
+
+/// # Authentication middleware
+/// Loads the logged in user of every request and their permissions,
+/// making them available through `request.user()` and `request.has_perm()`.
+/// It has to be added after the `SessionMiddleware`.
+///
+/// Templates rendered with `WebServer::render_request` receive the `user` and `perms` variables,
+/// and the `has_perm` function, which is also available as `user.has_perm`.
+pub struct AuthMiddleware;
+
+
+impl AuthMiddleware {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+
+impl Default for AuthMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Middleware for AuthMiddleware {
+    fn before(&self, server: &WebServer, request: &mut Request) -> Option<Response> {
+        let session = match &request.session {
+            Some(session) => session,
+            None => panic!("The AuthMiddleware requires the SessionMiddleware to be added before it.")
+        };
+
+        let id = session.get::<i32>(SESSION_KEY)?;
+        let user = match User::get(server.get_database(), id) {
+            Ok(Some(user)) if user.is_active => user,
+            Ok(_) => return None,
+            Err(e) => {
+                eprintln!("Unable to load user {id}: {e}");
+                return None
+            }
+        };
+
+        // Superusers have every permission, so there is nothing to load
+        if !user.is_superuser {
+            match user.get_all_permissions(server.get_database()) {
+                Ok(permissions) => *request.permissions.borrow_mut() = permissions,
+                Err(e) => eprintln!("Unable to load the permissions of user {id}: {e}")
+            }
+        }
+        *request.user.borrow_mut() = Some(user);
+        None
+    }
+
+    /// The permissions are checked by a function, since templates can shadow the `user` and `perms` variables
+    fn context(&self, _server: &WebServer, request: &Request) -> Option<Value> {
+        let user = request.user.borrow();
+        let permissions: HashSet<String> = request.permissions.borrow().clone();
+        let (is_active, is_superuser) = user
+            .as_ref()
+            .map_or((false, false), |user| (user.is_active, user.is_superuser));
+        let has_perm = Value::from_function(move |permission: &str| -> bool {
+            is_active && (is_superuser || permissions.contains(permission))
+        });
+
+        let user = user.as_ref().map(|user| context!(
+            id => user.id,
+            username => user.username,
+            email => user.email,
+            is_superuser => user.is_superuser,
+            has_perm => has_perm.clone()
+        ));
+
+        let mut perms: Vec<String> = request.permissions.borrow().iter().cloned().collect();
+        perms.sort();
+
+        Some(context!(user, perms, _has_perm => has_perm))
+    }
+}
+
+
+#[cfg(all(test, feature = "database_sqlite"))]
+mod tests {
+    use tempfile::TempDir;
+    use serde_json::Value;
+    use crate::{session::{SessionData, SessionValues}, sql::tests::database};
+    use super::*;
+
+    /// A server with the user table, and a user "bob" with the password "secret"
+    fn server() -> (WebServer, User, TempDir) {
+        let (mut db, dir) = database();
+        db.add_table::<User>();
+        let user = User::create(&db, "bob", "secret").unwrap();
+        let mut server = WebServer::new();
+        server.connect_to_database(db);
+        (server, user, dir)
+    }
+
+    /// A request with an empty session, like the `SessionMiddleware` would load it
+    fn request() -> Request {
+        let mut request = Request::from_bytes(b"GET /profile HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        request.session = Some(SessionData::default());
+        request
+    }
+
+    #[test]
+    fn authenticates_active_users() {
+        let (server, mut user, _dir) = server();
+        assert_ne!(user.password, "secret");
+        assert!(authenticate(&server, "bob", "secret").is_some());
+        assert!(authenticate(&server, "bob", "wrong").is_none());
+        assert!(authenticate(&server, "alice", "secret").is_none());
+
+        user.is_active = false;
+        user.save(server.get_database()).unwrap();
+        assert!(authenticate(&server, "bob", "secret").is_none());
+    }
+
+    #[test]
+    fn login_cycles_the_session() {
+        let (_server, user, _dir) = server();
+        let mut request = request();
+        let values = SessionValues::from([(String::from("cart"), Value::from(3))]);
+        request.session = Some(SessionData::new(Some(String::from("old")), values));
+
+        login(&request, &user);
+        let session = request.session();
+        assert_eq!(session.key(), None);
+        assert_eq!(session.get::<i32>(SESSION_KEY), Some(user.id));
+        assert_eq!(session.get::<i32>("cart"), Some(3));
+        assert!(request.is_authenticated());
+    }
+
+    #[test]
+    fn login_as_another_user_flushes_the_session() {
+        let (server, user, _dir) = server();
+        let other = User::create(server.get_database(), "alice", "secret").unwrap();
+        let request = request();
+        login(&request, &user);
+        request.session().set("cart", 3);
+
+        login(&request, &other);
+        assert_eq!(request.session().get::<i32>(SESSION_KEY), Some(other.id));
+        assert!(!request.session().contains("cart"));
+    }
+
+    #[test]
+    fn logout_flushes_the_session() {
+        let (_server, user, _dir) = server();
+        let request = request();
+        login(&request, &user);
+        logout(&request);
+        assert!(request.session().is_empty());
+        assert!(!request.is_authenticated());
+    }
+
+    #[test]
+    fn middleware_loads_the_user() {
+        let (server, user, _dir) = server();
+        let mut request = request();
+        request.session().set(SESSION_KEY, user.id);
+        assert!(AuthMiddleware::new().before(&server, &mut request).is_none());
+        assert_eq!(request.user().map(|user| user.username), Some(String::from("bob")));
+
+        let mut anonymous = self::request();
+        anonymous.session().set(SESSION_KEY, user.id + 1);
+        AuthMiddleware::new().before(&server, &mut anonymous);
+        assert!(!anonymous.is_authenticated());
+    }
+}
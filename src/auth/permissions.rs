@@ -0,0 +1,445 @@
+use std::collections::HashSet;
+use minijinja::{State, Value};
+use table_macro::table;
+use crate::{
+    auth::User,
+    http::{Request, Response},
+    server::WebServer,
+    sql::{self, Database}
+};
+
+
+/// # Permission
+/// A named permission, like `blog.edit_post`, stored in the `aerielle_permission` table.
+/// Permissions are given to users directly, or through their groups.
+#[table(name = "aerielle_permission")]
+pub struct Permission {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    // The full name of the permission, like "blog.edit_post"
+    #[unique]
+    #[not_null]
+    codename: Text,
+
+    // A human-readable description
+    #[not_null]
+    #[default = ""]
+    name: Text
+}
+
+
+/// # Group
+/// A named collection of permissions, stored in the `aerielle_group` table.
+/// Every user in the group has every permission of the group.
+#[table(name = "aerielle_group")]
+pub struct Group {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[unique]
+    #[not_null]
+    name: Text
+}
+
+
+/// The groups of the users
+#[table(name = "aerielle_user_groups", unique_together = "user_id, group_id")]
+pub struct UserGroup {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    #[foreign_key(User, on_delete = "cascade")]
+    user_id: Integer,
+
+    #[not_null]
+    #[foreign_key(Group, on_delete = "cascade")]
+    group_id: Integer
+}
+
+
+/// The permissions given directly to users
+#[table(name = "aerielle_user_permissions", unique_together = "user_id, permission_id")]
+pub struct UserPermission {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    #[foreign_key(User, on_delete = "cascade")]
+    user_id: Integer,
+
+    #[not_null]
+    #[foreign_key(Permission, on_delete = "cascade")]
+    permission_id: Integer
+}
+
+
+/// The permissions of the groups
+#[table(name = "aerielle_group_permissions", unique_together = "group_id, permission_id")]
+pub struct GroupPermission {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    #[foreign_key(Group, on_delete = "cascade")]
+    group_id: Integer,
+
+    #[not_null]
+    #[foreign_key(Permission, on_delete = "cascade")]
+    permission_id: Integer
+}
+
+
+/// Add every table used by permissions and groups to the database
+pub fn add_permission_tables(database: &mut Database) {
+    database.add_table::<Permission>();
+    database.add_table::<Group>();
+    database.add_table::<UserGroup>();
+    database.add_table::<UserPermission>();
+    database.add_table::<GroupPermission>();
+}
+
+
+/// Insert a row into a junction table, unless it already exists
+fn link(database: &Database, table: &'static str, columns: (&'static str, &'static str), ids: (i32, i32)) -> Result<(), sqlx::Error> {
+    // The pair is unique, so concurrent links of the same pair insert it only once
+    let insert = database.dialect().insert_or_ignore(
+        table,
+        &[columns.0, columns.1],
+        &[database.placeholder(1), database.placeholder(2)]
+    );
+
+    database.run(move |pool| async move {
+        sqlx::query(&insert)
+            .bind(ids.0)
+            .bind(ids.1)
+            .execute(&pool)
+            .await
+            .map(|_| ())
+    })
+}
+
+
+/// Remove a row from a junction table
+fn unlink(database: &Database, table: &'static str, columns: (&'static str, &'static str), ids: (i32, i32)) -> Result<(), sqlx::Error> {
+    let delete = format!(
+        "DELETE FROM {table} WHERE {first} = {p1} AND {second} = {p2}",
+        first=columns.0,
+        second=columns.1,
+        p1=database.placeholder(1),
+        p2=database.placeholder(2)
+    );
+
+    database.run(move |pool| async move {
+        sqlx::query(&delete)
+            .bind(ids.0)
+            .bind(ids.1)
+            .execute(&pool)
+            .await
+            .map(|_| ())
+    })
+}
+
+
+impl Permission {
+    /// Create a permission, like `Permission::create(db, "blog.edit_post", "Can edit posts")`
+    pub fn create(database: &Database, codename: &str, name: &str) -> Result<Permission, sqlx::Error> {
+        let query = format!(
+            "INSERT INTO {table} (codename, name) VALUES ({p1}, {p2})",
+            table=Self::table_name(),
+            p1=database.placeholder(1),
+            p2=database.placeholder(2)
+        );
+        let (codename_value, name) = (codename.to_string(), name.to_string());
+
+        database.run(move |pool| async move {
+            sqlx::query(&query)
+                .bind(codename_value)
+                .bind(name)
+                .execute(&pool)
+                .await
+        })?;
+
+        Self::get(database, codename)?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// Get a permission by its codename
+    pub fn get(database: &Database, codename: &str) -> Result<Option<Permission>, sqlx::Error> {
+        let query = format!(
+            "SELECT * FROM {table} WHERE codename = {p1}",
+            table=Self::table_name(),
+            p1=database.placeholder(1)
+        );
+        let codename = codename.to_string();
+
+        database.run(move |pool| async move {
+            sqlx::query_as::<_, Permission>(&query)
+                .bind(codename)
+                .fetch_optional(&pool)
+                .await
+        })
+    }
+}
+
+
+impl Group {
+    pub fn create(database: &Database, name: &str) -> Result<Group, sqlx::Error> {
+        let query = format!(
+            "INSERT INTO {table} (name) VALUES ({p1})",
+            table=Self::table_name(),
+            p1=database.placeholder(1)
+        );
+        let name_value = name.to_string();
+
+        database.run(move |pool| async move {
+            sqlx::query(&query)
+                .bind(name_value)
+                .execute(&pool)
+                .await
+        })?;
+
+        Self::get(database, name)?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// Get a group by its name
+    pub fn get(database: &Database, name: &str) -> Result<Option<Group>, sqlx::Error> {
+        let query = format!(
+            "SELECT * FROM {table} WHERE name = {p1}",
+            table=Self::table_name(),
+            p1=database.placeholder(1)
+        );
+        let name = name.to_string();
+
+        database.run(move |pool| async move {
+            sqlx::query_as::<_, Group>(&query)
+                .bind(name)
+                .fetch_optional(&pool)
+                .await
+        })
+    }
+
+    pub fn add_permission(&self, database: &Database, permission: &Permission) -> Result<(), sqlx::Error> {
+        link(database, GroupPermission::table_name(), ("group_id", "permission_id"), (self.id, permission.id))
+    }
+
+    pub fn remove_permission(&self, database: &Database, permission: &Permission) -> Result<(), sqlx::Error> {
+        unlink(database, GroupPermission::table_name(), ("group_id", "permission_id"), (self.id, permission.id))
+    }
+}
+
+
+impl User {
+    pub fn add_to_group(&self, database: &Database, group: &Group) -> Result<(), sqlx::Error> {
+        link(database, UserGroup::table_name(), ("user_id", "group_id"), (self.id, group.id))
+    }
+
+    pub fn remove_from_group(&self, database: &Database, group: &Group) -> Result<(), sqlx::Error> {
+        unlink(database, UserGroup::table_name(), ("user_id", "group_id"), (self.id, group.id))
+    }
+
+    pub fn add_permission(&self, database: &Database, permission: &Permission) -> Result<(), sqlx::Error> {
+        link(database, UserPermission::table_name(), ("user_id", "permission_id"), (self.id, permission.id))
+    }
+
+    pub fn remove_permission(&self, database: &Database, permission: &Permission) -> Result<(), sqlx::Error> {
+        unlink(database, UserPermission::table_name(), ("user_id", "permission_id"), (self.id, permission.id))
+    }
+
+    /// Get the codenames of every permission of the user, given directly or through a group
+    pub fn get_all_permissions(&self, database: &Database) -> Result<HashSet<String>, sqlx::Error> {
+        let query = format!(
+            "SELECT p.codename FROM {permission} p \
+                JOIN {user_permission} up ON up.permission_id = p.id \
+                WHERE up.user_id = {p1} \
+            UNION \
+            SELECT p.codename FROM {permission} p \
+                JOIN {group_permission} gp ON gp.permission_id = p.id \
+                JOIN {user_group} ug ON ug.group_id = gp.group_id \
+                WHERE ug.user_id = {p2}",
+            permission=Permission::table_name(),
+            user_permission=UserPermission::table_name(),
+            group_permission=GroupPermission::table_name(),
+            user_group=UserGroup::table_name(),
+            p1=database.placeholder(1),
+            p2=database.placeholder(2)
+        );
+        let id = self.id;
+
+        let codenames: Vec<String> = database.run(move |pool| async move {
+            sqlx::query_scalar(&query)
+                .bind(id)
+                .bind(id)
+                .fetch_all(&pool)
+                .await
+        })?;
+
+        Ok(codenames.into_iter().collect())
+    }
+
+    /// # Check a permission
+    /// Tests whether the user has a permission, like `user.has_perm(db, "blog.edit_post")`.
+    /// Inactive users have no permissions, and superusers have every permission.
+    /// Inside handlers, prefer `request.has_perm`, which does not query the database again.
+    pub fn has_perm(&self, database: &Database, permission: &str) -> bool {
+        if !self.is_active {
+            return false;
+        }
+        if self.is_superuser {
+            return true;
+        }
+
+        match self.get_all_permissions(database) {
+            Ok(permissions) => permissions.contains(permission),
+            Err(e) => {
+                eprintln!("Unable to load the permissions of user {}: {e}", self.username);
+                false
+            }
+        }
+    }
+}
+
+
+/// # Permission required
+/// Wraps a handler function, so only users with the given permission can reach it.
+/// Everyone else receives a `403 Forbidden` response.
+/// To redirect anonymous users to the login page instead, wrap it in `login_required` as well.
+/// ## Example
+/// ```rust
+/// server.add_path("/posts/edit", login_required(permission_required("blog.edit_post", edit_post)));
+/// ```
+pub fn permission_required<F>(permission: &str, handler: F) -> impl Fn(&WebServer, &Request) -> Response + 'static
+where
+    F: Fn(&WebServer, &Request) -> Response + 'static
+{
+    let permission = permission.to_string();
+    move |server: &WebServer, request: &Request| {
+        match request.has_perm(&permission) {
+            true => handler(server, request),
+            false => server.forbidden_error(request)
+        }
+    }
+}
+
+
+/// # `has_perm` template function
+/// Tests whether the logged in user has a permission, in templates rendered with `WebServer::render_request`.
+/// It uses the `_has_perm` variable of the `AuthMiddleware`, so a `user` variable of the handler
+/// or of a loop does not change the result.
+/// ```html
+/// {% if has_perm("blog.edit_post") %}
+///     <a href="/posts/edit">Edit</a>
+/// {% endif %}
+/// ```
+pub fn has_perm_function(state: &State, permission: &str) -> bool {
+    match state.lookup("_has_perm") {
+        Some(check) if !check.is_undefined() => check
+            .call(state, &[Value::from(permission)])
+            .is_ok_and(|value| value.is_true()),
+        _ => false
+    }
+}
+
+
+#[cfg(all(test, feature = "database_sqlite"))]
+mod tests {
+    use minijinja::context;
+    use tempfile::TempDir;
+    use crate::{
+        auth::{AuthMiddleware, SESSION_KEY},
+        middleware::Middleware,
+        session::SessionData,
+        sql::tests::database
+    };
+    use super::*;
+
+    /// A server with the user and permission tables, a user "bob" and an "editors" group with "blog.edit_post"
+    fn server() -> (WebServer, User, Group, TempDir) {
+        let (mut db, dir) = database();
+        db.add_table::<User>();
+        add_permission_tables(&mut db);
+
+        let user = User::create(&db, "bob", "secret").unwrap();
+        let permission = Permission::create(&db, "blog.edit_post", "Can edit posts").unwrap();
+        let group = Group::create(&db, "editors").unwrap();
+        group.add_permission(&db, &permission).unwrap();
+
+        let mut server = WebServer::new();
+        server.connect_to_database(db);
+        (server, user, group, dir)
+    }
+
+    /// Render a template with the context of the `AuthMiddleware` for the logged in user, and the given context
+    fn render(server: &WebServer, user: &User, template: &str, context: Value) -> String {
+        let mut request = Request::from_bytes(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        request.session = Some(SessionData::default());
+        request.session().set(SESSION_KEY, user.id);
+        AuthMiddleware::new().before(server, &mut request);
+
+        let middleware = AuthMiddleware::new().context(server, &request).unwrap();
+        let context = minijinja::value::merge_maps([middleware, context]);
+        server.get_environment().render_str(template, context).unwrap()
+    }
+
+    #[test]
+    fn permissions_through_groups() {
+        let (server, mut user, group, _dir) = server();
+        let db = server.get_database();
+        assert!(!user.has_perm(db, "blog.edit_post"));
+
+        user.add_to_group(db, &group).unwrap();
+        assert!(user.has_perm(db, "blog.edit_post"));
+        assert!(!user.has_perm(db, "blog.delete_post"));
+
+        user.is_active = false;
+        assert!(!user.has_perm(db, "blog.edit_post"));
+
+        user.is_active = true;
+        user.remove_from_group(db, &group).unwrap();
+        assert!(!user.has_perm(db, "blog.edit_post"));
+    }
+
+    #[test]
+    fn linking_twice_keeps_one_row() {
+        let (server, user, group, _dir) = server();
+        let db = server.get_database();
+        user.add_to_group(db, &group).unwrap();
+        user.add_to_group(db, &group).unwrap();
+        assert_eq!(db.filter_table::<UserGroup>().count().unwrap(), 1);
+
+        // The pair is unique in the table itself
+        let insert = format!("INSERT INTO {} (user_id, group_id) VALUES ({}, {})", UserGroup::table_name(), user.id, group.id);
+        assert!(db.run(move |pool| async move { sqlx::query(&insert).execute(&pool).await }).is_err());
+    }
+
+    #[test]
+    fn templates_check_the_logged_in_user() {
+        let (server, user, group, _dir) = server();
+        user.add_to_group(server.get_database(), &group).unwrap();
+
+        let template = r#"{{ has_perm("blog.edit_post") }} {{ user.has_perm("blog.edit_post") }} {{ has_perm("blog.delete_post") }}"#;
+        assert_eq!(render(&server, &user, template, context!()), "true true false");
+
+        // A user variable of the handler does not grant its permissions
+        let other = context!(user => context!(is_superuser => true), perms => vec!["blog.delete_post"]);
+        assert_eq!(
+            render(&server, &user, r#"{{ has_perm("blog.edit_post") }} {{ has_perm("blog.delete_post") }}"#, other),
+            "true false"
+        );
+
+        let looped = r#"{% for user in users %}{{ has_perm("blog.delete_post") }}{% endfor %}"#;
+        assert_eq!(render(&server, &user, looped, context!(users => vec![context!(is_superuser => true)])), "false");
+    }
+
+    #[test]
+    fn templates_without_a_user_have_no_permissions() {
+        let (server, _user, _group, _dir) = server();
+        let rendered = server.get_environment().render_str(r#"{{ has_perm("blog.edit_post") }}"#, context!(user => context!(is_superuser => true))).unwrap();
+        assert_eq!(rendered, "false");
+    }
+}
@@ -0,0 +1,22 @@
+use rand::{distributions::Alphanumeric, Rng};
+
+
+/// Generate a random string of letters and digits
+pub fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+
+/// Compare two byte strings without leaking the position of the first difference through timing
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |difference, (x, y)| difference | (x ^ y)) == 0
+}
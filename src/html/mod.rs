@@ -1,3 +1,3 @@
 mod render;
 
-pub use render::render;
+pub use render::{render, render_request};
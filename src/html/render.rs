@@ -1,7 +1,7 @@
-use minijinja::Value;
+use minijinja::{value::merge_maps, Value};
 use crate::{
     header,
-    http::{Response, Status, Header},
+    http::{Request, Response, Status, Header},
     server::WebServer
 };
 
@@ -30,4 +30,22 @@ pub fn render(
             Response::server_error()
         }
     }
-}
\ No newline at end of file
+}
+
+/// Render a template with the context of the request, provided by the middleware of the server.
+/// The given context takes precedence over the variables of the middleware.
+pub fn render_request(
+    server: &WebServer,
+    request: &Request,
+    name: &str,
+    context: Value
+) -> Response {
+    let mut contexts: Vec<Value> = server
+        .get_middleware()
+        .iter()
+        .filter_map(|middleware| middleware.context(server, request))
+        .collect();
+    contexts.push(context);
+
+    render(server, name, merge_maps(contexts))
+}
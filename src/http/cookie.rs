@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use crate::http::date::http_date;
+
+
+/// The octets, which are not allowed in a cookie name (a token, with the separators of RFC 2616)
+const NAME: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'(').add(b')').add(b',').add(b'/').add(b':').add(b';')
+    .add(b'<').add(b'=').add(b'>').add(b'?').add(b'@').add(b'[').add(b'\\').add(b']')
+    .add(b'{').add(b'}');
+
+/// The octets, which are not allowed in a cookie value
+const VALUE: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b',').add(b';').add(b'\\');
+
+/// The octets, which are not allowed in the value of an attribute, like `Path`
+const ATTRIBUTE: &AsciiSet = &CONTROLS.add(b';');
+
+
+/// The `SameSite` attribute of a cookie
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None
+}
+
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None"
+        };
+        write!(f, "{}", s)
+    }
+}
+
+
+/// # Cookie
+/// A cookie, which can be sent to the client through the `Set-Cookie` header.
+/// Octets, which are not allowed in the name, the value, the path or the domain of a cookie
+/// (like `;`, line breaks or non-ASCII characters), are percent-encoded, so they can not inject attributes or headers.
+/// ## Example
+/// ```rust
+/// use std::time::Duration;
+/// use aerielle::http::{Cookie, SameSite};
+///
+/// fn remember(server: &WebServer, request: &Request) -> Response {
+///     let mut response = Response::new(Status::OK, "Remembered");
+///     response.set_cookie(
+///         Cookie::new("theme", "dark")
+///             .path("/")
+///             .max_age(Duration::from_secs(60 * 60 * 24 * 365))
+///             .http_only(true)
+///             .same_site(SameSite::Lax)
+///     );
+///     response
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>
+}
+
+
+impl Cookie {
+    pub fn new(name: impl ToString, value: impl ToString) -> Self {
+        Self {
+            name: utf8_percent_encode(&name.to_string(), NAME).to_string(),
+            value: utf8_percent_encode(&value.to_string(), VALUE).to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None
+        }
+    }
+
+    /// A cookie, which tells the client to delete the cookie with the given name.
+    /// The `path` and `domain` have to match the ones the cookie was set with.
+    pub fn removal(name: impl ToString) -> Self {
+        Self::new(name, "")
+            .path("/")
+            .max_age(Duration::ZERO)
+            .expires(UNIX_EPOCH)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /* BUILDER */
+    pub fn path(mut self, path: impl ToString) -> Self {
+        self.path = Some(utf8_percent_encode(&path.to_string(), ATTRIBUTE).to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl ToString) -> Self {
+        self.domain = Some(utf8_percent_encode(&domain.to_string(), ATTRIBUTE).to_string());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    /// Browsers reject `SameSite=None` cookies, which are not `Secure`, so it also marks the cookie as secure.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        if same_site == SameSite::None {
+            self.secure = true;
+        }
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Parse the value of a `Cookie` request header, like `theme=dark; session=abc`.
+    /// If a cookie is sent multiple times, the first value is kept.
+    pub fn parse_header(header: &str) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        for pair in header.split(';') {
+            let Some((name, value)) = pair.split_once('=') else { continue };
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+
+            // Values may be wrapped in double quotes
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+
+            cookies
+                .entry(name.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+        cookies
+    }
+}
+
+
+impl Display for Cookie {
+    /// Format the cookie as the value of a `Set-Cookie` header
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = &self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = &self.expires {
+            write!(f, "; Expires={}", http_date(*expires))?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = &self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_attributes() {
+        let cookie = Cookie::new("theme", "dark")
+            .path("/")
+            .domain("example.com")
+            .max_age(Duration::from_secs(3600))
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(cookie.to_string(), "theme=dark; Path=/; Domain=example.com; Max-Age=3600; HttpOnly; SameSite=Lax");
+
+        let cookie = Cookie::new("id", "1").same_site(SameSite::None);
+        assert_eq!(cookie.to_string(), "id=1; Secure; SameSite=None");
+    }
+
+    #[test]
+    fn removal_expires_the_cookie() {
+        assert_eq!(
+            Cookie::removal("session").to_string(),
+            "session=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn encodes_invalid_octets() {
+        let cookie = Cookie::new("a=b; c", "x; Domain=evil.com\r\nSet-Cookie: y=z");
+        assert_eq!(cookie.name(), "a%3Db%3B%20c");
+        assert_eq!(cookie.value(), "x%3B%20Domain=evil.com%0D%0ASet-Cookie:%20y=z");
+
+        let cookie = Cookie::new("name", "caf\u{e9} \"quoted\"")
+            .path("/a;b\n")
+            .domain("example.com; Secure");
+        assert_eq!(
+            cookie.to_string(),
+            "name=caf%C3%A9%20%22quoted%22; Path=/a%3Bb%0A; Domain=example.com%3B Secure"
+        );
+    }
+
+    #[test]
+    fn parses_request_headers() {
+        let cookies = Cookie::parse_header("theme=dark; session=\"abc\"; theme=light; =empty; broken");
+        assert_eq!(cookies.get("theme").map(String::as_str), Some("dark"));
+        assert_eq!(cookies.get("session").map(String::as_str), Some("abc"));
+        assert_eq!(cookies.len(), 2);
+    }
+}
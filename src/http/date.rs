@@ -0,0 +1,43 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+
+/// Format a point in time as an HTTP date, like `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+
+    let days = seconds / 86_400;
+    let seconds_of_day = seconds % 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!(
+        "{weekday}, {day:02} {month} {year} {hour:02}:{minute:02}:{second:02} GMT",
+        weekday=DAYS[(days % 7) as usize],
+        month=MONTHS[(month - 1) as usize],
+        hour=seconds_of_day / 3600,
+        minute=(seconds_of_day % 3600) / 60,
+        second=seconds_of_day % 60
+    )
+}
+
+
+/// Convert the number of days since 1970-01-01 into a (year, month, day) triple
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,56 @@
+use std::net::IpAddr;
+
+
+/// A single element of a `Forwarded` header, added by one proxy
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForwardedElement {
+    /// The client, which connected to the proxy (`for=`)
+    pub client: Option<String>,
+    /// The protocol of the request received by the proxy (`proto=`)
+    pub proto: Option<String>,
+    /// The `Host` header of the request received by the proxy (`host=`)
+    pub host: Option<String>
+}
+
+
+/// Parse the value of a `Forwarded` header (RFC 7239),
+/// like `for=192.0.2.60;proto=https;host=example.com, for="[2001:db8::1]:4711"`
+pub fn parse_forwarded(value: &str) -> Vec<ForwardedElement> {
+    value
+        .split(',')
+        .map(|element| {
+            let mut forwarded = ForwardedElement::default();
+            for pair in element.split(';') {
+                let Some((key, value)) = pair.split_once('=') else { continue };
+                let value = value.trim().trim_matches('"').to_string();
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => forwarded.client = Some(value),
+                    "proto" => forwarded.proto = Some(value),
+                    "host" => forwarded.host = Some(value),
+                    _ => ()
+                }
+            }
+            forwarded
+        })
+        .collect()
+}
+
+
+/// Parse the address of a node, as used by `Forwarded` and `X-Forwarded-For`,
+/// ignoring its port (`192.0.2.60:4711`, `[2001:db8::1]:4711`).
+/// Unknown and obfuscated nodes (`unknown`, `_hidden`) return `None`.
+pub fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Some(rest) = node.strip_prefix('[') {
+        let (address, _) = rest.split_once(']')?;
+        return address.parse().ok();
+    }
+    if let Ok(address) = node.parse() {
+        return Some(address);
+    }
+
+    // An IPv4 address with a port
+    let (address, _) = node.split_once(':')?;
+    address.parse().ok()
+}
@@ -22,7 +22,7 @@ impl Header {
 
 impl From<String> for Header {
     fn from(value: String) -> Self {
-        match value.is_empty() {
+        match value.trim().is_empty() {
             // If the string is empty, return an empty header
             true => Self {
                 key: String::new(),
@@ -32,25 +32,257 @@ impl From<String> for Header {
             // If the string is not empty, parse it
             false => {
                 // Split the header into a key and a value
-                let index: usize = match value.find(": ") {
-                    Some(i) => i,
+                let (key, value) = match value.trim().split_once(':') {
+                    Some((key, value)) => (key.trim().to_string(), value.trim().to_string()),
                     None => panic!("Unable to parse header: \"{value}\"")
                 };
-                let (key, value) = value.trim().split_at(index);
 
-                Self {
-                    key: key.to_string(),
-                    value: value[2..].to_string()
-                }
+                Self { key, value }
             }
         }
     }
 }
 
 
+/// # Header map
+/// The headers of a request or a response.
+/// - Header names are compared case-insensitively, as HTTP requires
+/// - A header may appear multiple times (like `Set-Cookie`)
+/// - Iteration follows the order in which the headers were added
+#[derive(Clone, Debug, Default)]
+pub struct HeaderMap {
+    headers: Vec<Header>
+}
+
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the value of the first header with the given name
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|header| header.key.eq_ignore_ascii_case(key))
+            .map(|header| header.value.as_str())
+    }
+
+    /// Get the values of every header with the given name, in order
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|header| header.key.eq_ignore_ascii_case(key))
+            .map(|header| header.value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.headers.iter().any(|header| header.key.eq_ignore_ascii_case(key))
+    }
+
+    /// Set a header, replacing every other header with the same name
+    pub fn insert(&mut self, key: impl ToString, value: impl ToString) {
+        let key = key.to_string();
+        match self.headers.iter().position(|header| header.key.eq_ignore_ascii_case(&key)) {
+            // Keep the position of the first header, and drop the rest
+            Some(index) => {
+                self.headers[index] = Header::new(key.clone(), value.to_string());
+                let mut position = 0;
+                self.headers.retain(|header| {
+                    position += 1;
+                    position - 1 <= index || !header.key.eq_ignore_ascii_case(&key)
+                });
+            },
+            None => self.headers.push(Header::new(key, value.to_string()))
+        }
+    }
+
+    /// Add a header, keeping the other headers with the same name
+    pub fn append(&mut self, key: impl ToString, value: impl ToString) {
+        self.headers.push(Header::new(key.to_string(), value.to_string()))
+    }
+
+    /// Remove every header with the given name
+    pub fn remove(&mut self, key: &str) {
+        self.headers.retain(|header| !header.key.eq_ignore_ascii_case(key))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Header> {
+        self.headers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /* TYPED ACCESSORS */
+    pub fn content_length(&self) -> Option<usize> {
+        self.get("Content-Length")?.trim().parse::<usize>().ok()
+    }
+
+    pub fn set_content_length(&mut self, length: usize) {
+        self.insert("Content-Length", length)
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.get("Content-Type")
+    }
+
+    pub fn set_content_type(&mut self, content_type: &str) {
+        self.insert("Content-Type", content_type)
+    }
+
+    pub fn content_encoding(&self) -> Option<&str> {
+        self.get("Content-Encoding")
+    }
+
+    pub fn set_content_encoding(&mut self, encoding: &str) {
+        self.insert("Content-Encoding", encoding)
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.get("Host")
+    }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.get("User-Agent")
+    }
+
+    pub fn authorization(&self) -> Option<&str> {
+        self.get("Authorization")
+    }
+
+    pub fn accept_encoding(&self) -> Option<&str> {
+        self.get("Accept-Encoding")
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.get("Location")
+    }
+
+    pub fn set_location(&mut self, location: &str) {
+        self.insert("Location", location)
+    }
+
+    /// Add a header name to the `Vary` header, unless it is already listed
+    pub fn add_vary(&mut self, field: &str) {
+        let mut fields: Vec<String> = self.get_all("Vary")
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect();
+
+        if !fields.iter().any(|existing| existing.eq_ignore_ascii_case(field) || existing == "*") {
+            fields.push(field.to_string());
+        }
+        self.insert("Vary", fields.join(", "))
+    }
+}
+
+
+impl From<Vec<Header>> for HeaderMap {
+    fn from(headers: Vec<Header>) -> Self {
+        Self { headers }
+    }
+}
+
+
+impl FromIterator<Header> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = Header>>(iter: I) -> Self {
+        Self { headers: iter.into_iter().collect() }
+    }
+}
+
+
+impl Extend<Header> for HeaderMap {
+    fn extend<I: IntoIterator<Item = Header>>(&mut self, iter: I) {
+        self.headers.extend(iter)
+    }
+}
+
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = &'a Header;
+    type IntoIter = std::slice::Iter<'a, Header>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.headers.iter()
+    }
+}
+
+
 #[macro_export]
 macro_rules! header {
     ($key: tt : $value: tt) => {
         Header::new($key.to_string(), $value.to_string())
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let mut headers = HeaderMap::new();
+        headers.append("Content-Type", "text/html");
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(headers.content_type(), Some("text/html"));
+        assert!(headers.contains("CONTENT-TYPE"));
+
+        headers.remove("content-TYPE");
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn keeps_multiple_values_in_order() {
+        let mut headers = HeaderMap::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Server", "aerielle");
+        headers.append("set-cookie", "b=2");
+        assert_eq!(headers.get("Set-Cookie"), Some("a=1"));
+        assert_eq!(headers.get_all("Set-Cookie"), vec!["a=1", "b=2"]);
+        assert_eq!(headers.len(), 3);
+    }
+
+    #[test]
+    fn insert_replaces_every_value_in_place() {
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Cookie");
+        headers.append("Server", "aerielle");
+        headers.append("vary", "Origin");
+        headers.insert("VARY", "*");
+
+        let names: Vec<&str> = headers.iter().map(|header| header.key().as_str()).collect();
+        assert_eq!(names, vec!["VARY", "Server"]);
+        assert_eq!(headers.get_all("Vary"), vec!["*"]);
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let headers: HeaderMap = vec![
+            Header::from(String::from("content-length:  42 \r\n")),
+            Header::from(String::from("Host: example.com:8080")),
+            Header::from(String::from("Authorization: Bearer a:b"))
+        ].into();
+        assert_eq!(headers.content_length(), Some(42));
+        assert_eq!(headers.host(), Some("example.com:8080"));
+        assert_eq!(headers.authorization(), Some("Bearer a:b"));
+        assert_eq!(headers.accept_encoding(), None);
+    }
+
+    #[test]
+    fn vary_lists_each_field_once() {
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Cookie");
+        headers.add_vary("Accept-Encoding");
+        headers.add_vary("cookie");
+        assert_eq!(headers.get_all("Vary"), vec!["Cookie, Accept-Encoding"]);
+    }
+}
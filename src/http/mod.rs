@@ -3,9 +3,18 @@ mod response;
 mod method;
 mod status;
 mod header;
+mod query;
+mod multipart;
+mod cookie;
+mod date;
+mod forwarded;
 
-pub use request::Request;
+pub use request::{Request, DEFAULT_MAX_BODY_SIZE};
 pub use response::Response;
 pub use method::Method;
 pub use status::Status;
-pub use header::Header;
\ No newline at end of file
+pub use header::{Header, HeaderMap};
+pub use cookie::{Cookie, SameSite};
+pub use date::http_date;
+pub use query::QueryMap;
+pub use multipart::{Multipart, MultipartConfig, MultipartError, MultipartForm, Part, UploadedFile};
\ No newline at end of file
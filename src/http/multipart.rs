@@ -0,0 +1,474 @@
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path
+};
+use tempfile::NamedTempFile;
+use crate::http::{QueryMap, Response, Status};
+
+
+/// The limits of a multipart parser
+#[derive(Clone, Debug)]
+pub struct MultipartConfig {
+    /// Files larger than this many bytes are written to a temporary file instead of being kept in memory
+    pub spool_threshold: usize,
+    /// The maximum size of a single uploaded file
+    pub max_file_size: usize,
+    /// The maximum size of a single non-file field
+    pub max_field_size: usize,
+    /// The maximum size of the whole body
+    pub max_total_size: usize
+}
+
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        Self {
+            spool_threshold: 2_621_440,  // 2.5 MB, like Django
+            max_file_size: 104_857_600,  // 100 MB
+            max_field_size: 2_621_440,
+            max_total_size: 104_857_600
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum MultipartError {
+    Io(io::Error),
+    Malformed(String),
+    TooLarge(String)
+}
+
+
+impl Display for MultipartError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error while reading multipart body: {e}"),
+            Self::Malformed(message) => write!(f, "Malformed multipart body: {message}"),
+            Self::TooLarge(message) => write!(f, "Multipart body too large: {message}")
+        }
+    }
+}
+
+
+impl From<io::Error> for MultipartError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+
+impl From<MultipartError> for Response {
+    fn from(value: MultipartError) -> Self {
+        let status = match value {
+            MultipartError::Io(_) => Status::InternalServerError,
+            MultipartError::Malformed(_) => Status::BadRequest,
+            MultipartError::TooLarge(_) => Status::PayloadTooLarge
+        };
+        Response::new(status, value.to_string())
+    }
+}
+
+
+/// Where the contents of an uploaded file are kept
+#[derive(Debug)]
+enum FileData {
+    Memory(Vec<u8>),
+    Spooled(NamedTempFile)
+}
+
+
+/// # Uploaded file
+/// A file sent in a `multipart/form-data` body.
+/// Small files are kept in memory, larger ones are spooled to a temporary file,
+/// which is deleted once the `UploadedFile` is dropped.
+#[derive(Debug)]
+pub struct UploadedFile {
+    /// The name of the form field
+    pub name: String,
+    /// The name of the file on the client's machine.
+    /// It is chosen by the client, so it is untrusted: it may contain `..`, path separators or NUL bytes.
+    /// Use `safe_filename` to build a path from it.
+    pub filename: String,
+    pub content_type: String,
+    /// The size of the file in bytes
+    pub size: usize,
+    data: FileData
+}
+
+
+impl UploadedFile {
+    /// # Safe file name
+    /// The file name sent by the client, reduced to something safe to join to a directory:
+    /// only the final path component is kept (with both `/` and `\` as separators),
+    /// NUL bytes are dropped, and `.` or `..` become an empty string.
+    /// ## Example
+    /// ```rust
+    /// // "../../etc/passwd" becomes "passwd"
+    /// file.save(format!("uploads/{}", file.safe_filename()))?;
+    /// ```
+    pub fn safe_filename(&self) -> String {
+        let name = self.filename
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .replace('\0', "");
+
+        match name.trim() {
+            "." | ".." => String::new(),
+            _ => name
+        }
+    }
+
+    /// Test whether the file was written to a temporary file
+    pub fn is_spooled(&self) -> bool {
+        matches!(self.data, FileData::Spooled(_))
+    }
+
+    /// The path of the temporary file, if the file was spooled to disk
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            FileData::Memory(_) => None,
+            FileData::Spooled(file) => Some(file.path())
+        }
+    }
+
+    /// Read the whole file into memory
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.data {
+            FileData::Memory(bytes) => Ok(bytes.clone()),
+            FileData::Spooled(file) => fs::read(file.path())
+        }
+    }
+
+    /// Move the file to its final location
+    pub fn save<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        match self.data {
+            FileData::Memory(bytes) => fs::write(path, bytes),
+            FileData::Spooled(file) => match file.persist(&path) {
+                Ok(_) => Ok(()),
+                // Persisting fails across file systems, so fall back to copying
+                Err(e) => fs::copy(e.file.path(), path).map(|_| ())
+            }
+        }
+    }
+}
+
+
+/// A single part of a multipart body
+#[derive(Debug)]
+pub enum Part {
+    Field { name: String, value: String },
+    File(UploadedFile)
+}
+
+
+/// The fields and files of a fully parsed multipart body
+#[derive(Debug, Default)]
+pub struct MultipartForm {
+    pub fields: QueryMap,
+    pub files: Vec<UploadedFile>
+}
+
+
+impl MultipartForm {
+    /// Get the first file uploaded under the given field name
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+}
+
+
+/// # Multipart parser
+/// A streaming parser for `multipart/form-data` bodies.
+/// The body is read in chunks, so parts are never held in memory as a whole,
+/// unless they are smaller than the spool threshold.
+/// ## Example
+/// ```rust
+/// let mut parser = Multipart::new(body, boundary, MultipartConfig::default());
+/// while let Some(part) = parser.next_part()? {
+///     match part {
+///         Part::Field { name, value } => println!("{name} = {value}"),
+///         // The file name comes from the client, so never use it as a path directly
+///         Part::File(file) => file.save(format!("uploads/{}", file.safe_filename()))?
+///     }
+/// }
+/// ```
+pub struct Multipart<R: Read> {
+    reader: R,
+    config: MultipartConfig,
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    total: usize,
+    eof: bool,
+    finished: bool
+}
+
+
+const CHUNK_SIZE: usize = 8192;
+
+
+impl<R: Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &str, config: MultipartConfig) -> Self {
+        Self {
+            reader,
+            config,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // Pretend the body starts with a line break, so the first boundary looks like the others
+            buffer: b"\r\n".to_vec(),
+            total: 0,
+            eof: false,
+            finished: false
+        }
+    }
+
+    /// Read the next part of the body, returning `None` once the closing boundary was reached
+    pub fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        // Skip to the next boundary (only the preamble is skipped on the first part)
+        let delimiter = self.delimiter.clone();
+        loop {
+            if let Some(index) = find(&self.buffer, &delimiter) {
+                self.buffer.drain(..index + delimiter.len());
+                break;
+            }
+            let keep = self.buffer.len().min(delimiter.len());
+            self.buffer.drain(..self.buffer.len() - keep);
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("missing boundary".to_string()));
+            }
+        }
+
+        // Test for the closing boundary
+        while self.buffer.len() < 2 {
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of body".to_string()));
+            }
+        }
+        if self.buffer.starts_with(b"--") {
+            self.finished = true;
+            return Ok(None);
+        }
+
+        // Read the headers of the part
+        let headers_end = loop {
+            if let Some(index) = find(&self.buffer, b"\r\n\r\n") {
+                break index;
+            }
+            if self.buffer.len() > 16384 {
+                return Err(MultipartError::TooLarge("part headers".to_string()));
+            }
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of part headers".to_string()));
+            }
+        };
+        let headers = String::from_utf8_lossy(&self.buffer[..headers_end]).to_string();
+        self.buffer.drain(..headers_end + 4);
+
+        let mut name: Option<String> = None;
+        let mut filename: Option<String> = None;
+        let mut content_type = String::from("text/plain");
+        for line in headers.split("\r\n") {
+            let Some((key, value)) = line.split_once(':') else { continue };
+            if key.trim().eq_ignore_ascii_case("Content-Disposition") {
+                for (param, param_value) in parse_params(value) {
+                    match param.as_str() {
+                        "name" => name = Some(param_value),
+                        "filename" => filename = Some(param_value),
+                        _ => ()
+                    }
+                }
+            } else if key.trim().eq_ignore_ascii_case("Content-Type") {
+                content_type = value.trim().to_string();
+            }
+        }
+        let name = match name {
+            Some(name) => name,
+            None => return Err(MultipartError::Malformed("part without a name".to_string()))
+        };
+
+        // Read the body of the part
+        match filename {
+            Some(filename) => {
+                let (data, size) = self.read_file()?;
+                Ok(Some(Part::File(UploadedFile { name, filename, content_type, size, data })))
+            },
+            None => {
+                let mut value: Vec<u8> = Vec::new();
+                let limit = self.config.max_field_size;
+                self.read_body(|chunk| {
+                    if value.len() + chunk.len() > limit {
+                        return Err(MultipartError::TooLarge(format!("field \"{name}\"")));
+                    }
+                    value.extend_from_slice(chunk);
+                    Ok(())
+                })?;
+                let value = String::from_utf8_lossy(&value).to_string();
+                Ok(Some(Part::Field { name, value }))
+            }
+        }
+    }
+
+    /// Parse every remaining part of the body
+    pub fn collect(mut self) -> Result<MultipartForm, MultipartError> {
+        let mut form = MultipartForm::default();
+        while let Some(part) = self.next_part()? {
+            match part {
+                Part::Field { name, value } => form.fields.append(name, value),
+                Part::File(file) => form.files.push(file)
+            }
+        }
+        Ok(form)
+    }
+
+    /// Read the body of a file part, spooling it to disk once it grows past the threshold
+    fn read_file(&mut self) -> Result<(FileData, usize), MultipartError> {
+        let mut data = FileData::Memory(Vec::new());
+        let mut size: usize = 0;
+        let (threshold, limit) = (self.config.spool_threshold, self.config.max_file_size);
+
+        self.read_body(|chunk| {
+            size += chunk.len();
+            if size > limit {
+                return Err(MultipartError::TooLarge("uploaded file".to_string()));
+            }
+
+            // Move the file to disk once it gets too large
+            if let FileData::Memory(bytes) = &data && size > threshold {
+                let mut file = NamedTempFile::new()?;
+                file.write_all(bytes)?;
+                data = FileData::Spooled(file);
+            }
+
+            match &mut data {
+                FileData::Memory(bytes) => bytes.extend_from_slice(chunk),
+                FileData::Spooled(file) => file.write_all(chunk)?
+            }
+            Ok(())
+        })?;
+
+        if let FileData::Spooled(file) = &mut data {
+            file.flush()?;
+            file.as_file_mut().seek(SeekFrom::Start(0))?;
+        }
+
+        Ok((data, size))
+    }
+
+    /// Pass the body of the current part to `sink` chunk by chunk, until the next delimiter
+    fn read_body<F>(&mut self, mut sink: F) -> Result<(), MultipartError>
+    where
+        F: FnMut(&[u8]) -> Result<(), MultipartError>
+    {
+        let delimiter_length = self.delimiter.len();
+        loop {
+            if let Some(index) = find(&self.buffer, &self.delimiter) {
+                sink(&self.buffer[..index])?;
+                // Leave the delimiter in the buffer for the next part
+                self.buffer.drain(..index);
+                return Ok(());
+            }
+
+            // Everything except a possible partial delimiter at the end is part of the body
+            if self.buffer.len() >= delimiter_length {
+                let safe = self.buffer.len() - (delimiter_length - 1);
+                sink(&self.buffer[..safe])?;
+                self.buffer.drain(..safe);
+            }
+
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of body".to_string()));
+            }
+        }
+    }
+
+    /// Read the next chunk of the body into the buffer, returning `false` at the end of the body
+    fn fill(&mut self) -> Result<bool, MultipartError> {
+        if self.eof {
+            return Ok(false);
+        }
+
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let read = self.reader.read(&mut chunk)?;
+        if read == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+
+        self.total += read;
+        if self.total > self.config.max_total_size {
+            return Err(MultipartError::TooLarge("request body".to_string()));
+        }
+
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(true)
+    }
+}
+
+
+/// Find the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+
+/// Parse the parameters of a header value, like `form-data; name="file"; filename="a.png"`.
+/// The first item (`form-data`) is skipped, and the keys are lowercased.
+pub fn parse_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = value;
+
+    // Skip the value itself
+    match rest.find(';') {
+        Some(index) => rest = &rest[index + 1..],
+        None => return params
+    }
+
+    loop {
+        rest = rest.trim_start();
+        let Some(equals) = rest.find('=') else { break };
+        let key = rest[..equals].trim().to_ascii_lowercase();
+        rest = &rest[equals + 1..];
+
+        // Quoted values may contain semicolons and escaped quotes
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '\\' => if let Some((_, escaped)) = chars.next() { value.push(escaped) },
+                    '"' => { end = index + 1; break },
+                    _ => value.push(c)
+                }
+            }
+            rest = &quoted[end..];
+            rest = match rest.find(';') {
+                Some(index) => &rest[index + 1..],
+                None => ""
+            };
+            value
+        } else {
+            let (value, remaining) = match rest.find(';') {
+                Some(index) => (&rest[..index], &rest[index + 1..]),
+                None => (rest, "")
+            };
+            rest = remaining;
+            value.trim().to_string()
+        };
+
+        params.push((key, value));
+    }
+
+    params
+}
@@ -0,0 +1,156 @@
+use serde::de::DeserializeOwned;
+
+
+/// # Query map
+/// The decoded key-value pairs of a query string (or an `application/x-www-form-urlencoded` body).
+/// A key may appear multiple times, like in `?tag=rust&tag=web`,
+/// and the pairs keep the order they were sent in.
+#[derive(Clone, Debug, Default)]
+pub struct QueryMap {
+    pairs: Vec<(String, String)>
+}
+
+
+impl QueryMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse and percent-decode a query string, like `q=rust+web&page=2`
+    pub fn parse(query: &str) -> Self {
+        let pairs = match serde_urlencoded::from_str::<Vec<(String, String)>>(query) {
+            Ok(pairs) => pairs,
+            Err(e) => {
+                eprintln!("Unable to parse query string \"{query}\": {e}");
+                Vec::new()
+            }
+        };
+        Self { pairs }
+    }
+
+    /// Get the first value of the given key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Get every value of the given key, in the order they were sent
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(k, _)| k == key)
+    }
+
+    /// Add a value to the given key, keeping the existing values
+    pub fn append(&mut self, key: impl ToString, value: impl ToString) {
+        self.pairs.push((key.to_string(), value.to_string()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Deserialize the pairs into a struct.
+    /// If a key appears multiple times, the last value is used.
+    /// ## Example
+    /// ```rust
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Search {
+    ///     q: String,
+    ///     page: Option<u32>
+    /// }
+    ///
+    /// let query = QueryMap::parse("q=rust&page=2");
+    /// let search: Search = query.deserialize().unwrap();
+    /// ```
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_urlencoded::de::Error> {
+        // Keep only the last value of every key, since serde rejects duplicate fields
+        let mut last: Vec<(&str, &str)> = Vec::new();
+        for (key, value) in self.iter() {
+            match last.iter_mut().find(|(k, _)| *k == key) {
+                Some(pair) => pair.1 = value,
+                None => last.push((key, value))
+            }
+        }
+
+        let encoded = serde_urlencoded::to_string(&last)
+            .map_err(<serde_urlencoded::de::Error as serde::de::Error>::custom)?;
+        serde_urlencoded::from_str(&encoded)
+    }
+}
+
+
+impl<'a> IntoIterator for &'a QueryMap {
+    type Item = &'a (String, String);
+    type IntoIter = std::slice::Iter<'a, (String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pairs.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use crate::http::Request;
+    use super::*;
+
+    #[test]
+    fn decodes_pairs() {
+        let query = QueryMap::parse("q=rust+web&name=J%C3%BCrgen&empty=&flag");
+        assert_eq!(query.get("q"), Some("rust web"));
+        assert_eq!(query.get("name"), Some("Jürgen"));
+        assert_eq!(query.get("empty"), Some(""));
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get("missing"), None);
+    }
+
+    #[test]
+    fn keeps_repeated_keys_in_order() {
+        let query = QueryMap::parse("tag=rust&page=2&tag=web");
+        assert_eq!(query.get("tag"), Some("rust"));
+        assert_eq!(query.get_all("tag"), vec!["rust", "web"]);
+        assert_eq!(query.len(), 3);
+    }
+
+    #[test]
+    fn deserializes_into_a_struct() {
+        #[derive(Deserialize)]
+        struct Search {
+            q: String,
+            page: Option<u32>
+        }
+
+        let search: Search = QueryMap::parse("q=a%26b&page=2&page=3").deserialize().unwrap();
+        assert_eq!(search.q, "a&b");
+        assert_eq!(search.page, Some(3));
+        assert!(QueryMap::parse("page=2").deserialize::<Search>().is_err());
+    }
+
+    #[test]
+    fn splits_the_request_target() {
+        let request = Request::from_bytes(b"GET /caf%C3%A9/menu?q=soup%20du%20jour&q=tea#top HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(request.path, "/café/menu");
+        assert_eq!(request.query_string, "q=soup%20du%20jour&q=tea");
+        assert_eq!(request.query.get_all("q"), vec!["soup du jour", "tea"]);
+    }
+}
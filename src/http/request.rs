@@ -1,63 +1,561 @@
 use std::{
     io::{
+        self,
         Result,
         BufRead, BufReader,
-        Read
+        Chain, Cursor, Read, Take
     },
     net::TcpStream,
 
 };
+use std::borrow::Cow;
+#[cfg(feature = "_db_must")]
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+#[cfg(feature = "_db_must")]
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
 use std::io::Write;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use crate::session::SessionData;
+use crate::server::TrustedProxies;
+use crate::middleware::TemplateSecret;
+#[cfg(feature = "_db_must")]
+use crate::auth::User;
 use crate::http::{
     Response,
     Method,
     Status,
-    Header
+    Cookie,
+    Header,
+    HeaderMap,
+    QueryMap,
+    MultipartConfig,
+    MultipartForm,
+    Multipart,
+    multipart::parse_params,
+    forwarded::{parse_forwarded, parse_node}
 };
 use regex as re;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use percent_encoding::percent_decode_str;
 
 
 pub struct Request {
     pub method: Method,
     pub url: String,
+    pub path: String,
+    pub query_string: String,
+    pub query: QueryMap,
     pub version: String,
-    pub headers: Vec<Header>,
-    pub body: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
 
     pub ip: Option<SocketAddr>,
+    pub(crate) client_ip: Option<IpAddr>,
+    pub(crate) scheme: String,
+    pub(crate) forwarded_host: Option<String>,
 
+    pub(crate) session: Option<SessionData>,
+    pub(crate) csp_nonce: Option<String>,
+    pub(crate) csrf_secret: RefCell<Option<TemplateSecret>>,
+    #[cfg(feature = "_db_must")]
+    pub(crate) user: RefCell<Option<User>>,
+    #[cfg(feature = "_db_must")]
+    pub(crate) permissions: RefCell<HashSet<String>>,
+    #[cfg(feature = "_db_must")]
+    pub(crate) principal: RefCell<Option<Box<dyn Any>>>,
+
+    body_error: Option<Status>,
+    unread_body: RefCell<Option<BodyReader>>,
+    parsed_multipart: RefCell<Option<std::result::Result<MultipartForm, Response>>>,
     stream: TcpStream
 }
 
 
+/// The part of a body already buffered while reading the headers, followed by the rest of it in the stream
+type BodyReader = Chain<Cursor<Vec<u8>>, Take<TcpStream>>;
+
+
+/// The largest body read by default, in bytes (100 MB, the multipart default)
+pub const DEFAULT_MAX_BODY_SIZE: usize = 104_857_600;
+
+
 impl Request {
-    pub fn respond(&mut self, response: Response) -> Result<()> {
+    pub fn respond(&mut self, mut response: Response) -> Result<()> {
+        // Discard the part of a streamed body the handler did not read,
+        // so the client is not reset before it reads the response
+        if let Some(mut reader) = self.unread_body.get_mut().take() {
+            io::copy(&mut reader, &mut io::sink())?;
+        }
+
+        // The body is final now, so set its length
+        response.headers.set_content_length(response.body.len());
+
         // Format the headers into strings
         let mut headers: Vec<String> = Vec::new();
         for header in response.headers.iter() {
             headers.push(format!("{key}: {value}", key=header.key(),value=header.value()));
         }
 
-        // Write the response text
-        let response_text: String = format!(
-            "HTTP/{version} {status_int} {status}\r\n{headers}\r\n\r\n{body}",
+        // Write the status line and the headers
+        let head: String = format!(
+            "HTTP/{version} {status_int} {status}\r\n{headers}\r\n\r\n",
             version=self.version,
             status_int=response.status as u16,
             status=response.status,
-            headers=headers.join("\r\n"),
-            body=response.body
+            headers=headers.join("\r\n")
         );
 
         // Write the response
-        self.stream.write(response_text.as_bytes())?;
+        self.stream.write_all(head.as_bytes())?;
+        self.stream.write_all(&response.body)?;
         Ok(())
     }
+
+    /// Get the value of the first header with the given name (case-insensitive)
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key)
+    }
+
+    /// # Get the session of the request
+    /// Returns the session loaded by the `SessionMiddleware`.
+    /// ## Panicking
+    /// This method panics if no `SessionMiddleware` was added to the server.
+    pub fn session(&self) -> &SessionData {
+        match &self.session {
+            Some(session) => session,
+            None => panic!("Tried to access the session, but no SessionMiddleware was added to the server.")
+        }
+    }
+
+    /// # Get the IP address of the client
+    /// Returns the address of the client, which made the request.
+    /// Unlike `request.ip`, which is the address of the connection,
+    /// this is the real client behind the trusted proxies of the server (`WebServer::set_trusted_proxies`),
+    /// as told by their `Forwarded` or `X-Forwarded-For` headers.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.client_ip
+    }
+
+    /// The scheme of the request, `http` or `https`, as told by the trusted proxies of the server
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// Test whether the request was made over HTTPS
+    pub fn is_secure(&self) -> bool {
+        self.scheme == "https"
+    }
+
+    /// The host of the request, as told by the trusted proxies of the server or by the `Host` header
+    pub fn host(&self) -> Option<&str> {
+        self.forwarded_host.as_deref().or(self.headers.host())
+    }
+
+    /// # Resolve the trusted proxies
+    /// Finds the real client, scheme and host of a request, which was forwarded by trusted proxies.
+    /// The forwarded addresses are followed from the nearest proxy toward the client,
+    /// stopping at the first address, which is not trusted, so clients can not spoof their address.
+    pub(crate) fn resolve_proxies(&mut self, proxies: &TrustedProxies) {
+        self.client_ip = self.ip.map(|address| address.ip());
+        match self.client_ip {
+            Some(peer) if proxies.contains(peer) => (),
+            _ => return
+        }
+
+        // The forwarded hops, from the client to the nearest proxy
+        let split = |name: &str| -> Vec<String> {
+            self.headers
+                .get_all(name)
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect()
+        };
+        let hops: Vec<Option<IpAddr>>;
+        let protos: Vec<Option<String>>;
+        let hosts: Vec<Option<String>>;
+        if self.headers.contains("Forwarded") {
+            let elements: Vec<_> = self.headers
+                .get_all("Forwarded")
+                .iter()
+                .flat_map(|value| parse_forwarded(value))
+                .collect();
+            hops = elements.iter().map(|element| element.client.as_deref().and_then(parse_node)).collect();
+            protos = elements.iter().map(|element| element.proto.clone()).collect();
+            hosts = elements.iter().map(|element| element.host.clone()).collect();
+        } else {
+            hops = split("X-Forwarded-For").iter().map(|node| parse_node(node)).collect();
+            protos = split("X-Forwarded-Proto").into_iter().map(Some).collect();
+            hosts = split("X-Forwarded-Host").into_iter().map(Some).collect();
+        }
+
+        // Step back from the nearest proxy, while the addresses are trusted
+        let mut index = hops.len();
+        while index > 0 {
+            index -= 1;
+            match hops[index] {
+                Some(address) => {
+                    self.client_ip = Some(address);
+                    if !proxies.contains(address) {
+                        break;
+                    }
+                },
+                // Unknown or obfuscated clients can not be followed any further
+                None => break
+            }
+        }
+
+        // Use the values added along with the client, or the ones of the nearest proxy
+        let pick = |values: &Vec<Option<String>>| -> Option<String> {
+            match values.len() == hops.len() && !hops.is_empty() {
+                true => values[index].clone(),
+                false => values.last().cloned().flatten()
+            }
+        };
+        if let Some(proto) = pick(&protos).map(|proto| proto.to_lowercase())
+            && (proto == "http" || proto == "https")
+        {
+            self.scheme = proto;
+        }
+        self.forwarded_host = pick(&hosts);
+    }
+
+    /// The `Content-Security-Policy` nonce of the request, generated by the `SecurityMiddleware`
+    pub fn csp_nonce(&self) -> Option<&str> {
+        self.csp_nonce.as_deref()
+    }
+
+    /// # Get the logged in user
+    /// Returns the user loaded by the `AuthMiddleware`, or `None` for anonymous users.
+    #[cfg(feature = "_db_must")]
+    pub fn user(&self) -> Option<User> {
+        self.user.borrow().clone()
+    }
+
+    /// Test whether a user is logged in
+    #[cfg(feature = "_db_must")]
+    pub fn is_authenticated(&self) -> bool {
+        self.user.borrow().is_some()
+    }
+
+    /// # Check a permission of the logged in user
+    /// Tests whether the logged in user has a permission, like `request.has_perm("blog.edit_post")`.
+    /// The permissions are loaded once per request by the `AuthMiddleware`.
+    /// Anonymous and inactive users have no permissions, and superusers have every permission.
+    #[cfg(feature = "_db_must")]
+    pub fn has_perm(&self, permission: &str) -> bool {
+        match &*self.user.borrow() {
+            Some(user) if user.is_superuser => user.is_active,
+            Some(user) => user.is_active && self.permissions.borrow().contains(permission),
+            None => false
+        }
+    }
+
+    /// # Get the authenticated principal
+    /// Returns the value found by the credential lookup of an authentication guard
+    /// (`basic_auth_required`, `bearer_required`, `jwt_required` or `api_key_required`),
+    /// if it is of the requested type.
+    /// ## Example
+    /// ```rust
+    /// fn report(server: &WebServer, request: &Request) -> Response {
+    ///     let key: ApiKey = request.principal().unwrap();
+    ///     /* ... */
+    /// }
+    /// ```
+    #[cfg(feature = "_db_must")]
+    pub fn principal<T: Clone + 'static>(&self) -> Option<T> {
+        self.principal
+            .borrow()
+            .as_ref()
+            .and_then(|principal| principal.downcast_ref::<T>())
+            .cloned()
+    }
+
+    /// # Get the cookies of the request
+    /// Parses every `Cookie` header of the request into a map of names and values.
+    /// ## Example
+    /// ```rust
+    /// fn index(server: &WebServer, request: &Request) -> Response {
+    ///     let cookies = request.cookies();
+    ///     let theme = cookies.get("theme").map(String::as_str).unwrap_or("light");
+    ///     /* ... */
+    /// }
+    /// ```
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        for header in self.headers.get_all("Cookie") {
+            for (name, value) in Cookie::parse_header(header) {
+                cookies.entry(name).or_insert(value);
+            }
+        }
+        cookies
+    }
+
+    /// Get the value of a single cookie
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    /// # Deserialize the query string
+    /// Deserializes the query string of the request into a struct.
+    /// ## Returns
+    /// This method returns a `Result<T, Response>`:
+    /// - `Ok(T)` if the query string could be deserialized
+    /// - `Err(Response)` with a `400 Bad Request` response, which can be returned from the handler
+    /// ## Example
+    /// ```rust
+    /// use serde::Deserialize;
+    /// use aerielle::{WebServer, http::{Request, Response}};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Search {
+    ///     q: String,
+    ///     page: Option<u32>
+    /// }
+    ///
+    /// fn search(server: &WebServer, request: &Request) -> Response {
+    ///     let search: Search = match request.query_as() {
+    ///         Ok(search) => search,
+    ///         Err(response) => return response
+    ///     };
+    ///     /* ... */
+    /// }
+    /// ```
+    pub fn query_as<T: DeserializeOwned>(&self) -> std::result::Result<T, Response> {
+        serde_urlencoded::from_str(&self.query_string)
+            .map_err(|e| Response::new(Status::BadRequest, format!("Invalid query string: {e}")))
+    }
+
+    /// Get the body of the request as text, replacing invalid UTF-8 sequences
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Test whether the `Content-Type` of the request is the given MIME type, ignoring its parameters
+    pub fn has_content_type(&self, mime: &str) -> bool {
+        match self.header("Content-Type") {
+            Some(content_type) => content_type
+                .split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case(mime),
+            None => false
+        }
+    }
+
+    /// # Parse a JSON body
+    /// Deserializes an `application/json` body into a value.
+    /// ## Returns
+    /// This method returns a `Result<T, Response>`:
+    /// - `Ok(T)` if the body could be deserialized
+    /// - `Err(Response)` with a `415 Unsupported Media Type` response, if the body is not JSON
+    /// - `Err(Response)` with a `400 Bad Request` response, if the body can not be parsed into `T`
+    ///
+    /// The error responses carry a JSON body describing the problem:
+    /// ```json
+    /// {"error": "syntax", "message": "expected value", "line": 1, "column": 2}
+    /// ```
+    /// ## Example
+    /// ```rust
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct NewPost {
+    ///     title: String
+    /// }
+    ///
+    /// fn create_post(server: &WebServer, request: &Request) -> Response {
+    ///     let post: NewPost = match request.json() {
+    ///         Ok(post) => post,
+    ///         Err(response) => return response
+    ///     };
+    ///     /* ... */
+    /// }
+    /// ```
+    pub fn json<T: DeserializeOwned>(&self) -> std::result::Result<T, Response> {
+        // Accept application/json and its suffixed variants, like application/problem+json
+        let is_json = match self.header("Content-Type") {
+            Some(content_type) => {
+                let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+                mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+            },
+            None => false
+        };
+        if !is_json {
+            return Err(Response::json_with_status(
+                Status::UnsupportedMediaType,
+                &json!({
+                    "error": "unsupported_media_type",
+                    "message": "Expected a body with the application/json content type"
+                })
+            ));
+        }
+
+        serde_json::from_slice(&self.body).map_err(|e| {
+            let category = match e.classify() {
+                serde_json::error::Category::Io => "io",
+                serde_json::error::Category::Syntax => "syntax",
+                serde_json::error::Category::Data => "data",
+                serde_json::error::Category::Eof => "eof"
+            };
+            Response::json_with_status(
+                Status::BadRequest,
+                &json!({
+                    "error": category,
+                    "message": e.to_string(),
+                    "line": e.line(),
+                    "column": e.column()
+                })
+            )
+        })
+    }
+
+    /// # Parse a URL-encoded form
+    /// Parses an `application/x-www-form-urlencoded` body into a `QueryMap`.
+    /// ## Returns
+    /// This method returns a `Result<QueryMap, Response>`:
+    /// - `Ok(QueryMap)` with the fields of the form
+    /// - `Err(Response)` with a `415 Unsupported Media Type` response, if the body is not a URL-encoded form
+    /// ## Example
+    /// ```rust
+    /// fn login(server: &WebServer, request: &Request) -> Response {
+    ///     let form = match request.form() {
+    ///         Ok(form) => form,
+    ///         Err(response) => return response
+    ///     };
+    ///     let username = form.get("username").unwrap_or_default();
+    ///     /* ... */
+    /// }
+    /// ```
+    pub fn form(&self) -> std::result::Result<QueryMap, Response> {
+        if !self.has_content_type("application/x-www-form-urlencoded") {
+            return Err(Response::new(
+                Status::UnsupportedMediaType,
+                "Expected an application/x-www-form-urlencoded body"
+            ));
+        }
+        Ok(QueryMap::parse(&self.text()))
+    }
+
+    /// # Parse a multipart form
+    /// Parses a `multipart/form-data` body into its fields and uploaded files,
+    /// using the default `MultipartConfig`.
+    /// The body is streamed from the connection while it is parsed, so only fields and files
+    /// smaller than the spool threshold are kept in memory, and larger files are written to temporary files.
+    /// Since the body is not kept, it can only be parsed once per request.
+    /// ## Returns
+    /// This method returns a `Result<MultipartForm, Response>`:
+    /// - `Ok(MultipartForm)` with the fields and the files of the form
+    /// - `Err(Response)` with a `415 Unsupported Media Type`, `400 Bad Request`
+    ///   or `413 Payload Too Large` response
+    /// ## Example
+    /// ```rust
+    /// fn upload(server: &WebServer, request: &Request) -> Response {
+    ///     let form = match request.multipart() {
+    ///         Ok(form) => form,
+    ///         Err(response) => return response
+    ///     };
+    ///     for file in form.files {
+    ///         // The file name comes from the client, so never use it as a path directly
+    ///         let path = format!("uploads/{}", file.safe_filename());
+    ///         file.save(path).unwrap();
+    ///     }
+    ///     /* ... */
+    /// }
+    /// ```
+    pub fn multipart(&self) -> std::result::Result<MultipartForm, Response> {
+        self.multipart_with(MultipartConfig::default())
+    }
+
+    /// Parse a `multipart/form-data` body with custom size limits
+    pub fn multipart_with(&self, config: MultipartConfig) -> std::result::Result<MultipartForm, Response> {
+        // The form may already have been parsed by a middleware
+        if let Some(parsed) = self.parsed_multipart.take() {
+            return parsed;
+        }
+
+        if !self.has_content_type("multipart/form-data") {
+            return Err(Response::new(
+                Status::UnsupportedMediaType,
+                "Expected a multipart/form-data body"
+            ));
+        }
+
+        let boundary = match self.multipart_boundary() {
+            Some(boundary) if !boundary.is_empty() => boundary,
+            _ => return Err(Response::new(Status::BadRequest, "Missing multipart boundary"))
+        };
+
+        let parsed = match self.unread_body.borrow_mut().as_mut() {
+            Some(reader) => Multipart::new(reader, &boundary, config).collect(),
+            None => Multipart::new(self.body.as_slice(), &boundary, config).collect()
+        };
+        parsed.map_err(Response::from)
+    }
+
+    /// Get a field of a multipart body, keeping the parsed form for the handler
+    pub(crate) fn multipart_field(&self, name: &str) -> Option<String> {
+        let parsed = self.multipart();
+        let value = match &parsed {
+            Ok(form) => form.fields.get(name).map(str::to_string),
+            Err(_) => None
+        };
+        *self.parsed_multipart.borrow_mut() = Some(parsed);
+        value
+    }
+
+    /// Get the boundary of a multipart body from the `Content-Type` header
+    fn multipart_boundary(&self) -> Option<String> {
+        self.header("Content-Type")
+            .map(parse_params)
+            .and_then(|params| params
+                .into_iter()
+                .find(|(key, _)| key == "boundary")
+                .map(|(_, value)| value)
+            )
+    }
+
+    /// Split a request target into its percent-decoded path and its raw query string
+    fn split_target(target: &str) -> (String, String) {
+        // The fragment is never part of the request
+        let target = match target.split_once('#') {
+            Some((target, _)) => target,
+            None => target
+        };
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (target, "")
+        };
+
+        (
+            percent_decode_str(path).decode_utf8_lossy().to_string(),
+            query.to_string()
+        )
+    }
 }
 
 impl From<TcpStream> for Request {
     fn from(value: TcpStream) -> Self {
+        Self::read(value, DEFAULT_MAX_BODY_SIZE)
+    }
+}
+
+
+impl Request {
+    /// # Read a request
+    /// Reads the request line, the headers and the body of a request from the stream.
+    /// Bodies whose `Content-Length` is larger than `max_body_size` are not read at all,
+    /// and `body_error` tells the server to reject the request with `413 Payload Too Large`,
+    /// or with `400 Bad Request` when the body is shorter than announced or cannot be read.
+    /// `multipart/form-data` bodies are left in the stream, to be parsed while they are read.
+    pub(crate) fn read(value: TcpStream, max_body_size: usize) -> Self {
         // Get the client's IP address
         let ip_address = match value.peer_addr() {
             Ok(address) => Some(address),
@@ -72,8 +570,7 @@ impl From<TcpStream> for Request {
         let mut url: String;
         let version: String;
 
-        let mut headers: Vec<Header> = Vec::new();
-        let mut content_length: usize = 0;
+        let mut headers: HeaderMap = HeaderMap::new();
 
         // Read the stream into a BufReader
         let mut reader: BufReader<&TcpStream> = BufReader::new(&value);
@@ -122,45 +619,94 @@ impl From<TcpStream> for Request {
                 Err(e) => panic!("Unable to read in header line: {e}")
             }
 
-            // Test if the line is empty
-            if line.as_str() == "\r\n" {
+            // Test if the line is empty (or the client stopped sending)
+            if line.trim().is_empty() {
                 break;
             }
 
             // Parse the header
             let header: Header = Header::from(line);
-            if header.key().as_str() == "Content-Length" {
-                content_length = header.value().parse::<usize>().unwrap_or(0);
-            }
-            
-            headers.push(header);
+            headers.append(header.key(), header.value());
         }
+        let content_length: usize = headers.content_length().unwrap_or(0);
 
-        // Read the body
-        let mut body: Vec<u8> = vec![0u8; content_length];
+        // Read the body, unless it is larger than allowed.
+        // The buffer grows with the bytes actually sent, not with the claimed length.
+        let mut body_error: Option<Status> = None;
+        let mut body: Vec<u8> = Vec::new();
+        let mut unread_body: Option<BodyReader> = None;
 
-        match reader.read_exact(&mut body) {
-            Ok(_) => (),
-            Err(e) => panic!("Unable to read body of request: {e}")
-        };
+        let is_multipart = headers.content_type()
+            .and_then(|content_type| content_type.split(';').next())
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("multipart/form-data"));
 
-        let body: String = match String::from_utf8(body) {
-            Ok(s) => s,
-            Err(e) => panic!("Unable to convert UTF-8 array into a string: {e}")
-        };
+        if content_length > max_body_size {
+            body_error = Some(Status::PayloadTooLarge);
+        } else if is_multipart {
+            let buffered: Vec<u8> = reader.buffer()[..content_length.min(reader.buffer().len())].to_vec();
+            let remaining = (content_length - buffered.len()) as u64;
+            match value.try_clone() {
+                Ok(stream) => unread_body = Some(Cursor::new(buffered).chain(stream.take(remaining))),
+                Err(e) => {
+                    eprintln!("Unable to read body of request: {e}");
+                    body_error = Some(Status::BadRequest);
+                }
+            }
+        } else {
+            match (&mut reader).take(content_length as u64).read_to_end(&mut body) {
+                Ok(read) if read == content_length => (),
+                Ok(read) => {
+                    eprintln!("Unable to read body of request: expected {content_length} bytes, got {read}");
+                    body_error = Some(Status::BadRequest);
+                },
+                Err(e) => {
+                    eprintln!("Unable to read body of request: {e}");
+                    body_error = Some(Status::BadRequest);
+                }
+            };
+        }
+
+        // Split the URL into the path and the query
+        let (path, query_string) = Self::split_target(&url);
+        let query = QueryMap::parse(&query_string);
 
         // Return
         Self {
             method,
             url,
+            path,
+            query_string,
+            query,
             version,
             headers,
             body,
             ip: ip_address,
+            client_ip: ip_address.map(|address| address.ip()),
+            scheme: String::from("http"),
+            forwarded_host: None,
+            session: None,
+            csp_nonce: None,
+            csrf_secret: RefCell::new(None),
+            #[cfg(feature = "_db_must")]
+            user: RefCell::new(None),
+            #[cfg(feature = "_db_must")]
+            permissions: RefCell::new(HashSet::new()),
+            #[cfg(feature = "_db_must")]
+            principal: RefCell::new(None),
+            body_error,
+            unread_body: RefCell::new(unread_body),
+            parsed_multipart: RefCell::new(None),
             stream: value
         }
 
     }
+
+    /// Get the status to reject the request with when its body could not be read:
+    /// `413 Payload Too Large` when it was larger than the maximum body size,
+    /// `400 Bad Request` when it was shorter than its `Content-Length` or the read failed
+    pub(crate) fn body_error(&self) -> Option<Status> {
+        self.body_error
+    }
 }
 
 
@@ -168,4 +714,142 @@ impl Display for Request {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} {}", self.method, self.url)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+impl Request {
+    /// Read a request from raw bytes, sent over a local connection which is then closed for writing
+    pub(crate) fn from_bytes(raw: &[u8]) -> Self {
+        Self::from_bytes_with(raw, DEFAULT_MAX_BODY_SIZE)
+    }
+
+    /// Read a request from raw bytes with a maximum body size
+    pub(crate) fn from_bytes_with(raw: &[u8], max_body_size: usize) -> Self {
+        use std::net::{Shutdown, TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        // Write from another thread, since a large body is only read while it is parsed
+        let raw = raw.to_vec();
+        std::thread::spawn(move || {
+            client.write_all(&raw).unwrap();
+            client.shutdown(Shutdown::Write).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        Self::read(stream, max_body_size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_body() {
+        let request = Request::from_bytes(b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello");
+        assert_eq!(request.body, b"hello");
+        assert_eq!(request.body_error(), None);
+    }
+
+    #[test]
+    fn short_body_is_a_bad_request() {
+        let request = Request::from_bytes(b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello");
+        assert_eq!(request.body_error(), Some(Status::BadRequest));
+    }
+
+    #[test]
+    fn large_body_is_not_read() {
+        let request = Request::from_bytes_with(b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\n0123456789", 4);
+        assert_eq!(request.body_error(), Some(Status::PayloadTooLarge));
+        assert!(request.body.is_empty());
+    }
+
+    /// Build a request with the given content type and body
+    fn with_body(content_type: &str, body: &str) -> Request {
+        Request::from_bytes(format!(
+            "POST /posts HTTP/1.1\r\nHost: localhost\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ).as_bytes())
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    struct NewPost {
+        title: String
+    }
+
+    #[test]
+    fn parses_json_bodies() {
+        let post: NewPost = with_body("application/json; charset=utf-8", r#"{"title": "Hello"}"#).json().ok().unwrap();
+        assert_eq!(post.title, "Hello");
+
+        let post: NewPost = with_body("application/merge-patch+json", r#"{"title": "Hi"}"#).json().ok().unwrap();
+        assert_eq!(post.title, "Hi");
+    }
+
+    #[test]
+    fn json_errors_are_mapped_to_responses() {
+        let response = with_body("text/plain", r#"{"title": "Hello"}"#).json::<NewPost>().unwrap_err();
+        assert_eq!(response.status, Status::UnsupportedMediaType);
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+
+        let response = with_body("application/json", r#"{"title": "#).json::<NewPost>().unwrap_err();
+        assert_eq!(response.status, Status::BadRequest);
+        let error: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(error["error"], "eof");
+
+        let response = with_body("application/json", r#"{"title": 1}"#).json::<NewPost>().unwrap_err();
+        assert_eq!(response.status, Status::BadRequest);
+        let error: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(error["error"], "data");
+        assert_eq!(error["line"], 1);
+    }
+
+    #[test]
+    fn json_responses() {
+        let response = Response::json(&json!({"id": 1}));
+        assert_eq!(response.status, Status::OK);
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+        assert_eq!(response.body, br#"{"id":1}"#);
+    }
+
+    /// Build a multipart request with a text field and a file
+    fn multipart_request(file: &[u8]) -> Vec<u8> {
+        let mut body: Vec<u8> = Vec::new();
+        body.extend_from_slice(b"--XYZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHoliday\r\n");
+        body.extend_from_slice(b"--XYZ\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"photo.jpg\"\r\n");
+        body.extend_from_slice(b"Content-Type: image/jpeg\r\n\r\n");
+        body.extend_from_slice(file);
+        body.extend_from_slice(b"\r\n--XYZ--\r\n");
+
+        let mut raw: Vec<u8> = format!(
+            "POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=XYZ\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        ).into_bytes();
+        raw.extend_from_slice(&body);
+        raw
+    }
+
+    #[test]
+    fn multipart_body_is_streamed() {
+        // Larger than the buffer of the reader, so most of it is still in the stream
+        let file: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let request = Request::from_bytes(&multipart_request(&file));
+        assert!(request.body.is_empty());
+
+        let form = request.multipart().ok().unwrap();
+        assert_eq!(form.fields.get("title"), Some("Holiday"));
+        assert_eq!(form.file("photo").unwrap().bytes().unwrap(), file);
+    }
+
+    #[test]
+    fn multipart_field_keeps_the_form() {
+        let request = Request::from_bytes(&multipart_request(b"jpeg"));
+        assert_eq!(request.multipart_field("title"), Some(String::from("Holiday")));
+
+        let form = request.multipart().ok().unwrap();
+        assert_eq!(form.file("photo").unwrap().bytes().unwrap(), b"jpeg");
+    }
+}
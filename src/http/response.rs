@@ -1,82 +1,161 @@
 use std::{
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     io::ErrorKind
 };
-use crate::{
-    header,
-    http::{Header, Status}
-};
+use serde::Serialize;
+use crate::http::{Cookie, Header, HeaderMap, Status};
 
 
 pub struct Response {
     pub status: Status,
-    pub headers: Vec<Header>,
-    pub body: String
+    pub headers: HeaderMap,
+    pub body: Vec<u8>
 }
 
 
 impl Response {
-    pub fn new(status: Status, body: String) -> Self {
+    /// Create a new response.
+    /// The `Content-Length` header is set from the body when the response is sent.
+    pub fn new(status: Status, body: impl Into<Vec<u8>>) -> Self {
         Self {
             status,
-            headers: vec![Header::new(
-                "Content-Length".to_string(),
-                body.len().to_string()
-            )],
-            body
+            headers: HeaderMap::new(),
+            body: body.into()
         }
     }
-    
+
+    /// Add a header, keeping the other headers with the same name
     pub fn add_header(&mut self, header: Header) {
-        self.headers.push(header);
+        self.headers.append(header.key(), header.value());
+    }
+
+    /// Set a header, replacing every other header with the same name
+    pub fn set_header(&mut self, header: Header) {
+        self.headers.insert(header.key(), header.value());
+    }
+
+    /// Get the value of the first header with the given name (case-insensitive)
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key)
+    }
+
+    /// Add a `Set-Cookie` header. Multiple cookies can be set on the same response.
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        self.headers.append("Set-Cookie", cookie);
+    }
+
+    /// Tell the client to delete the cookie with the given name (set on the `/` path).
+    /// To delete a cookie set with another path or domain, use `Cookie::removal` with `set_cookie`.
+    pub fn remove_cookie(&mut self, name: &str) {
+        self.set_cookie(Cookie::removal(name));
+    }
+
+    /// Replace the body of the response
+    pub fn set_body(&mut self, body: impl Into<Vec<u8>>) {
+        self.body = body.into();
+    }
+
+    /// # JSON response
+    /// Serializes a value into a `200 OK` response with the `application/json` content type.
+    /// If the value can not be serialized, a `500 Internal Server Error` is returned instead.
+    /// ## Example
+    /// ```rust
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Post {
+    ///     id: i32,
+    ///     title: String
+    /// }
+    ///
+    /// fn post(server: &WebServer, request: &Request) -> Response {
+    ///     Response::json(&Post { id: 1, title: String::from("Hello") })
+    /// }
+    /// ```
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Self {
+        Self::json_with_status(Status::OK, value)
+    }
+
+    /// Serialize a value into a JSON response with the given status
+    pub fn json_with_status<T: Serialize + ?Sized>(status: Status, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                let mut response = Self::new(status, body);
+                response.headers.set_content_type("application/json");
+                response
+            },
+            Err(e) => {
+                eprintln!("Unable to serialize JSON response: {e}");
+                Self::server_error()
+            }
+        }
     }
-    
+
+    /// Redirect the client to another URL with a `302 Found` response
+    pub fn redirect(location: &str) -> Self {
+        let mut response = Self::new(Status::Found, String::new());
+        response.headers.set_location(location);
+        response
+    }
+
     pub fn server_error() -> Self {
         Self::new(
             Status::InternalServerError,
             String::new()
         )
     }
-    
+
     pub fn not_found() -> Self {
         Self::new(
             Status::InternalServerError,
             String::new()
         )
     }
-    
+
+    /// Guess the `Content-Type` of a file from its extension
+    pub fn content_type(path: &Path) -> &'static str {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => match ext {
+                "css" => "text/css",
+                "js" | "mjs" => "application/javascript",
+                "json" => "application/json",
+                "txt" => "text/plain",
+                "xml" => "application/xml",
+                "svg" => "image/svg+xml",
+                "png" => "image/png",
+                "jpg" | "jpeg" => "image/jpeg",
+                "gif" => "image/gif",
+                "webp" => "image/webp",
+                "ico" => "image/x-icon",
+                "woff" => "font/woff",
+                "woff2" => "font/woff2",
+                "pdf" => "application/pdf",
+                "zip" => "application/zip",
+                "gz" => "application/gzip",
+                "wasm" => "application/wasm",
+                _ => "text/html"
+            },
+            None => "text/html"
+        }
+    }
+
     pub fn read_in(path: PathBuf) -> Self {
         // Get the type of static file returned
-        let content_type: Header = match path.extension() {
-            Some(ext) => match ext.to_str() {
-                Some(ext) => match ext {
-                    "css" => header!("Content-Type": "text/css"),
-                    "js" => header!("Content-Type": "application/javascript"),
-                    _ => header!("Content-Type": "text/html")
-                },
-                None => header!("Content-Type": "text/html")
-            },
-            None => header!("Content-Type": "text/html")
-        };
-        
+        let content_type: &str = Self::content_type(&path);
+
         // Read in the file
-        let content: String = match fs::read_to_string(path) {
+        let content: Vec<u8> = match fs::read(path) {
             Ok(c) => c,
             Err(e) => match e.kind() {
                 ErrorKind::NotFound => return Self::new(Status::NotFound, String::new()),
                 _ => panic!("Reading in file failed: {e}")
             }
         };
-        
+
         // Return
-       Self {
-            status: Status::OK,
-            headers: vec![
-                content_type,
-                Header::new("Content-Length".to_string(), content.len().to_string())
-            ],
-            body: content
-        }
+        let mut response = Self::new(Status::OK, content);
+        response.headers.set_content_type(content_type);
+        response
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,13 @@
 mod server;
+#[cfg(feature = "_db_must")]
 mod sql;
 mod http;
 mod html;
+mod middleware;
+mod session;
+mod crypto;
+#[cfg(feature = "_db_must")]
+mod auth;
 
 
 use minijinja::context;
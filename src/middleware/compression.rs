@@ -0,0 +1,357 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf}
+};
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression as Level
+};
+use crate::{
+    http::{Method, Request, Response, Status},
+    middleware::Middleware,
+    server::WebServer
+};
+
+
+/// A content coding, which can be negotiated through the `Accept-Encoding` header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate
+}
+
+
+impl Encoding {
+    /// The name of the encoding, as used in the `Content-Encoding` header
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate"
+        }
+    }
+
+    /// The extension of a precompressed static file using this encoding
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::Brotli => Some("br"),
+            Self::Gzip => Some("gz"),
+            Self::Deflate => None
+        }
+    }
+
+    /// Compress the given bytes, returning `None` if the encoding is not available
+    fn compress(&self, body: &[u8], level: u32) -> Option<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Level::new(level));
+                encoder.write_all(body).ok()?;
+                encoder.finish().ok()
+            },
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Level::new(level));
+                encoder.write_all(body).ok()?;
+                encoder.finish().ok()
+            },
+            #[cfg(feature = "brotli")]
+            Self::Brotli => {
+                let mut output = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, level, 22);
+                    encoder.write_all(body).ok()?;
+                }
+                Some(output)
+            },
+            #[cfg(not(feature = "brotli"))]
+            Self::Brotli => None
+        }
+    }
+}
+
+
+/// # Compression
+/// Middleware, which compresses responses with the best encoding the client accepts.
+/// Responses are only compressed, if:
+/// - they do not already have a `Content-Encoding`
+/// - their body is at least `min_size` bytes long
+/// - their `Content-Type` is not already compressed (images, fonts, archives, ...)
+///
+/// Requests for static files are answered with a precompressed `.br` or `.gz` sibling
+/// of the requested file, if one exists next to it.
+/// ## Example
+/// ```rust
+/// use aerielle::{WebServer, middleware::Compression};
+///
+/// fn main() {
+///     let mut server = WebServer::new();
+///     server.add_middleware(Compression::new().min_size(1024));
+///     server.start().unwrap();
+/// }
+/// ```
+pub struct Compression {
+    min_size: usize,
+    level: u32,
+    encodings: Vec<Encoding>,
+    precompressed: bool
+}
+
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the minimum size of a body, in bytes, for it to be compressed
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Set the compression level (`0`-`9` for gzip and deflate, `0`-`11` for brotli)
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set the encodings the server is willing to use, in the order of preference
+    pub fn encodings(mut self, encodings: Vec<Encoding>) -> Self {
+        self.encodings = encodings;
+        self
+    }
+
+    /// Enable or disable serving precompressed siblings of static files
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Choose an encoding from the `Accept-Encoding` header of a request.
+    /// The encoding with the highest quality value wins.
+    /// If multiple encodings share the same quality value, the server's preference decides.
+    pub fn negotiate(accept_encoding: &str, available: &[Encoding]) -> Option<Encoding> {
+        // Parse the header into (coding, quality) pairs
+        let accepted: Vec<(String, f32)> = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let coding = parts.next()?.trim().to_ascii_lowercase();
+                if coding.is_empty() {
+                    return None;
+                }
+
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+
+                Some((coding, quality))
+            })
+            .collect();
+
+        // Find the quality value of every available encoding
+        let quality_of = |encoding: &Encoding| -> f32 {
+            let exact = accepted
+                .iter()
+                .find(|(coding, _)| coding == encoding.name() || (encoding == &Encoding::Gzip && coding == "x-gzip"));
+            let wildcard = accepted.iter().find(|(coding, _)| coding == "*");
+            match exact.or(wildcard) {
+                Some((_, q)) => *q,
+                None => 0.0
+            }
+        };
+
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in available {
+            let quality = quality_of(encoding);
+            if quality <= 0.0 {
+                continue;
+            }
+            if best.is_none_or(|(_, q)| quality > q) {
+                best = Some((*encoding, quality));
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Test whether a `Content-Type` is worth compressing
+    pub fn is_compressible(content_type: &str) -> bool {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+
+        mime.starts_with("text/")
+            || mime.ends_with("+xml")
+            || mime.ends_with("+json")
+            || matches!(
+                mime.as_str(),
+                "application/javascript"
+                    | "application/json"
+                    | "application/xml"
+                    | "application/wasm"
+                    | "application/x-www-form-urlencoded"
+            )
+    }
+
+    /// The encodings, which can be used to compress a response on the fly
+    fn dynamic_encodings(&self) -> Vec<Encoding> {
+        self.encodings
+            .iter()
+            .copied()
+            .filter(|encoding| cfg!(feature = "brotli") || *encoding != Encoding::Brotli)
+            .collect()
+    }
+
+    /// Look for a precompressed sibling of the requested static file
+    fn serve_precompressed(&self, server: &WebServer, request: &Request) -> Option<Response> {
+        let path: PathBuf = server.static_path(request.path.as_str())?;
+        let accept_encoding = request.headers.accept_encoding()?;
+
+        // Only offer the encodings, which have a precompressed file next to the original
+        let available: Vec<Encoding> = self.encodings
+            .iter()
+            .copied()
+            .filter(|encoding| match encoding.extension() {
+                Some(ext) => Self::sibling(&path, ext).is_file(),
+                None => false
+            })
+            .collect();
+
+        let encoding = Self::negotiate(accept_encoding, &available)?;
+        let sibling = Self::sibling(&path, encoding.extension()?);
+
+        let mut response = Response::read_in(sibling);
+        if response.status != Status::OK {
+            return None;
+        }
+        response.headers.set_content_type(Response::content_type(&path));
+        response.headers.set_content_encoding(encoding.name());
+        response.headers.add_vary("Accept-Encoding");
+        Some(response)
+    }
+
+    /// `file.css` -> `file.css.gz`
+    fn sibling(path: &Path, extension: &str) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(extension);
+        PathBuf::from(name)
+    }
+}
+
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            min_size: 860,
+            level: 6,
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            precompressed: true
+        }
+    }
+}
+
+
+impl Middleware for Compression {
+    fn before(&self, server: &WebServer, request: &mut Request) -> Option<Response> {
+        match request.method {
+            Method::GET | Method::HEAD if self.precompressed && server.static_enabled() => {
+                self.serve_precompressed(server, request)
+            },
+            _ => None
+        }
+    }
+
+    fn after(&self, _server: &WebServer, request: &Request, response: &mut Response) {
+        // Do not compress twice
+        if response.headers.content_encoding().is_some() {
+            return;
+        }
+
+        // Only compress compressible content
+        match response.headers.content_type() {
+            Some(content_type) if Self::is_compressible(content_type) => (),
+            _ => return
+        }
+
+        // The representation now depends on the Accept-Encoding header of the client
+        response.headers.add_vary("Accept-Encoding");
+
+        if response.body.len() < self.min_size {
+            return;
+        }
+
+        // Choose the encoding
+        let encoding = match request.headers.accept_encoding() {
+            Some(accept_encoding) => match Self::negotiate(accept_encoding, &self.dynamic_encodings()) {
+                Some(encoding) => encoding,
+                None => return
+            },
+            None => return
+        };
+
+        // Compress the body, but only keep it if it actually got smaller
+        if let Some(compressed) = encoding.compress(&response.body, self.level)
+            && compressed.len() < response.body.len()
+        {
+            response.set_body(compressed);
+            response.headers.set_content_encoding(encoding.name());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    #[test]
+    fn highest_quality_wins() {
+        assert_eq!(Compression::negotiate("gzip;q=0.5, deflate;q=0.8", &ALL), Some(Encoding::Deflate));
+        assert_eq!(Compression::negotiate("deflate, gzip;q=1.0, br;q=0.1", &ALL), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn ties_follow_server_order() {
+        assert_eq!(Compression::negotiate("deflate, gzip, br", &ALL), Some(Encoding::Brotli));
+        assert_eq!(Compression::negotiate("deflate, gzip", &[Encoding::Gzip, Encoding::Deflate]), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn zero_quality_and_wildcards() {
+        assert_eq!(Compression::negotiate("gzip;q=0", &ALL), None);
+        assert_eq!(Compression::negotiate("*;q=0.5, br;q=0", &ALL), Some(Encoding::Gzip));
+        assert_eq!(Compression::negotiate("identity", &ALL), None);
+        assert_eq!(Compression::negotiate("", &ALL), None);
+    }
+
+    #[test]
+    fn accepts_x_gzip_and_any_case() {
+        assert_eq!(Compression::negotiate("x-gzip", &ALL), Some(Encoding::Gzip));
+        assert_eq!(Compression::negotiate("GZIP; q=0.9", &ALL), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn compresses_large_text_responses() {
+        let server = WebServer::new();
+        let request = Request::from_bytes(b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n");
+
+        let mut response = Response::new(Status::OK, "hello ".repeat(500));
+        response.headers.set_content_type("text/html; charset=utf-8");
+        Compression::new().after(&server, &request, &mut response);
+        assert_eq!(response.headers.content_encoding(), Some("gzip"));
+        assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+
+        let mut small = Response::new(Status::OK, "hello");
+        small.headers.set_content_type("text/html");
+        Compression::new().after(&server, &request, &mut small);
+        assert_eq!(small.headers.content_encoding(), None);
+        assert_eq!(small.body, b"hello");
+    }
+}
@@ -0,0 +1,318 @@
+use std::time::Duration;
+use regex::Regex;
+use crate::{
+    http::{Method, Request, Response, Status},
+    middleware::Middleware,
+    server::WebServer
+};
+
+
+/// # CORS middleware
+/// Lets pages of other origins call the server through cross-origin resource sharing.
+/// Preflight requests (`OPTIONS` with an `Access-Control-Request-Method` header) are answered
+/// by the middleware with `204 No Content`, and the `Access-Control-*` headers are added
+/// to the responses of allowed origins.
+/// It should be added before the other middleware, so preflight requests are answered first.
+/// ## Example
+/// ```rust
+/// server.add_middleware(
+///     Cors::new()
+///         .allow_origin("https://app.example.com")
+///         .allow_origin_pattern(r"https://.*\.example\.com")
+///         .allow_credentials(true)
+///         .paths(r"/api/.*")
+/// );
+/// ```
+pub struct Cors {
+    origins: Vec<String>,
+    origin_patterns: Vec<Regex>,
+    any_origin: bool,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+    paths: Option<Regex>
+}
+
+
+impl Cors {
+    pub fn new() -> Self {
+        Self {
+            origins: Vec::new(),
+            origin_patterns: Vec::new(),
+            any_origin: false,
+            methods: ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+                .map(String::from)
+                .to_vec(),
+            headers: ["Accept", "Authorization", "Content-Type", "X-CSRFToken", "X-Requested-With"]
+                .map(String::from)
+                .to_vec(),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: Some(Duration::from_secs(60 * 60 * 24)),  // One day
+            paths: None
+        }
+    }
+
+    /* BUILDER */
+    /// Allow an origin, like `https://app.example.com`
+    pub fn allow_origin(mut self, origin: impl ToString) -> Self {
+        self.origins.push(origin.to_string().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Allow every origin matching the pattern, like `https://.*\.example\.com`
+    pub fn allow_origin_pattern(mut self, pattern: &str) -> Self {
+        match Regex::new(&format!("^{pattern}$")) {
+            Ok(re) => self.origin_patterns.push(re),
+            Err(e) => panic!("Invalid CORS origin pattern \"{pattern}\": {e}")
+        }
+        self
+    }
+
+    /// Allow every origin
+    pub fn allow_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Set the methods, which other origins may use
+    pub fn allow_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString
+    {
+        self.methods = methods.into_iter().map(|method| method.to_string().to_uppercase()).collect();
+        self
+    }
+
+    /// Set the request headers, which other origins may send
+    pub fn allow_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString
+    {
+        self.headers = headers.into_iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Set the response headers, which the scripts of other origins may read
+    pub fn expose_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString
+    {
+        self.expose_headers = headers.into_iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Allow other origins to send cookies and the `Authorization` header
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Set how long browsers may cache the answer to a preflight request, or `None` to not send it
+    pub fn max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Only handle requests, whose path matches the pattern, using the same syntax as `add_path`
+    pub fn paths(mut self, pattern: &str) -> Self {
+        match Regex::new(&format!("^{pattern}$")) {
+            Ok(re) => self.paths = Some(re),
+            Err(e) => panic!("Invalid CORS path pattern \"{pattern}\": {e}")
+        }
+        self
+    }
+
+    /// Get the `Origin` header of the request, if CORS applies to it
+    fn origin<'a>(&self, request: &'a Request) -> Option<&'a str> {
+        if self.paths.as_ref().is_some_and(|paths| !paths.is_match(&request.path)) {
+            return None;
+        }
+        request.header("Origin")
+    }
+
+    /// Test whether the request is a preflight request, sent by the browser before the actual request
+    fn is_preflight(request: &Request) -> bool {
+        matches!(request.method, Method::OPTIONS) && request.headers.contains("Access-Control-Request-Method")
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.any_origin
+            || self.origins.iter().any(|allowed| allowed == origin)
+            || self.origin_patterns.iter().any(|pattern| pattern.is_match(origin))
+    }
+
+    /// Test whether every header requested by a preflight request is allowed
+    fn headers_allowed(&self, requested: Option<&str>) -> bool {
+        let Some(requested) = requested else { return true };
+        requested
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| self.headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(header)))
+    }
+
+    /// Add the headers, which are sent both for preflight and actual requests
+    fn add_origin_headers(&self, origin: &str, response: &mut Response) {
+        // Credentials are never allowed together with the wildcard, so the origin is echoed instead
+        let allow_origin = match self.any_origin && !self.credentials {
+            true => "*",
+            false => origin
+        };
+        response.headers.insert("Access-Control-Allow-Origin", allow_origin);
+        if self.credentials {
+            response.headers.insert("Access-Control-Allow-Credentials", "true");
+        }
+    }
+}
+
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Middleware for Cors {
+    fn before(&self, _server: &WebServer, request: &mut Request) -> Option<Response> {
+        if !Self::is_preflight(request) {
+            return None;
+        }
+        let origin = self.origin(request)?;
+        let method = request.header("Access-Control-Request-Method")?;
+
+        // A preflight request is always answered, but without the CORS headers the browser blocks the actual request
+        let mut response = Response::new(Status::NoContent, Vec::new());
+        response.headers.add_vary("Origin");
+
+        let method_allowed = self.methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method));
+        let requested_headers = request.header("Access-Control-Request-Headers");
+        if !self.is_allowed(origin) || !method_allowed || !self.headers_allowed(requested_headers) {
+            return Some(response);
+        }
+
+        self.add_origin_headers(origin, &mut response);
+        response.headers.insert("Access-Control-Allow-Methods", self.methods.join(", "));
+        if !self.headers.is_empty() {
+            response.headers.insert("Access-Control-Allow-Headers", self.headers.join(", "));
+        }
+        if let Some(max_age) = self.max_age {
+            response.headers.insert("Access-Control-Max-Age", max_age.as_secs());
+        }
+        Some(response)
+    }
+
+    fn after(&self, _server: &WebServer, request: &Request, response: &mut Response) {
+        // Preflight requests were answered completely in `before`
+        if Self::is_preflight(request) {
+            return;
+        }
+        let Some(origin) = self.origin(request) else { return };
+
+        // The answer depends on the origin, unless every origin receives the same one
+        if !self.any_origin || self.credentials {
+            response.headers.add_vary("Origin");
+        }
+        if !self.is_allowed(origin) || response.headers.contains("Access-Control-Allow-Origin") {
+            return;
+        }
+
+        self.add_origin_headers(origin, response);
+        if !self.expose_headers.is_empty() {
+            response.headers.insert("Access-Control-Expose-Headers", self.expose_headers.join(", "));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preflight(origin: &str, method: &str, headers: &str) -> Request {
+        Request::from_bytes(format!(
+            "OPTIONS /api/posts HTTP/1.1\r\nHost: api.example.com\r\nOrigin: {origin}\r\n\
+            Access-Control-Request-Method: {method}\r\nAccess-Control-Request-Headers: {headers}\r\n\r\n"
+        ).as_bytes())
+    }
+
+    fn actual(origin: &str) -> Request {
+        Request::from_bytes(format!("GET /api/posts HTTP/1.1\r\nHost: api.example.com\r\nOrigin: {origin}\r\n\r\n").as_bytes())
+    }
+
+    /// Run the actual request through the middleware, returning the response
+    fn respond(cors: &Cors, request: Request) -> Response {
+        let mut response = Response::new(Status::OK, "[]");
+        cors.after(&WebServer::new(), &request, &mut response);
+        response
+    }
+
+    #[test]
+    fn answers_allowed_preflight_requests() {
+        let cors = Cors::new().allow_origin("https://app.example.com/");
+        let mut request = preflight("https://app.example.com", "put", "content-type, x-csrftoken");
+        let response = cors.before(&WebServer::new(), &mut request).unwrap();
+
+        assert_eq!(response.status, Status::NoContent);
+        assert_eq!(response.header("Access-Control-Allow-Origin"), Some("https://app.example.com"));
+        assert_eq!(response.header("Access-Control-Allow-Methods"), Some("GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS"));
+        assert_eq!(response.header("Access-Control-Max-Age"), Some("86400"));
+        assert_eq!(response.header("Access-Control-Allow-Credentials"), None);
+        assert_eq!(response.header("Vary"), Some("Origin"));
+    }
+
+    #[test]
+    fn rejected_preflight_requests_have_no_cors_headers() {
+        let cors = Cors::new()
+            .allow_origin_pattern(r"https://.*\.example\.com")
+            .allow_methods(["GET"]);
+
+        for mut request in [
+            preflight("https://evil.com", "GET", ""),
+            preflight("https://app.example.com", "DELETE", ""),
+            preflight("https://app.example.com", "GET", "X-Secret")
+        ] {
+            let response = cors.before(&WebServer::new(), &mut request).unwrap();
+            assert_eq!(response.status, Status::NoContent);
+            assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+        }
+
+        // Without the request method header, OPTIONS is an ordinary request
+        let mut request = actual("https://app.example.com");
+        request.method = Method::OPTIONS;
+        assert!(cors.before(&WebServer::new(), &mut request).is_none());
+    }
+
+    #[test]
+    fn credentials_echo_the_origin() {
+        let response = respond(&Cors::new().allow_any_origin(), actual("https://app.example.com"));
+        assert_eq!(response.header("Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(response.header("Vary"), None);
+
+        let cors = Cors::new().allow_any_origin().allow_credentials(true).expose_headers(["X-Total"]);
+        let response = respond(&cors, actual("https://app.example.com"));
+        assert_eq!(response.header("Access-Control-Allow-Origin"), Some("https://app.example.com"));
+        assert_eq!(response.header("Access-Control-Allow-Credentials"), Some("true"));
+        assert_eq!(response.header("Access-Control-Expose-Headers"), Some("X-Total"));
+        assert_eq!(response.header("Vary"), Some("Origin"));
+    }
+
+    #[test]
+    fn other_origins_and_paths_are_ignored() {
+        let cors = Cors::new().allow_origin("https://app.example.com").paths("/api/.*");
+        let response = respond(&cors, actual("https://evil.com"));
+        assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.header("Vary"), Some("Origin"));
+
+        let cors = Cors::new().allow_origin("https://app.example.com").paths("/public/.*");
+        let response = respond(&cors, actual("https://app.example.com"));
+        assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.header("Vary"), None);
+    }
+}
@@ -0,0 +1,444 @@
+use std::sync::{Arc, Mutex};
+use minijinja::{context, Error, ErrorKind, State, Value};
+use regex::Regex;
+use crate::{
+    crypto::{constant_time_eq, random_string},
+    http::{Method, Request, Response},
+    middleware::Middleware,
+    server::WebServer
+};
+
+
+/// The session key, under which the CSRF secret is stored
+pub const SESSION_KEY: &str = "_csrf_token";
+
+/// The length of the CSRF secret, a masked token is twice as long
+const SECRET_LENGTH: usize = 32;
+
+/// The characters of secrets and tokens
+const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// The CSRF secret of a request, shared with the `_csrf_token` template variable.
+/// Templates can not reach the session, so a secret they create is stored in the session after the response.
+pub(crate) type TemplateSecret = Arc<Mutex<Option<String>>>;
+
+
+/// # CSRF middleware
+/// Protects form submissions against cross-site request forgery.
+/// Every session receives a secret, and unsafe requests (`POST`, `PUT`, `PATCH` and `DELETE`)
+/// are rejected with `403 Forbidden`, unless they send a token matching that secret,
+/// either in the `csrfmiddlewaretoken` form field or in the `X-CSRFToken` header.
+/// It has to be added after the `SessionMiddleware`.
+///
+/// Templates rendered with `WebServer::render_request` can include the token with the
+/// `csrf_token()` and `csrf_input()` functions. The secret of the session is only created
+/// once a template calls one of them, so other pages do not start a session for anonymous visitors:
+/// ```html
+/// <form method="post">
+///     {{ csrf_input() }}
+///     <input name="title">
+/// </form>
+/// ```
+/// ## Example
+/// ```rust
+/// server.add_middleware(SessionMiddleware::new(MemorySession::new()));
+/// server.add_middleware(
+///     CsrfMiddleware::new()
+///         .https(true)
+///         .exempt("/api/webhook")
+///         .trusted_origin("https://admin.example.com")
+/// );
+/// ```
+pub struct CsrfMiddleware {
+    header_name: String,
+    field_name: String,
+    exempt: Vec<Regex>,
+    trusted_origins: Vec<String>,
+    https: bool
+}
+
+
+impl CsrfMiddleware {
+    pub fn new() -> Self {
+        Self {
+            header_name: String::from("X-CSRFToken"),
+            field_name: String::from("csrfmiddlewaretoken"),
+            exempt: Vec::new(),
+            trusted_origins: Vec::new(),
+            https: false
+        }
+    }
+
+    /* BUILDER */
+    /// Set the header, which JavaScript clients send the token in
+    pub fn header_name(mut self, name: impl ToString) -> Self {
+        self.header_name = name.to_string();
+        self
+    }
+
+    /// Set the form field, which forms send the token in
+    pub fn field_name(mut self, name: impl ToString) -> Self {
+        self.field_name = name.to_string();
+        self
+    }
+
+    /// Exempt every path matching the pattern from the check, using the same syntax as `add_path`
+    pub fn exempt(mut self, pattern: &str) -> Self {
+        match Regex::new(&format!("^{pattern}$")) {
+            Ok(re) => self.exempt.push(re),
+            Err(e) => panic!("Invalid CSRF exempt pattern \"{pattern}\": {e}")
+        }
+        self
+    }
+
+    /// Accept unsafe requests from another origin, like `https://admin.example.com`
+    pub fn trusted_origin(mut self, origin: impl ToString) -> Self {
+        self.trusted_origins.push(origin.to_string().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Tell the middleware, that the site is served over HTTPS, even if `request.is_secure()` does not know it.
+    /// Secure unsafe requests are also required to have a `Referer` from the same origin,
+    /// if they do not have an `Origin` header.
+    pub fn https(mut self, https: bool) -> Self {
+        self.https = https;
+        self
+    }
+
+    /// The origin of the server, as sent by browsers in the `Origin` header
+    fn own_origin(&self, request: &Request) -> Option<String> {
+        let host = request.host()?;
+        let scheme = if self.is_secure(request) { "https" } else { "http" };
+        Some(format!("{scheme}://{host}"))
+    }
+
+    fn is_secure(&self, request: &Request) -> bool {
+        self.https || request.is_secure()
+    }
+
+    fn is_trusted(&self, request: &Request, origin: &str) -> bool {
+        self.own_origin(request).as_deref() == Some(origin)
+            || self.trusted_origins.iter().any(|trusted| trusted == origin)
+    }
+
+    /// Find the token sent with the request, in the header or in the form
+    fn submitted_token(&self, request: &Request) -> Option<String> {
+        if let Some(token) = request.header(&self.header_name) {
+            return Some(token.trim().to_string());
+        }
+
+        if request.has_content_type("application/x-www-form-urlencoded") {
+            let form = request.form().ok()?;
+            return form.get(&self.field_name).map(str::to_string);
+        }
+
+        if request.has_content_type("multipart/form-data") {
+            return request.multipart_field(&self.field_name);
+        }
+
+        None
+    }
+
+    /// Test the request, returning the reason of the rejection
+    fn check(&self, request: &Request) -> Result<(), &'static str> {
+        match request.header("Origin") {
+            Some(origin) => if !self.is_trusted(request, origin.trim_end_matches('/')) {
+                return Err("Origin checking failed");
+            },
+            None => if self.is_secure(request) {
+                // Without an Origin header, a secure request has to come from a page of a trusted origin
+                let referer = request.header("Referer").ok_or("Referer checking failed - no Referer")?;
+                match referer_origin(referer) {
+                    Some(origin) if origin.starts_with("https://") && self.is_trusted(request, origin) => (),
+                    _ => return Err("Referer checking failed - Referer does not match any trusted origins")
+                }
+            }
+        }
+
+        let secret: String = request.session().get(SESSION_KEY).ok_or("CSRF token not set")?;
+        let token = self.submitted_token(request).ok_or("CSRF token missing")?;
+        match unmask(&token) {
+            Some(unmasked) if constant_time_eq(unmasked.as_bytes(), secret.as_bytes()) => Ok(()),
+            _ => Err("CSRF token incorrect")
+        }
+    }
+}
+
+
+impl Default for CsrfMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Middleware for CsrfMiddleware {
+    fn before(&self, server: &WebServer, request: &mut Request) -> Option<Response> {
+        if request.session.is_none() {
+            panic!("The CsrfMiddleware requires the SessionMiddleware to be added before it.");
+        }
+
+        // Safe methods never change anything, so they are not checked
+        if !matches!(request.method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE) {
+            return None;
+        }
+        if self.exempt.iter().any(|pattern| pattern.is_match(&request.path)) {
+            return None;
+        }
+
+        match self.check(request) {
+            Ok(()) => None,
+            Err(reason) => {
+                eprintln!("Forbidden ({reason}): {}", request.path);
+                Some(server.forbidden_error(request))
+            }
+        }
+    }
+
+    /// Store a secret created while rendering in the session, which sets the session cookie
+    fn after(&self, _server: &WebServer, request: &Request, _response: &mut Response) {
+        let Some(secret) = request.csrf_secret.take() else { return };
+        let secret = secret.lock().unwrap().take();
+        if let Some(secret) = secret && request.session().get::<String>(SESSION_KEY).is_none() {
+            request.session().set(SESSION_KEY, &secret);
+        }
+    }
+
+    /// `_csrf_token` is a function, so a secret (and with it a session) is only created
+    /// for pages which actually include a token, not for every rendered template
+    fn context(&self, _server: &WebServer, request: &Request) -> Option<Value> {
+        // Templates rendered more than once per request share the same secret
+        let secret: TemplateSecret = request.csrf_secret
+            .borrow_mut()
+            .get_or_insert_with(|| Arc::new(Mutex::new(request.session().get(SESSION_KEY))))
+            .clone();
+
+        let token = Value::from_function(move || -> String {
+            let mut secret = secret.lock().unwrap();
+            mask(secret.get_or_insert_with(|| random_string(SECRET_LENGTH)))
+        });
+
+        Some(context!(
+            _csrf_token => token,
+            _csrf_field => self.field_name
+        ))
+    }
+}
+
+
+/// # Get the CSRF token
+/// Returns a token for the session of the request, creating its secret if needed.
+/// Every call returns a differently masked token, all of which are valid,
+/// so the token can not be recovered from compressed responses (BREACH).
+/// Useful for JavaScript clients, which send it back in the `X-CSRFToken` header.
+pub fn get_csrf_token(request: &Request) -> String {
+    let session = request.session();
+    let secret: String = match session.get(SESSION_KEY) {
+        Some(secret) => secret,
+        None => {
+            let secret = random_string(SECRET_LENGTH);
+            session.set(SESSION_KEY, &secret);
+            secret
+        }
+    };
+    mask(&secret)
+}
+
+
+/// Replace the CSRF secret of the session, invalidating every token issued before (like logging in)
+pub fn rotate_csrf_token(request: &Request) {
+    request.session().remove(SESSION_KEY);
+}
+
+
+fn position(c: u8) -> Option<usize> {
+    CHARS.iter().position(|&x| x == c)
+}
+
+
+/// Mask the secret with a random mask, returning the mask followed by the masked secret
+fn mask(secret: &str) -> String {
+    let mask = random_string(SECRET_LENGTH);
+    let masked: String = secret
+        .bytes()
+        .zip(mask.bytes())
+        .map(|(s, m)| {
+            let (s, m) = (position(s).unwrap_or(0), position(m).unwrap_or(0));
+            CHARS[(s + m) % CHARS.len()] as char
+        })
+        .collect();
+    format!("{mask}{masked}")
+}
+
+
+/// Recover the secret from a masked token, accepting unmasked secrets as well
+fn unmask(token: &str) -> Option<String> {
+    if token.len() == SECRET_LENGTH {
+        return Some(token.to_string());
+    }
+    if token.len() != SECRET_LENGTH * 2 {
+        return None;
+    }
+
+    let (mask, masked) = token.as_bytes().split_at(SECRET_LENGTH);
+    masked
+        .iter()
+        .zip(mask.iter())
+        .map(|(&c, &m)| {
+            let (c, m) = (position(c)?, position(m)?);
+            Some(CHARS[(c + CHARS.len() - m) % CHARS.len()] as char)
+        })
+        .collect()
+}
+
+
+/// Get the origin (`scheme://host:port`) of a `Referer` header
+fn referer_origin(referer: &str) -> Option<&str> {
+    let start = referer.find("://")? + 3;
+    let end = referer[start..]
+        .find(['/', '?', '#'])
+        .map(|index| start + index)
+        .unwrap_or(referer.len());
+    Some(&referer[..end])
+}
+
+
+/// # `csrf_token` template function
+/// Returns a CSRF token for the current request, provided by the `CsrfMiddleware`
+/// to templates rendered with `WebServer::render_request`.
+pub fn csrf_token_function(state: &State) -> Result<String, Error> {
+    match state.lookup("_csrf_token") {
+        Some(token) if !token.is_undefined() => Ok(token.call(state, &[])?.to_string()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidOperation,
+            "csrf_token() requires the CsrfMiddleware and WebServer::render_request"
+        ))
+    }
+}
+
+
+/// # `csrf_input` template function
+/// Returns a hidden form field holding a CSRF token for the current request.
+pub fn csrf_input_function(state: &State) -> Result<Value, Error> {
+    let token = csrf_token_function(state)?;
+    let field = state
+        .lookup("_csrf_field")
+        .map(|field| field.to_string())
+        .unwrap_or_else(|| String::from("csrfmiddlewaretoken"));
+
+    Ok(Value::from_safe_string(format!(
+        "<input type=\"hidden\" name=\"{field}\" value=\"{token}\">"
+    )))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{http::Status, session::{SessionData, SessionValues}};
+    use super::*;
+
+    const SECRET: &str = "abcdefghijklmnopqrstuvwxyz012345";
+
+    /// A `POST` request with the given extra headers and URL-encoded body, and a session holding the secret
+    fn post(headers: &str, body: &str) -> Request {
+        let mut request = Request::from_bytes(format!(
+            "POST /comments HTTP/1.1\r\nHost: example.com\r\n{headers}Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ).as_bytes());
+        let values = SessionValues::from([(String::from(SESSION_KEY), serde_json::Value::from(SECRET))]);
+        request.session = Some(SessionData::new(Some(String::from("key")), values));
+        request
+    }
+
+    /// The status the middleware responds with, or `None` if it lets the request through
+    fn status(middleware: &CsrfMiddleware, mut request: Request) -> Option<Status> {
+        middleware.before(&WebServer::new(), &mut request).map(|response| response.status)
+    }
+
+    #[test]
+    fn accepts_masked_tokens() {
+        let middleware = CsrfMiddleware::new();
+        let token = mask(SECRET);
+        assert_ne!(token, mask(SECRET));
+
+        assert_eq!(status(&middleware, post("", &format!("csrfmiddlewaretoken={token}"))), None);
+        assert_eq!(status(&middleware, post(&format!("X-CSRFToken: {}\r\n", mask(SECRET)), "")), None);
+    }
+
+    #[test]
+    fn accepts_tokens_in_multipart_forms() {
+        let body = format!(
+            "--XYZ\r\nContent-Disposition: form-data; name=\"csrfmiddlewaretoken\"\r\n\r\n{}\r\n\
+            --XYZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello\r\n--XYZ--\r\n",
+            mask(SECRET)
+        );
+        let mut request = Request::from_bytes(format!(
+            "POST /posts HTTP/1.1\r\nHost: example.com\r\nContent-Type: multipart/form-data; boundary=XYZ\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ).as_bytes());
+        let values = SessionValues::from([(String::from(SESSION_KEY), serde_json::Value::from(SECRET))]);
+        request.session = Some(SessionData::new(None, values));
+
+        assert!(CsrfMiddleware::new().before(&WebServer::new(), &mut request).is_none());
+        // The handler still receives the form
+        assert_eq!(request.multipart().ok().unwrap().fields.get("title"), Some("Hello"));
+    }
+
+    #[test]
+    fn rejects_tampered_and_missing_tokens() {
+        let middleware = CsrfMiddleware::new();
+        let mut token = mask(SECRET).into_bytes();
+        token[40] = if token[40] == b'a' { b'b' } else { b'a' };
+        let token = String::from_utf8(token).unwrap();
+
+        assert_eq!(status(&middleware, post("", &format!("csrfmiddlewaretoken={token}"))), Some(Status::Forbidden));
+        assert_eq!(status(&middleware, post("", "csrfmiddlewaretoken=short")), Some(Status::Forbidden));
+        assert_eq!(status(&middleware, post("", "title=Hello")), Some(Status::Forbidden));
+        assert_eq!(status(&middleware, post("", &format!("csrfmiddlewaretoken={}", mask("another secret of 32 characters")))), Some(Status::Forbidden));
+    }
+
+    #[test]
+    fn checks_the_origin() {
+        let body = format!("csrfmiddlewaretoken={}", mask(SECRET));
+        let middleware = CsrfMiddleware::new().trusted_origin("https://admin.example.com/");
+
+        assert_eq!(status(&middleware, post("Origin: http://example.com\r\n", &body)), None);
+        assert_eq!(status(&middleware, post("Origin: https://admin.example.com\r\n", &body)), None);
+        assert_eq!(status(&middleware, post("Origin: https://evil.com\r\n", &body)), Some(Status::Forbidden));
+    }
+
+    #[test]
+    fn secure_requests_need_a_same_origin_referer() {
+        let body = format!("csrfmiddlewaretoken={}", mask(SECRET));
+        let middleware = CsrfMiddleware::new().https(true);
+
+        assert_eq!(status(&middleware, post("Referer: https://example.com/comments/new\r\n", &body)), None);
+        assert_eq!(status(&middleware, post("Referer: https://evil.com/comments/new\r\n", &body)), Some(Status::Forbidden));
+        assert_eq!(status(&middleware, post("Referer: http://example.com/comments/new\r\n", &body)), Some(Status::Forbidden));
+        assert_eq!(status(&middleware, post("", &body)), Some(Status::Forbidden));
+    }
+
+    #[test]
+    fn safe_methods_and_exempt_paths_are_not_checked() {
+        let mut request = Request::from_bytes(b"GET /comments HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        request.session = Some(SessionData::default());
+        assert_eq!(status(&CsrfMiddleware::new(), request), None);
+
+        assert_eq!(status(&CsrfMiddleware::new().exempt("/comm.*"), post("", "")), None);
+    }
+
+    #[test]
+    fn templates_create_the_secret() {
+        let server = WebServer::new();
+        let middleware = CsrfMiddleware::new();
+        let mut request = Request::from_bytes(b"GET /form HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        request.session = Some(SessionData::default());
+
+        let context = middleware.context(&server, &request).unwrap();
+        let token = server.get_environment().render_str("{{ csrf_token() }}", context).unwrap();
+        middleware.after(&server, &request, &mut Response::new(Status::OK, ""));
+
+        let secret: String = request.session().get(SESSION_KEY).unwrap();
+        assert_eq!(unmask(&token), Some(secret));
+    }
+}
@@ -0,0 +1,46 @@
+mod compression;
+mod cors;
+mod csrf;
+mod rate_limit;
+mod security;
+
+pub use compression::{Compression, Encoding};
+pub use cors::Cors;
+pub use csrf::{
+    CsrfMiddleware,
+    get_csrf_token, rotate_csrf_token,
+    csrf_token_function, csrf_input_function
+};
+pub(crate) use csrf::TemplateSecret;
+pub use rate_limit::{Algorithm, Decision, MemoryStore, RateLimit, RateLimitStore, RateLimiter};
+pub use security::SecurityMiddleware;
+
+use minijinja::Value;
+use crate::{
+    http::{Request, Response},
+    server::WebServer
+};
+
+
+/// # Middleware
+/// A hook into the request-response cycle of the `WebServer`, similar to Django's middleware.
+/// Middleware is added with `WebServer::add_middleware`, and runs in the order it was added:
+/// - `before` runs for every request before it is routed to a handler function
+/// - `after` runs in the reverse order, once a response was produced
+///
+/// If `before` returns a `Response`, the request is not routed any further,
+/// and only the middleware which already ran get to see the response in `after`.
+///
+/// Middleware can also provide variables to templates through `context`,
+/// which are available to every template rendered with `WebServer::render_request`.
+pub trait Middleware {
+    fn before(&self, _server: &WebServer, _request: &mut Request) -> Option<Response> {
+        None
+    }
+
+    fn after(&self, _server: &WebServer, _request: &Request, _response: &mut Response) {}
+
+    fn context(&self, _server: &WebServer, _request: &Request) -> Option<Value> {
+        None
+    }
+}
@@ -0,0 +1,438 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+use regex::Regex;
+use crate::{
+    http::{Request, Response},
+    middleware::Middleware,
+    server::WebServer
+};
+
+
+/// The algorithm, which decides whether a request is over the limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Allows bursts of up to `limit` requests, refilling at `limit` requests per `window`
+    TokenBucket,
+    /// Allows at most `limit` requests in any `window` long period
+    SlidingWindow
+}
+
+
+/// # Rate limit
+/// The number of requests a client may make in a time window.
+/// ## Example
+/// ```rust
+/// let limit = RateLimit::per_minute(5).algorithm(Algorithm::SlidingWindow);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    limit: u32,
+    window: Duration,
+    algorithm: Algorithm
+}
+
+
+impl RateLimit {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        if limit == 0 || window.is_zero() {
+            panic!("A rate limit has to allow at least one request in a non-empty window");
+        }
+        Self { limit, window, algorithm: Algorithm::TokenBucket }
+    }
+
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    pub fn per_hour(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60 * 60))
+    }
+
+    /* BUILDER */
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+
+/// A function, which tells clients apart
+pub type KeyFunction = Box<dyn Fn(&Request) -> Option<String>>;
+
+
+/// The outcome of counting a request against a rate limit
+#[derive(Clone, Copy, Debug)]
+pub struct Decision {
+    /// Whether the request is allowed
+    pub allowed: bool,
+    /// The number of requests still allowed right now
+    pub remaining: u32,
+    /// The time until the limit is fully available again
+    pub reset: Duration,
+    /// The time until the next request is allowed
+    pub retry_after: Duration
+}
+
+
+/// # Rate limit store
+/// Keeps the state of the rate limits between requests.
+/// Aerielle comes with the `MemoryStore`, which keeps it in the memory of the server.
+pub trait RateLimitStore {
+    /// Count a request with the given key against the rate limit
+    fn hit(&self, key: &str, limit: &RateLimit) -> Decision;
+}
+
+
+/// The state of a single key
+enum Counter {
+    Bucket { tokens: f64, updated: Instant },
+    Window(VecDeque<Instant>)
+}
+
+
+/// How often the `MemoryStore` forgets the keys, which are back at their full limit
+const CLEAR_INTERVAL: Duration = Duration::from_secs(60);
+
+
+/// # In-memory rate limit store
+/// Keeps the state of the rate limits in the memory of the server.
+/// Keys, which are back at their full limit, are forgotten every minute.
+/// Every counter is lost when the server is restarted.
+pub struct MemoryStore {
+    counters: Mutex<HashMap<String, (Counter, Instant)>>,
+    last_cleared: Mutex<Instant>
+}
+
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::new(HashMap::new()),
+            last_cleared: Mutex::new(Instant::now())
+        }
+    }
+
+    /// Remove the counters of every key, which is back at its full limit
+    pub fn clear_expired(&self) {
+        let now = Instant::now();
+        self.counters
+            .lock()
+            .expect("Rate limit store was poisoned")
+            .retain(|_, (_, expires)| *expires > now);
+    }
+}
+
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl RateLimitStore for MemoryStore {
+    fn hit(&self, key: &str, limit: &RateLimit) -> Decision {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().expect("Rate limit store was poisoned");
+
+        // Forget the keys, which were not seen for a while, so the store does not keep growing
+        let mut last_cleared = self.last_cleared.lock().expect("Rate limit store was poisoned");
+        if now.duration_since(*last_cleared) >= CLEAR_INTERVAL {
+            counters.retain(|_, (_, expires)| *expires > now);
+            *last_cleared = now;
+        }
+        drop(last_cleared);
+
+        let (counter, expires) = counters
+            .entry(key.to_string())
+            .or_insert_with(|| match limit.algorithm {
+                Algorithm::TokenBucket => (Counter::Bucket { tokens: limit.limit as f64, updated: now }, now),
+                Algorithm::SlidingWindow => (Counter::Window(VecDeque::new()), now)
+            });
+
+        let decision = match counter {
+            Counter::Bucket { tokens, updated } => {
+                // Refill the bucket for the time passed since the last request
+                let rate = limit.limit as f64 / limit.window.as_secs_f64();
+                *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * rate).min(limit.limit as f64);
+                *updated = now;
+
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    remaining: tokens.floor() as u32,
+                    reset: Duration::from_secs_f64((limit.limit as f64 - *tokens) / rate),
+                    retry_after: Duration::from_secs_f64((1.0 - *tokens).max(0.0) / rate)
+                }
+            },
+            Counter::Window(hits) => {
+                // Drop the requests, which left the window
+                while hits.front().is_some_and(|hit| now.duration_since(*hit) >= limit.window) {
+                    hits.pop_front();
+                }
+
+                let allowed = hits.len() < limit.limit as usize;
+                if allowed {
+                    hits.push_back(now);
+                }
+                let until_oldest_leaves = hits
+                    .front()
+                    .map(|oldest| limit.window.saturating_sub(now.duration_since(*oldest)))
+                    .unwrap_or_default();
+                Decision {
+                    allowed,
+                    remaining: limit.limit - hits.len() as u32,
+                    reset: hits
+                        .back()
+                        .map(|newest| limit.window.saturating_sub(now.duration_since(*newest)))
+                        .unwrap_or_default(),
+                    retry_after: match allowed {
+                        true => Duration::ZERO,
+                        false => until_oldest_leaves
+                    }
+                }
+            }
+        };
+
+        *expires = now + decision.reset;
+        decision
+    }
+}
+
+
+/// # Rate limiting middleware
+/// Limits the number of requests a client can make, responding with `429 Too Many Requests`
+/// (`WebServer::set_too_many_requests_error`) once the limit is reached.
+/// Limited responses carry the `Retry-After`, `RateLimit-Limit`, `RateLimit-Remaining`,
+/// `RateLimit-Reset` and `RateLimit-Policy` headers.
+///
+/// Clients are told apart by their IP address by default, but they can also be keyed
+/// by the logged in user, or by a custom function.
+/// Routes can have their own limits, and only the first matching route is counted.
+/// ## Example
+/// ```rust
+/// server.add_middleware(
+///     RateLimiter::new()
+///         .limit(RateLimit::per_minute(120))
+///         .route("/accounts/login/", RateLimit::per_minute(5).algorithm(Algorithm::SlidingWindow))
+///         .route("/accounts/password_reset/", RateLimit::per_hour(3))
+/// );
+/// ```
+pub struct RateLimiter {
+    default: Option<RateLimit>,
+    routes: Vec<(Regex, RateLimit)>,
+    key: KeyFunction,
+    store: Box<dyn RateLimitStore>
+}
+
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            default: None,
+            routes: Vec::new(),
+            key: Box::new(Self::ip_key),
+            store: Box::new(MemoryStore::new())
+        }
+    }
+
+    /* BUILDER */
+    /// Limit every request, which does not match a route with its own limit
+    pub fn limit(mut self, limit: RateLimit) -> Self {
+        self.default = Some(limit);
+        self
+    }
+
+    /// Limit the requests, whose path matches the pattern, using the same syntax as `add_path`
+    pub fn route(mut self, pattern: &str, limit: RateLimit) -> Self {
+        match Regex::new(&format!("^{pattern}$")) {
+            Ok(re) => self.routes.push((re, limit)),
+            Err(e) => panic!("Invalid rate limit pattern \"{pattern}\": {e}")
+        }
+        self
+    }
+
+    /// Tell clients apart by their IP address (the default)
+    pub fn key_by_ip(mut self) -> Self {
+        self.key = Box::new(Self::ip_key);
+        self
+    }
+
+    /// Tell clients apart by the logged in user, falling back to the IP address for anonymous clients.
+    /// The `AuthMiddleware` has to be added before the rate limiter.
+    #[cfg(feature = "_db_must")]
+    pub fn key_by_user(mut self) -> Self {
+        self.key = Box::new(|request: &Request| match request.user() {
+            Some(user) => Some(format!("user:{}", user.id)),
+            None => Self::ip_key(request)
+        });
+        self
+    }
+
+    /// Tell clients apart with a custom function. Requests, for which it returns `None`, are not limited.
+    /// ## Example
+    /// ```rust
+    /// let limiter = RateLimiter::new()
+    ///     .limit(RateLimit::per_hour(1000))
+    ///     .key_by(|request| request.header("X-API-Key").map(str::to_string));
+    /// ```
+    pub fn key_by(mut self, key: impl Fn(&Request) -> Option<String> + 'static) -> Self {
+        self.key = Box::new(key);
+        self
+    }
+
+    /// Keep the counters in a custom store
+    pub fn store(mut self, store: impl RateLimitStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    fn ip_key(request: &Request) -> Option<String> {
+        request.client_ip().map(|address| format!("ip:{address}"))
+    }
+
+    /// Find the limit of the request, along with the name of its counter
+    fn find_limit(&self, request: &Request) -> Option<(String, RateLimit)> {
+        for (index, (pattern, limit)) in self.routes.iter().enumerate() {
+            if pattern.is_match(&request.path) {
+                return Some((format!("route{index}"), *limit));
+            }
+        }
+        self.default.map(|limit| (String::from("default"), limit))
+    }
+}
+
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Round a duration up to whole seconds, as used by the headers
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+
+impl Middleware for RateLimiter {
+    fn before(&self, server: &WebServer, request: &mut Request) -> Option<Response> {
+        let (name, limit) = self.find_limit(request)?;
+        let key = (self.key)(request)?;
+
+        let decision = self.store.hit(&format!("{name}:{key}"), &limit);
+        if decision.allowed {
+            return None;
+        }
+
+        let mut response = server.too_many_requests_error(request);
+        let headers = &mut response.headers;
+        headers.insert("Retry-After", seconds(decision.retry_after).max(1));
+        headers.insert("RateLimit-Limit", limit.limit);
+        headers.insert("RateLimit-Remaining", decision.remaining);
+        headers.insert("RateLimit-Reset", seconds(decision.reset));
+        headers.insert("RateLimit-Policy", format!("{};w={}", limit.limit, seconds(limit.window)));
+        Some(response)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::http::Status;
+    use super::*;
+
+    #[test]
+    fn token_bucket_allows_bursts() {
+        let store = MemoryStore::new();
+        let limit = RateLimit::per_minute(3);
+        for remaining in [2, 1, 0] {
+            let decision = store.hit("client", &limit);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+
+        let decision = store.hit("client", &limit);
+        assert!(!decision.allowed);
+        assert!(decision.retry_after > Duration::from_secs(19) && decision.retry_after <= Duration::from_secs(20));
+
+        // Other keys have their own bucket
+        assert!(store.hit("other", &limit).allowed);
+    }
+
+    #[test]
+    fn sliding_window_counts_requests_in_the_window() {
+        let store = MemoryStore::new();
+        let limit = RateLimit::new(2, Duration::from_millis(100)).algorithm(Algorithm::SlidingWindow);
+        assert!(store.hit("client", &limit).allowed);
+        assert!(store.hit("client", &limit).allowed);
+
+        let decision = store.hit("client", &limit);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert!(decision.retry_after <= Duration::from_millis(100));
+
+        std::thread::sleep(Duration::from_millis(110));
+        assert!(store.hit("client", &limit).allowed);
+    }
+
+    #[test]
+    fn full_keys_are_forgotten_every_interval() {
+        let store = MemoryStore::new();
+        let limit = RateLimit::new(1, Duration::from_millis(10));
+        store.hit("a", &limit);
+        store.hit("b", &limit);
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Within the interval, nothing is removed
+        store.hit("c", &limit);
+        assert_eq!(store.counters.lock().unwrap().len(), 3);
+
+        *store.last_cleared.lock().unwrap() -= CLEAR_INTERVAL;
+        store.hit("c", &limit);
+        let counters = store.counters.lock().unwrap();
+        assert_eq!(counters.len(), 1);
+        assert!(counters.contains_key("c"));
+    }
+
+    #[test]
+    fn limited_requests_receive_429() {
+        let server = WebServer::new();
+        let limiter = RateLimiter::new()
+            .limit(RateLimit::per_minute(100))
+            .route("/login", RateLimit::per_minute(1));
+        let request = || Request::from_bytes(b"POST /login HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+        assert!(limiter.before(&server, &mut request()).is_none());
+        let response = limiter.before(&server, &mut request()).unwrap();
+        assert_eq!(response.status, Status::TooManyRequests);
+        assert_eq!(response.header("Retry-After"), Some("60"));
+        assert_eq!(response.header("RateLimit-Limit"), Some("1"));
+        assert_eq!(response.header("RateLimit-Remaining"), Some("0"));
+        assert_eq!(response.header("RateLimit-Policy"), Some("1;w=60"));
+
+        // Requests without a key are not limited
+        let limiter = RateLimiter::new().limit(RateLimit::per_minute(1)).key_by(|_| None);
+        assert!(limiter.before(&server, &mut request()).is_none());
+        assert!(limiter.before(&server, &mut request()).is_none());
+    }
+}
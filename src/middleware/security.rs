@@ -0,0 +1,260 @@
+use std::time::Duration;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use minijinja::{context, Value};
+use crate::{
+    http::{Request, Response},
+    middleware::Middleware,
+    server::WebServer
+};
+
+
+/// # Security middleware
+/// Adds security related headers to every response:
+/// - `Strict-Transport-Security`, telling browsers to only use HTTPS (disabled by default, only sent over HTTPS)
+/// - `Content-Security-Policy`, restricting the resources pages may load (disabled by default)
+/// - `X-Frame-Options: DENY`, so pages can not be embedded by other sites
+/// - `X-Content-Type-Options: nosniff`
+/// - `Referrer-Policy: same-origin`
+/// - `Permissions-Policy`, restricting the browser features pages may use (disabled by default)
+///
+/// Headers already set by the handler function are never overwritten.
+///
+/// Every `{nonce}` in the content security policy is replaced with a random nonce, generated for each request.
+/// It is available as `request.csp_nonce()`, and as `csp_nonce` in templates rendered with `WebServer::render_request`:
+/// ```html
+/// <script nonce="{{ csp_nonce }}">
+///     console.log("Allowed by the policy");
+/// </script>
+/// ```
+/// ## Example
+/// ```rust
+/// server.add_middleware(
+///     SecurityMiddleware::new()
+///         .hsts(Duration::from_secs(60 * 60 * 24 * 365), true, false)
+///         .content_security_policy("default-src 'self'; script-src 'self' 'nonce-{nonce}'")
+/// );
+/// ```
+pub struct SecurityMiddleware {
+    hsts: Option<String>,
+    content_security_policy: Option<String>,
+    report_only: bool,
+    frame_options: Option<String>,
+    content_type_nosniff: bool,
+    referrer_policy: Option<String>,
+    permissions_policy: Option<String>
+}
+
+
+impl SecurityMiddleware {
+    pub fn new() -> Self {
+        Self {
+            hsts: None,
+            content_security_policy: None,
+            report_only: false,
+            frame_options: Some(String::from("DENY")),
+            content_type_nosniff: true,
+            referrer_policy: Some(String::from("same-origin")),
+            permissions_policy: None
+        }
+    }
+
+    /* BUILDER */
+    /// Send `Strict-Transport-Security`, telling browsers to only connect over HTTPS for `max_age`.
+    /// Only enable it once the whole site works over HTTPS, because browsers remember it.
+    pub fn hsts(mut self, max_age: Duration, include_subdomains: bool, preload: bool) -> Self {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if preload {
+            value.push_str("; preload");
+        }
+        self.hsts = Some(value);
+        self
+    }
+
+    /// Send a `Content-Security-Policy`, where every `{nonce}` is replaced with the nonce of the request
+    pub fn content_security_policy(mut self, policy: impl ToString) -> Self {
+        self.content_security_policy = Some(policy.to_string());
+        self
+    }
+
+    /// Send the policy as `Content-Security-Policy-Report-Only`, so violations are only reported
+    pub fn report_only(mut self, report_only: bool) -> Self {
+        self.report_only = report_only;
+        self
+    }
+
+    /// Set the `X-Frame-Options` header (`DENY` or `SAMEORIGIN`), or `None` to not send it
+    pub fn frame_options(mut self, value: Option<&str>) -> Self {
+        self.frame_options = value.map(str::to_string);
+        self
+    }
+
+    pub fn content_type_nosniff(mut self, nosniff: bool) -> Self {
+        self.content_type_nosniff = nosniff;
+        self
+    }
+
+    /// Set the `Referrer-Policy` header, or `None` to not send it
+    pub fn referrer_policy(mut self, policy: Option<&str>) -> Self {
+        self.referrer_policy = policy.map(str::to_string);
+        self
+    }
+
+    /// Set the `Permissions-Policy` header, like `camera=(), geolocation=(self)`
+    pub fn permissions_policy(mut self, policy: impl ToString) -> Self {
+        self.permissions_policy = Some(policy.to_string());
+        self
+    }
+}
+
+
+impl Default for SecurityMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Generate a random nonce for the `Content-Security-Policy`
+fn generate_nonce() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>())
+}
+
+
+impl Middleware for SecurityMiddleware {
+    fn before(&self, _server: &WebServer, request: &mut Request) -> Option<Response> {
+        if self.content_security_policy.is_some() {
+            request.csp_nonce = Some(generate_nonce());
+        }
+        None
+    }
+
+    fn after(&self, _server: &WebServer, request: &Request, response: &mut Response) {
+        let headers = &mut response.headers;
+
+        // Browsers ignore HSTS on insecure connections
+        if let Some(hsts) = &self.hsts && request.is_secure() && !headers.contains("Strict-Transport-Security") {
+            headers.insert("Strict-Transport-Security", hsts);
+        }
+
+        if let Some(policy) = &self.content_security_policy {
+            let name = match self.report_only {
+                true => "Content-Security-Policy-Report-Only",
+                false => "Content-Security-Policy"
+            };
+            if !headers.contains(name) {
+                let policy = policy.replace("{nonce}", request.csp_nonce().unwrap_or_default());
+                headers.insert(name, policy);
+            }
+        }
+
+        let defaults = [
+            ("X-Frame-Options", self.frame_options.as_deref()),
+            ("X-Content-Type-Options", self.content_type_nosniff.then_some("nosniff")),
+            ("Referrer-Policy", self.referrer_policy.as_deref()),
+            ("Permissions-Policy", self.permissions_policy.as_deref())
+        ];
+        for (name, value) in defaults {
+            if let Some(value) = value && !headers.contains(name) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    fn context(&self, _server: &WebServer, request: &Request) -> Option<Value> {
+        let nonce = request.csp_nonce()?;
+        Some(context!(csp_nonce => nonce))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::http::Status;
+    use super::*;
+
+    fn request() -> Request {
+        Request::from_bytes(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+    }
+
+    /// Run a request through the middleware, returning the request and the response
+    fn run(middleware: &SecurityMiddleware, mut response: Response) -> (Request, Response) {
+        let server = WebServer::new();
+        let mut request = request();
+        assert!(middleware.before(&server, &mut request).is_none());
+        middleware.after(&server, &request, &mut response);
+        (request, response)
+    }
+
+    #[test]
+    fn adds_default_headers() {
+        let (request, response) = run(&SecurityMiddleware::new(), Response::new(Status::OK, ""));
+        assert_eq!(response.header("X-Frame-Options"), Some("DENY"));
+        assert_eq!(response.header("X-Content-Type-Options"), Some("nosniff"));
+        assert_eq!(response.header("Referrer-Policy"), Some("same-origin"));
+        assert_eq!(response.header("Content-Security-Policy"), None);
+        assert_eq!(request.csp_nonce(), None);
+
+        // HSTS is only sent over HTTPS
+        let middleware = SecurityMiddleware::new().hsts(Duration::from_secs(3600), true, false);
+        let (_, response) = run(&middleware, Response::new(Status::OK, ""));
+        assert_eq!(response.header("Strict-Transport-Security"), None);
+    }
+
+    #[test]
+    fn keeps_headers_of_the_handler() {
+        let mut response = Response::new(Status::OK, "");
+        response.headers.insert("X-Frame-Options", "SAMEORIGIN");
+        let (_, response) = run(&SecurityMiddleware::new().frame_options(None), response);
+        assert_eq!(response.headers.get_all("X-Frame-Options"), vec!["SAMEORIGIN"]);
+    }
+
+    #[test]
+    fn policy_uses_the_nonce_of_the_request() {
+        let middleware = SecurityMiddleware::new().content_security_policy("script-src 'nonce-{nonce}'; style-src 'nonce-{nonce}'");
+        let (request, response) = run(&middleware, Response::new(Status::OK, ""));
+        let nonce = request.csp_nonce().unwrap();
+        assert_eq!(nonce.len(), 22);
+        assert_eq!(
+            response.header("Content-Security-Policy"),
+            Some(format!("script-src 'nonce-{nonce}'; style-src 'nonce-{nonce}'").as_str())
+        );
+
+        let rendered = WebServer::new()
+            .get_environment()
+            .render_str("{{ csp_nonce }}", middleware.context(&WebServer::new(), &request).unwrap())
+            .unwrap();
+        assert_eq!(rendered, nonce);
+
+        // Every request has another nonce
+        let (other, _) = run(&middleware, Response::new(Status::OK, ""));
+        assert_ne!(other.csp_nonce(), Some(nonce));
+    }
+
+    #[test]
+    fn report_only_policy() {
+        let middleware = SecurityMiddleware::new().content_security_policy("default-src 'self'").report_only(true);
+        let (_, response) = run(&middleware, Response::new(Status::OK, ""));
+        assert_eq!(response.header("Content-Security-Policy"), None);
+        assert_eq!(response.header("Content-Security-Policy-Report-Only"), Some("default-src 'self'"));
+    }
+
+    #[test]
+    fn allowed_hosts() {
+        let mut server = WebServer::new();
+        server.set_allowed_hosts(["Example.com", ".example.org"]);
+        assert!(server.is_allowed_host("example.com"));
+        assert!(server.is_allowed_host("EXAMPLE.com:8080"));
+        assert!(server.is_allowed_host("example.com."));
+        assert!(server.is_allowed_host("example.org"));
+        assert!(server.is_allowed_host("www.example.org"));
+        assert!(!server.is_allowed_host("www.example.com"));
+        assert!(!server.is_allowed_host("badexample.org"));
+        assert!(!server.is_allowed_host("example.com@evil.com"));
+
+        server.set_allowed_hosts(["*"]);
+        assert!(server.is_allowed_host("anything.test"));
+    }
+}
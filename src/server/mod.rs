@@ -1,2 +1,5 @@
 mod server;
-pub use server::WebServer;
\ No newline at end of file
+mod proxy;
+
+pub use server::WebServer;
+pub use proxy::{TrustedProxies, read_proxy_header};
@@ -0,0 +1,338 @@
+use std::{
+    io::{Error, ErrorKind, Read, Result},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}
+};
+
+
+/// The signature at the start of every PROXY protocol v2 header
+const V2_SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+
+/// The longest possible PROXY protocol v1 header, including the line ending
+const V1_MAX_LENGTH: usize = 107;
+
+
+/// # Trusted proxies
+/// The addresses of the reverse proxies in front of the server, like `127.0.0.1` or `10.0.0.0/8`.
+/// Only requests coming from these addresses may tell the server the address of the real client.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>
+}
+
+
+impl TrustedProxies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust an address (`192.168.1.1`) or a network in CIDR notation (`10.0.0.0/8`, `fd00::/8`)
+    pub fn add(&mut self, proxy: &str) {
+        let (address, prefix) = match proxy.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (proxy, None)
+        };
+        let address: IpAddr = match address.trim().parse() {
+            Ok(address) => address,
+            Err(e) => panic!("Invalid trusted proxy address \"{proxy}\": {e}")
+        };
+
+        let max_prefix = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128
+        };
+        let prefix = match prefix.map(|prefix| prefix.trim().parse::<u8>()) {
+            Some(Ok(prefix)) if prefix <= max_prefix => prefix,
+            Some(_) => panic!("Invalid prefix length in trusted proxy \"{proxy}\""),
+            None => max_prefix
+        };
+
+        self.networks.push((address, prefix));
+    }
+
+    /// Test whether an address belongs to a trusted proxy
+    pub fn contains(&self, address: IpAddr) -> bool {
+        // Compare IPv4 clients of a dual-stack socket as IPv4 addresses
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+            v4 => v4
+        };
+
+        self.networks.iter().any(|(network, prefix)| match (network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(address) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(address) & mask
+            },
+            _ => false
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid PROXY protocol header: {message}"))
+}
+
+
+/// # Read a PROXY protocol header
+/// Reads the HAProxy PROXY protocol header (version 1 or 2) from the start of a connection,
+/// without reading any further, so the HTTP request can be read afterward.
+/// ## Returns
+/// This function returns an `std::io::Result<Option<SocketAddr>>`:
+/// - `Ok(Some(address))` with the address of the client, which connected to the proxy
+/// - `Ok(None)` if the proxy did not forward a client (like its own health checks)
+/// - `Err(e)` if the connection does not start with a valid header
+pub fn read_proxy_header<R: Read>(reader: &mut R) -> Result<Option<SocketAddr>> {
+    let mut start = [0u8; 5];
+    reader.read_exact(&mut start)?;
+
+    if &start == b"PROXY" {
+        read_v1(reader)
+    } else if start == V2_SIGNATURE[..5] {
+        read_v2(reader)
+    } else {
+        Err(invalid("missing signature"))
+    }
+}
+
+
+/// Read the rest of a version 1 header, like `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`
+fn read_v1<R: Read>(reader: &mut R) -> Result<Option<SocketAddr>> {
+    // Read byte by byte, so no part of the request is consumed
+    let mut line: Vec<u8> = b"PROXY".to_vec();
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("the header is too long"));
+        }
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+
+    let line = String::from_utf8(line).map_err(|_| invalid("the header is not ASCII"))?;
+    let fields: Vec<&str> = line.trim_end().split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _destination, source_port, _destination_port] => {
+            let address: IpAddr = source.parse().map_err(|_| invalid("invalid source address"))?;
+            let port: u16 = source_port.parse().map_err(|_| invalid("invalid source port"))?;
+            Ok(Some(SocketAddr::new(address, port)))
+        },
+        _ => Err(invalid("malformed version 1 header"))
+    }
+}
+
+
+/// Read the rest of a version 2 header, after the first five bytes of the signature
+fn read_v2<R: Read>(reader: &mut R) -> Result<Option<SocketAddr>> {
+    let mut rest = [0u8; 11];
+    reader.read_exact(&mut rest)?;
+    if rest[..7] != V2_SIGNATURE[5..] {
+        return Err(invalid("invalid version 2 signature"));
+    }
+
+    let (version_command, family) = (rest[7], rest[8]);
+    let length = u16::from_be_bytes([rest[9], rest[10]]) as usize;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+
+    // The addresses, followed by optional TLVs, which are skipped
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    // The LOCAL command is used for connections made by the proxy itself
+    match version_command & 0x0F {
+        0x0 => return Ok(None),
+        0x1 => (),
+        _ => return Err(invalid("unsupported command"))
+    }
+
+    match family >> 4 {
+        // IPv4: source address, destination address, source port, destination port
+        0x1 if body.len() >= 12 => {
+            let address = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let port = u16::from_be_bytes([body[8], body[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(address), port)))
+        },
+        // IPv6
+        0x2 if body.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[..16]);
+            let port = u16::from_be_bytes([body[32], body[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)))
+        },
+        // Unspecified or UNIX sockets
+        0x0 | 0x3 => Ok(None),
+        _ => Err(invalid("truncated addresses"))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::http::Request;
+    use super::*;
+
+    fn proxies(networks: &[&str]) -> TrustedProxies {
+        let mut proxies = TrustedProxies::new();
+        for network in networks {
+            proxies.add(network);
+        }
+        proxies
+    }
+
+    /// A request from the given peer with the given headers, resolved with the trusted proxies
+    fn resolve(peer: &str, headers: &str, trusted: &[&str]) -> Request {
+        let mut request = Request::from_bytes(format!("GET / HTTP/1.1\r\nHost: internal\r\n{headers}\r\n").as_bytes());
+        request.ip = Some(SocketAddr::new(peer.parse().unwrap(), 40000));
+        request.resolve_proxies(&proxies(trusted));
+        request
+    }
+
+    fn ip(address: &str) -> Option<IpAddr> {
+        Some(address.parse().unwrap())
+    }
+
+    #[test]
+    fn contains_networks() {
+        let proxies = proxies(&["10.0.0.0/8", "192.168.1.1", "fd00::/8"]);
+        assert!(proxies.contains("10.20.30.40".parse().unwrap()));
+        assert!(proxies.contains("192.168.1.1".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("11.0.0.1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.2".parse().unwrap()));
+        assert!(self::proxies(&["0.0.0.0/0"]).contains("203.0.113.9".parse().unwrap()));
+    }
+
+    #[test]
+    fn untrusted_peers_are_the_client() {
+        let request = resolve("203.0.113.9", "X-Forwarded-For: 198.51.100.1\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: example.com\r\n", &["10.0.0.0/8"]);
+        assert_eq!(request.client_ip(), ip("203.0.113.9"));
+        assert_eq!(request.scheme(), "http");
+        assert_eq!(request.host(), Some("internal"));
+    }
+
+    #[test]
+    fn follows_trusted_x_forwarded_for_hops() {
+        let headers = "X-Forwarded-For: 198.51.100.1, 203.0.113.7\r\nX-Forwarded-For: 10.0.0.2\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: example.com\r\n";
+        let request = resolve("10.0.0.1", headers, &["10.0.0.0/8"]);
+        // 203.0.113.7 is not trusted, so it is the client, even though it claims to forward 198.51.100.1
+        assert_eq!(request.client_ip(), ip("203.0.113.7"));
+        assert_eq!(request.scheme(), "https");
+        assert_eq!(request.host(), Some("example.com"));
+
+        let request = resolve("10.0.0.1", headers, &["10.0.0.0/8", "203.0.113.0/24"]);
+        assert_eq!(request.client_ip(), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn follows_forwarded_elements() {
+        let headers = "Forwarded: for=198.51.100.1;proto=https;host=example.com, for=\"[2001:db8::1]:4711\"\r\nX-Forwarded-For: 192.0.2.1\r\n";
+        let request = resolve("10.0.0.1", headers, &["10.0.0.0/8", "2001:db8::/32"]);
+        assert_eq!(request.client_ip(), ip("198.51.100.1"));
+        assert_eq!(request.scheme(), "https");
+        assert_eq!(request.host(), Some("example.com"));
+
+        // Obfuscated clients stop the search at the proxy, which saw them
+        let request = resolve("10.0.0.1", "Forwarded: for=198.51.100.1, for=_hidden, for=10.0.0.5\r\n", &["10.0.0.0/8"]);
+        assert_eq!(request.client_ip(), ip("10.0.0.5"));
+    }
+
+    #[test]
+    fn ignores_invalid_schemes() {
+        let request = resolve("10.0.0.1", "X-Forwarded-For: 198.51.100.1\r\nX-Forwarded-Proto: javascript\r\n", &["10.0.0.0/8"]);
+        assert_eq!(request.client_ip(), ip("198.51.100.1"));
+        assert_eq!(request.scheme(), "http");
+    }
+
+    #[test]
+    fn reads_version_1_headers() {
+        let mut stream: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        assert_eq!(read_proxy_header(&mut stream).unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(stream, b"GET / HTTP/1.1\r\n");
+
+        let mut stream: &[u8] = b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n";
+        assert_eq!(read_proxy_header(&mut stream).unwrap(), Some("[2001:db8::1]:56324".parse().unwrap()));
+
+        let mut stream: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_proxy_header(&mut stream).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_version_1_headers() {
+        let long = format!("PROXY TCP4 {}\r\n", "1".repeat(200));
+        for header in [
+            b"GET / HTTP/1.1\r\n".as_slice(),
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n",
+            b"PROXY TCP4 not-an-ip 198.51.100.1 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 99999 443\r\n",
+            b"PROXY TCP4 192.0.2.1",
+            long.as_bytes()
+        ] {
+            let mut stream = header;
+            assert!(read_proxy_header(&mut stream).is_err(), "{}", String::from_utf8_lossy(header));
+        }
+    }
+
+    /// A version 2 header with the given command, family and address block
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[test]
+    fn reads_version_2_headers() {
+        let ipv4 = [192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x01, 0xBB, 0x03, 0x00, 0x01, 0xFF];
+        let mut header = v2(0x1, 0x11, &ipv4);
+        header.extend_from_slice(b"GET");
+        let mut stream = header.as_slice();
+        assert_eq!(read_proxy_header(&mut stream).unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(stream, b"GET");
+
+        let mut ipv6 = [0u8; 36];
+        ipv6[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6[32..34].copy_from_slice(&443u16.to_be_bytes());
+        let header = v2(0x1, 0x21, &ipv6);
+        assert_eq!(read_proxy_header(&mut header.as_slice()).unwrap(), Some("[2001:db8::1]:443".parse().unwrap()));
+
+        // Health checks of the proxy itself
+        let header = v2(0x0, 0x00, &[]);
+        assert_eq!(read_proxy_header(&mut header.as_slice()).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_version_2_headers() {
+        let ipv4 = [192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x01, 0xBB];
+        let mut bad_signature = v2(0x1, 0x11, &ipv4);
+        bad_signature[6] = 0xFF;
+        let mut bad_version = v2(0x1, 0x11, &ipv4);
+        bad_version[12] = 0x11;
+        let mut too_short = v2(0x1, 0x11, &ipv4);
+        too_short.truncate(20);
+
+        for header in [
+            bad_signature,
+            bad_version,
+            too_short,
+            v2(0x2, 0x11, &ipv4),
+            v2(0x1, 0x11, &ipv4[..8]),
+            v2(0x1, 0x21, &ipv4)
+        ] {
+            assert!(read_proxy_header(&mut header.as_slice()).is_err(), "{header:?}");
+        }
+    }
+}
@@ -11,7 +11,9 @@ use std::fmt::Debug;
 use std::path::Path;
 use crate::{
     http::{Request, Response, Status},
-    html::render
+    server::{read_proxy_header, TrustedProxies},
+    html::{render, render_request},
+    middleware::Middleware
 };
 use regex::Regex;
 use minijinja as jinja;
@@ -32,11 +34,27 @@ pub struct WebServer {
 
     /* MAP URLs TO FUNCTIONS */
     url_map: Vec<(Regex, Box<dyn Fn(&Self, &Request) -> Response>)>,
+
+    /* MIDDLEWARE */
+    middleware: Vec<Box<dyn Middleware>>,
+
+    /* SECURITY */
+    allowed_hosts: Vec<String>,
+    trusted_proxies: TrustedProxies,
+    proxy_protocol: bool,
+    max_body_size: usize,
     
     /* ERROR FUNCTIONS */
     server_error: Box<dyn Fn(&Request) -> Response>,
     not_found_error: Box<dyn Fn(&Request) -> Response>,
+    forbidden_error: Box<dyn Fn(&Request) -> Response>,
+    unauthorized_error: Box<dyn Fn(&Request) -> Response>,
+    bad_request_error: Box<dyn Fn(&Request) -> Response>,
+    too_many_requests_error: Box<dyn Fn(&Request) -> Response>,
     
+    /* AUTHENTICATION */
+    login_url: String,
+
     /* TEMPLATE RENDERING */
     environment: jinja::Environment<'static>,
     
@@ -84,6 +102,94 @@ impl WebServer {
         )
     }
     
+    /// # Add middleware
+    /// Adds a middleware to the server, which will be run for every request.
+    /// Middleware runs in the order it was added before the request is routed,
+    /// and in the reverse order after the response was produced.
+    /// ## Parameters
+    /// - `middleware: impl Middleware` -> The middleware
+    /// ## Example
+    /// ```rust
+    /// use aerielle::{WebServer, middleware::Compression};
+    ///
+    /// fn main() {
+    ///     let mut server = WebServer::new();
+    ///     server.add_middleware(Compression::new());
+    ///     server.start().unwrap();
+    /// }
+    /// ```
+    pub fn add_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Box::new(middleware))
+    }
+
+    /// # Set the allowed hosts
+    /// Restricts the values of the `Host` header, which the server responds to.
+    /// Requests for other hosts are rejected with `400 Bad Request`, which prevents
+    /// HTTP `Host` header attacks (like poisoned password reset links).
+    /// By default, every host is allowed.
+    /// ## Parameters
+    /// - `hosts: I` -> The allowed hosts, where each host is:
+    ///     - a domain or an IP address, like `example.com` or `127.0.0.1`, matched exactly
+    ///     - a domain starting with a dot, like `.example.com`, matching the domain and every subdomain
+    ///     - `*`, matching every host
+    /// ## Example
+    /// ```rust
+    /// server.set_allowed_hosts(["example.com", ".example.com", "localhost"]);
+    /// ```
+    pub fn set_allowed_hosts<I, S>(&mut self, hosts: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString
+    {
+        self.allowed_hosts = hosts
+            .into_iter()
+            .map(|host| host.to_string().to_lowercase())
+            .collect();
+    }
+
+    /// # Set the trusted proxies
+    /// Tells the server which reverse proxies (like nginx) are in front of it.
+    /// For requests coming from these addresses, the real client IP address, scheme and host
+    /// are taken from the `Forwarded` or `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` headers,
+    /// and are available through `request.client_ip()`, `request.scheme()` and `request.host()`.
+    /// ## Parameters
+    /// - `proxies: I` -> The addresses (`127.0.0.1`) or networks (`10.0.0.0/8`) of the proxies
+    /// ## Panicking
+    /// This method panics, if an address can not be parsed.
+    /// ## Example
+    /// ```rust
+    /// server.set_trusted_proxies(["127.0.0.1", "::1", "10.0.0.0/8"]);
+    /// ```
+    pub fn set_trusted_proxies<I, S>(&mut self, proxies: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>
+    {
+        let mut trusted = TrustedProxies::new();
+        for proxy in proxies {
+            trusted.add(proxy.as_ref());
+        }
+        self.trusted_proxies = trusted;
+    }
+
+    /// Expect the HAProxy PROXY protocol (version 1 or 2) on connections from the trusted proxies,
+    /// which tells the server the address of the client, even for a TCP load balancer
+    pub fn set_proxy_protocol(&mut self, enabled: bool) {
+        self.proxy_protocol = enabled;
+    }
+
+    /// # Set the maximum body size
+    /// Limits the size of request bodies in bytes, which defaults to 100 MB.
+    /// Requests with a larger `Content-Length` are rejected with `413 Payload Too Large`,
+    /// before any of the body is read.
+    /// ## Example
+    /// ```rust
+    /// server.set_max_body_size(10 * 1024 * 1024);  // 10 MB
+    /// ```
+    pub fn set_max_body_size(&mut self, bytes: usize) {
+        self.max_body_size = bytes;
+    }
+
     pub fn read_in_templates(&mut self) {
         let templates = Self::list_items_in_dir(self.templates.as_path());
         let templates_dir_name = Self::dir_name(self.templates.as_path());
@@ -214,6 +320,11 @@ impl WebServer {
         self.get_environment().get_template(name)
     }
     
+    /// Set the URL, to which `login_required` redirects anonymous users
+    pub fn set_login_url(&mut self, url: impl ToString) {
+        self.login_url = url.to_string();
+    }
+
     pub fn set_server_error(&mut self, function: impl Fn(&Request) -> Response + 'static) {
         self.server_error = Box::new(function);
     }
@@ -222,6 +333,26 @@ impl WebServer {
         self.not_found_error = Box::new(function);
     }
 
+    pub fn set_bad_request_error(&mut self, function: impl Fn(&Request) -> Response + 'static) {
+        self.bad_request_error = Box::new(function);
+    }
+
+    /// Set the function, which responds to requests over the limit of the `RateLimiter`
+    pub fn set_too_many_requests_error(&mut self, function: impl Fn(&Request) -> Response + 'static) {
+        self.too_many_requests_error = Box::new(function);
+    }
+
+    /// Set the function, which responds to requests without the required permissions
+    pub fn set_forbidden_error(&mut self, function: impl Fn(&Request) -> Response + 'static) {
+        self.forbidden_error = Box::new(function);
+    }
+
+    /// Set the function, which responds to requests without valid credentials.
+    /// The authentication guards add the `WWW-Authenticate` challenge to its response.
+    pub fn set_unauthorized_error(&mut self, function: impl Fn(&Request) -> Response + 'static) {
+        self.unauthorized_error = Box::new(function);
+    }
+
     #[cfg(feature = "_db_must")]
     pub fn connect_to_database(&mut self, database: Database) {
         self.database = Some(database)
@@ -260,41 +391,87 @@ impl WebServer {
         self.static_dir != None && self.static_url != None
     }
     
+    pub fn get_login_url(&self) -> &str {
+        &self.login_url
+    }
+
     pub fn get_environment(&self) -> &jinja::Environment<'static> {
         &self.environment
     }
 
+    pub fn get_trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
+    }
+
+    pub fn get_allowed_hosts(&self) -> &[String] {
+        &self.allowed_hosts
+    }
+
+    /// Test whether the server responds to a host, ignoring its port
+    pub fn is_allowed_host(&self, host: &str) -> bool {
+        let Some(domain) = Self::split_host(host) else { return false };
+        let domain = domain.trim_end_matches('.');
+
+        self.allowed_hosts.iter().any(|pattern| match pattern.as_str() {
+            "*" => true,
+            pattern if pattern.starts_with('.') => {
+                domain == &pattern[1..] || domain.ends_with(pattern)
+            },
+            pattern => domain == pattern
+        })
+    }
+
+    pub fn get_middleware(&self) -> &[Box<dyn Middleware>] {
+        &self.middleware
+    }
+
     #[cfg(feature = "_db_must")]
     pub fn get_database(&self) -> &Database {
         if let Some(db) = &self.database { db }
         else { panic!("Tried to access database, but no database was set.") }
     }
 
+    /// # Get the path of a static file
+    /// Translates a URL on the static URL into the path of the file inside the static directory.
+    /// ## Parameters
+    /// - `url: &str` -> The URL of the request
+    /// ## Returns
+    /// This method returns an `Option<PathBuf>`:
+    /// - `Some(path)` if the URL points to a static file
+    /// - `None` if static files are disabled, or the URL does not start with the static URL
+    pub fn static_path(&self, url: &str) -> Option<PathBuf> {
+        let static_url = self.static_url.as_ref()?;
+        let static_dir = self.static_dir.as_ref()?;
+
+        if !url.starts_with(static_url.as_str()) {
+            return None;
+        }
+
+        // Join the segments of the URL onto the static directory
+        let mut path: PathBuf = static_dir.clone();
+        for segment in url[static_url.len()..].split('/') {
+            match segment {
+                "" | "." => continue,
+                // Never leave the static directory
+                ".." => return None,
+                _ => path.push(segment)
+            }
+        }
+
+        Some(path)
+    }
+
     /* HANDLE REQUESTS */
     fn serve_static(&self, request: &Request, url: &str) -> Response {
-        if let Some(static_url) = &self.static_url {
-            // Trim the string
-            let mut trimmed: String = url
-                .trim_start_matches(static_url)
-                .replace('/', "\\");
-            
-            if trimmed.starts_with('/') {
-                trimmed = trimmed[1..].to_string();
-            }
-            
-            // Join the path
-            if let Some(static_path) = &self.static_dir {
-                let path: PathBuf = static_path.join(&trimmed);
-                
-                // Read in the file
-                let response: Response = Response::read_in(path);
-                return if response.status == Status::InternalServerError {
-                    (self.server_error)(request)
-                } else if response.status == Status::NotFound {
-                    (self.not_found_error)(request)
-                } else {
-                    response
-                }
+        if let Some(path) = self.static_path(url) {
+            // Read in the file
+            let response: Response = Response::read_in(path);
+            return if response.status == Status::InternalServerError {
+                (self.server_error)(request)
+            } else if response.status == Status::NotFound {
+                (self.not_found_error)(request)
+            } else {
+                response
             }
         }
         panic!("Static files are disabled, but a static file is expected: {url}");
@@ -338,9 +515,107 @@ impl WebServer {
         render(self, template, context)
     }
 
-    fn handle(&self, request: &Request) -> Response {
-        // Get the URL of the request
-        let url: &str = request.url.as_str();
+    /// # Render an HTML template for a request
+    /// This method renders an HTML template like `render`,
+    /// but it also passes the variables provided by the middleware of the server to the template,
+    /// like the logged in `user` of the `AuthMiddleware`.
+    /// If a variable is defined both by the middleware and the given context, the given context is used.
+    /// ## Parameters
+    /// - `request: &Request` -> The request, which is being responded to
+    /// - `template: &str` -> The name of the template
+    /// - `context: jinja::Value` -> The context, which will be passed to the template
+    /// ## Returns
+    /// This method returns a `Response`, just like `render`.
+    /// ## Example
+    /// ```rust
+    /// fn profile(server: &WebServer, request: &Request) -> Response {
+    ///     server.render_request(request, "profile.html", context!(title => "Profile"))
+    /// }
+    /// ```
+    pub fn render_request(&self, request: &Request, template: &str, context: jinja::Value) -> Response {
+        render_request(self, request, template, context)
+    }
+
+    /// Respond to a request without the required permissions
+    pub fn forbidden_error(&self, request: &Request) -> Response {
+        (self.forbidden_error)(request)
+    }
+
+    /// Respond to a request without valid credentials
+    pub fn unauthorized_error(&self, request: &Request) -> Response {
+        (self.unauthorized_error)(request)
+    }
+
+    /// Respond to a request over the rate limit
+    pub fn too_many_requests_error(&self, request: &Request) -> Response {
+        (self.too_many_requests_error)(request)
+    }
+
+    /// Run the request through the middleware and the router
+    fn handle(&self, request: &mut Request) -> Response {
+        // Find the real client behind the trusted proxies
+        request.resolve_proxies(&self.trusted_proxies);
+
+        // Reject requests for hosts the server does not respond to
+        if !self.validate_host(request) {
+            return (self.bad_request_error)(request);
+        }
+
+        // Reject bodies which were not read, because they were too large or incomplete
+        match request.body_error() {
+            Some(Status::PayloadTooLarge) => return WebServer::payload_too_large(request),
+            Some(_) => return (self.bad_request_error)(request),
+            None => ()
+        }
+
+        // Run the middleware before routing, stopping at the first one which responds
+        let mut response: Option<Response> = None;
+        let mut ran: usize = 0;
+        for middleware in self.middleware.iter() {
+            ran += 1;
+            if let Some(early) = middleware.before(self, request) {
+                response = Some(early);
+                break;
+            }
+        }
+
+        // Route the request
+        let mut response: Response = match response {
+            Some(response) => response,
+            None => self.route(request)
+        };
+
+        // Run the middleware after routing, in the reverse order
+        for middleware in self.middleware[..ran].iter().rev() {
+            middleware.after(self, request, &mut response);
+        }
+
+        response
+    }
+
+    /// Test the `Host` header of the request, which is required by HTTP/1.1
+    fn validate_host(&self, request: &Request) -> bool {
+        match request.host() {
+            Some(host) => {
+                let valid = self.is_allowed_host(host);
+                if !valid {
+                    eprintln!("Invalid HTTP_HOST header: \"{host}\"");
+                }
+                valid
+            },
+            None => {
+                let valid = request.version != "1.1";
+                if !valid {
+                    eprintln!("Missing Host header on an HTTP/1.1 request");
+                }
+                valid
+            }
+        }
+    }
+
+    fn route(&self, request: &Request) -> Response {
+        // Get the decoded path of the request, without the query string
+        let url: &str = request.path.as_str();
 
         if let Some(static_url) = &self.static_url {
             if url.starts_with(static_url) {
@@ -362,7 +637,7 @@ impl WebServer {
                 } else if response.status == Status::NotFound {
                     (self.not_found_error)(request)
                 } else {
-                    function(&self, request)
+                    response
                 }
             }
         }
@@ -402,14 +677,34 @@ impl WebServer {
         
         // Listen to incoming requests
         for stream in listener.incoming() {
-            let stream = stream?;
+            let mut stream = stream?;
+
+            // Read the address of the client from the PROXY protocol header of trusted proxies
+            let mut proxied_address = None;
+            if self.proxy_protocol {
+                let trusted = stream
+                    .peer_addr()
+                    .is_ok_and(|address| self.trusted_proxies.contains(address.ip()));
+                if trusted {
+                    match read_proxy_header(&mut stream) {
+                        Ok(address) => proxied_address = address,
+                        Err(e) => {
+                            eprintln!("Dropping connection: {e}");
+                            continue;
+                        }
+                    }
+                }
+            }
 
             // Interpret the request
-            let mut request: Request = Request::from(stream);
+            let mut request: Request = Request::read(stream, self.max_body_size);
+            if proxied_address.is_some() {
+                request.ip = proxied_address;
+            }
             println!("{request}");
 
             // Handle the request
-            let response: Response = self.handle(&request);
+            let response: Response = self.handle(&mut request);
 
             // Write the response
             request.respond(response)?;
@@ -432,6 +727,41 @@ impl WebServer {
             String::from("<h1>404 Not Found</h1>")
         )
     }
+
+    pub fn bad_request(_: &Request) -> Response {
+        Response::new(
+            Status::BadRequest,
+            String::from("<h1>400 Bad Request</h1>")
+        )
+    }
+
+    pub fn forbidden(_: &Request) -> Response {
+        Response::new(
+            Status::Forbidden,
+            String::from("<h1>403 Forbidden</h1>")
+        )
+    }
+    
+    pub fn unauthorized(_: &Request) -> Response {
+        Response::new(
+            Status::Unauthorized,
+            String::from("<h1>401 Unauthorized</h1>")
+        )
+    }
+
+    pub fn too_many_requests(_: &Request) -> Response {
+        Response::new(
+            Status::TooManyRequests,
+            String::from("<h1>429 Too Many Requests</h1>")
+        )
+    }
+
+    pub fn payload_too_large(_: &Request) -> Response {
+        Response::new(
+            Status::PayloadTooLarge,
+            String::from("<h1>413 Payload Too Large</h1>")
+        )
+    }
     
     /* UTILITY FUNCTIONS */
     /// # Get local IP address
@@ -556,6 +886,39 @@ impl WebServer {
         files
     }
 
+    /// Split the port off a `Host` header, returning the lowercase domain,
+    /// or `None` if the header is malformed
+    fn split_host(host: &str) -> Option<String> {
+        let host = host.trim().to_lowercase();
+
+        // IPv6 addresses are wrapped in brackets, like [::1]:8000
+        let (domain, port) = match host.starts_with('[') {
+            true => {
+                let end = host.find(']')?;
+                (&host[..=end], host[end + 1..].strip_prefix(':'))
+            },
+            false => match host.rsplit_once(':') {
+                Some((domain, port)) => (domain, Some(port)),
+                None => (host.as_str(), None)
+            }
+        };
+
+        let valid_domain = match domain.starts_with('[') {
+            true => domain[1..domain.len() - 1].chars().all(|c| c.is_ascii_hexdigit() || c == ':' || c == '.'),
+            false => !domain.is_empty() && domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        };
+        let valid_port = port.is_none_or(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()));
+        if !valid_domain || !valid_port {
+            return None;
+        }
+        // Anything after the closing bracket, which is not a port, is invalid
+        if domain.starts_with('[') && port.is_none() && !host.ends_with(']') {
+            return None;
+        }
+
+        Some(domain.to_string())
+    }
+
     /// Get the directory name of the given directory
     fn dir_name<P>(dir: P) -> String
     where
@@ -580,16 +943,32 @@ impl WebServer {
 
 impl Default for WebServer {
     fn default() -> Self {
+        let mut environment = jinja::Environment::new();
+        environment.add_function("csrf_token", crate::middleware::csrf_token_function);
+        environment.add_function("csrf_input", crate::middleware::csrf_input_function);
+        #[cfg(feature = "_db_must")]
+        environment.add_function("has_perm", crate::auth::has_perm_function);
+
         WebServer {
             ip: "localhost".to_string(),
             port: 8000,
             static_url: Some("/static".to_string()),
             static_dir: Some(PathBuf::from("static")),
             url_map: Vec::new(),
+            middleware: Vec::new(),
+            allowed_hosts: vec![String::from("*")],
+            trusted_proxies: TrustedProxies::new(),
+            proxy_protocol: false,
+            max_body_size: crate::http::DEFAULT_MAX_BODY_SIZE,
             server_error: Box::new(WebServer::server_error),
             not_found_error: Box::new(WebServer::not_found),
+            forbidden_error: Box::new(WebServer::forbidden),
+            unauthorized_error: Box::new(WebServer::unauthorized),
+            bad_request_error: Box::new(WebServer::bad_request),
+            too_many_requests_error: Box::new(WebServer::too_many_requests),
             templates: current_dir().unwrap().parent().unwrap().join("templates"),
-            environment: jinja::Environment::new(),
+            login_url: "/accounts/login/".to_string(),
+            environment,
             #[cfg(feature = "_db_must")]
             database: None
         }
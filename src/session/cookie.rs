@@ -0,0 +1,133 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use crate::{
+    server::WebServer,
+    session::{Session, SessionValues}
+};
+
+
+/// The contents of a session cookie
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    /// Expiry, in seconds since the UNIX epoch
+    expires: u64,
+    values: SessionValues
+}
+
+
+/// # Signed cookie sessions
+/// Keeps the whole session inside the session cookie, signed with HMAC-SHA256.
+/// The client can read the values of the session, but can not change them.
+/// Do not store secrets in these sessions, and keep them small (browsers limit cookies to 4 KB).
+pub struct CookieSession {
+    secret: Vec<u8>
+}
+
+
+impl CookieSession {
+    /// Create the backend with the secret key used for signing.
+    /// Anyone who knows the secret key can forge sessions, so keep it out of the source code.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self { secret: secret.as_ref().to_vec() }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size")
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+
+impl Session for CookieSession {
+    fn load(&self, _server: &WebServer, key: &str) -> Option<SessionValues> {
+        let (payload, signature) = key.rsplit_once('.')?;
+
+        // Verify the signature
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+
+        // Decode the payload
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let payload: Payload = serde_json::from_slice(&payload).ok()?;
+        match payload.expires > Self::now() {
+            true => Some(payload.values),
+            false => None
+        }
+    }
+
+    fn save(&self, _server: &WebServer, _key: &str, values: &SessionValues, max_age: Duration) -> String {
+        let payload = Payload {
+            expires: Self::now() + max_age.as_secs(),
+            values: values.clone()
+        };
+        let payload = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&payload).expect("Session values are always serializable")
+        );
+
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        format!("{payload}.{signature}")
+    }
+
+    fn delete(&self, _server: &WebServer, _key: &str) {
+        // The session only lives in the cookie, which is removed by the middleware
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn values() -> SessionValues {
+        SessionValues::from([(String::from("user"), json!(1))])
+    }
+
+    #[test]
+    fn signed_values_are_loaded() {
+        let server = WebServer::new();
+        let store = CookieSession::new("secret");
+        let cookie = store.save(&server, "", &values(), Duration::from_secs(60));
+        assert_eq!(store.load(&server, &cookie), Some(values()));
+
+        // Another secret can not verify the signature
+        assert_eq!(CookieSession::new("other").load(&server, &cookie), None);
+    }
+
+    #[test]
+    fn tampered_cookies_are_rejected() {
+        let server = WebServer::new();
+        let store = CookieSession::new("secret");
+        let cookie = store.save(&server, "", &values(), Duration::from_secs(60));
+        let (_, signature) = cookie.rsplit_once('.').unwrap();
+
+        let forged = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&json!({"expires": u64::MAX, "values": {"user": 2}})).unwrap()
+        );
+        assert_eq!(store.load(&server, &format!("{forged}.{signature}")), None);
+        assert_eq!(store.load(&server, &cookie.replace('.', "")), None);
+        assert_eq!(store.load(&server, "garbage"), None);
+    }
+
+    #[test]
+    fn expired_cookies_are_rejected() {
+        let server = WebServer::new();
+        let store = CookieSession::new("secret");
+        let cookie = store.save(&server, "", &values(), Duration::ZERO);
+        assert_eq!(store.load(&server, &cookie), None);
+    }
+}
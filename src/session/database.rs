@@ -0,0 +1,197 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+use table_macro::table;
+use crate::{
+    server::WebServer,
+    session::{clear_due, Session, SessionValues},
+    sql
+};
+
+
+/// The table, in which the `DatabaseSession` backend stores the sessions.
+/// Add it to the database before using the backend:
+/// ```rust
+/// database.add_table::<SessionTable>();
+/// ```
+#[table(name = "aerielle_session")]
+pub struct SessionTable {
+    #[primary_key]
+    #[not_null]
+    session_key: Text,
+
+    #[not_null]
+    session_data: Text,
+
+    // Seconds since the UNIX epoch
+    #[not_null]
+    expire_date: Float
+}
+
+
+/// # Database sessions
+/// Keeps the sessions in the `aerielle_session` table of the database connected to the server.
+/// Expired sessions are deleted every few minutes, while a session is saved.
+pub struct DatabaseSession {
+    last_cleared: Mutex<Instant>
+}
+
+
+impl DatabaseSession {
+    pub fn new() -> Self {
+        Self { last_cleared: Mutex::new(Instant::now()) }
+    }
+
+    fn now() -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+
+    /// Remove every expired session from the table
+    pub fn clear_expired(&self, server: &WebServer) {
+        let database = server.get_database();
+        let query = format!(
+            "DELETE FROM {table} WHERE expire_date < {p1}",
+            table=SessionTable::table_name(),
+            p1=database.placeholder(1)
+        );
+        let now = Self::now();
+
+        if let Err(e) = database.run(move |pool| async move {
+            sqlx::query(&query).bind(now).execute(&pool).await
+        }) {
+            eprintln!("Unable to clear expired sessions: {e}");
+        }
+    }
+}
+
+
+impl Default for DatabaseSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Session for DatabaseSession {
+    fn load(&self, server: &WebServer, key: &str) -> Option<SessionValues> {
+        let database = server.get_database();
+        let query = format!(
+            "SELECT * FROM {table} WHERE session_key = {p1}",
+            table=SessionTable::table_name(),
+            p1=database.placeholder(1)
+        );
+        let key = key.to_string();
+
+        let row = database.run(move |pool| async move {
+            sqlx::query_as::<_, SessionTable>(&query)
+                .bind(key)
+                .fetch_optional(&pool)
+                .await
+        });
+
+        match row {
+            Ok(Some(row)) if row.expire_date > Self::now() => {
+                serde_json::from_str(&row.session_data).ok()
+            },
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("Unable to load session: {e}");
+                None
+            }
+        }
+    }
+
+    fn save(&self, server: &WebServer, key: &str, values: &SessionValues, max_age: Duration) -> String {
+        if clear_due(&self.last_cleared) {
+            self.clear_expired(server);
+        }
+
+        let database = server.get_database();
+        let delete = format!(
+            "DELETE FROM {table} WHERE session_key = {p1}",
+            table=SessionTable::table_name(),
+            p1=database.placeholder(1)
+        );
+        let insert = format!(
+            "INSERT INTO {table} (session_key, session_data, expire_date) VALUES ({p1}, {p2}, {p3})",
+            table=SessionTable::table_name(),
+            p1=database.placeholder(1),
+            p2=database.placeholder(2),
+            p3=database.placeholder(3)
+        );
+        let data = serde_json::to_string(values).expect("Session values are always serializable");
+        let expires = Self::now() + max_age.as_secs_f64();
+        let session_key = key.to_string();
+
+        if let Err(e) = database.run(move |pool| async move {
+            let mut transaction = pool.begin().await?;
+            sqlx::query(&delete)
+                .bind(&session_key)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query(&insert)
+                .bind(&session_key)
+                .bind(data)
+                .bind(expires)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await
+        }) {
+            eprintln!("Unable to save session: {e}");
+        }
+
+        key.to_string()
+    }
+
+    fn delete(&self, server: &WebServer, key: &str) {
+        let database = server.get_database();
+        let query = format!(
+            "DELETE FROM {table} WHERE session_key = {p1}",
+            table=SessionTable::table_name(),
+            p1=database.placeholder(1)
+        );
+        let key = key.to_string();
+
+        if let Err(e) = database.run(move |pool| async move {
+            sqlx::query(&query).bind(key).execute(&pool).await
+        }) {
+            eprintln!("Unable to delete session: {e}");
+        }
+    }
+}
+
+
+#[cfg(all(test, feature = "database_sqlite"))]
+mod tests {
+    use serde_json::json;
+    use crate::sql::tests::database;
+    use super::*;
+
+    #[test]
+    fn sessions_are_stored_until_they_expire() {
+        let (mut db, _dir) = database();
+        db.add_table::<SessionTable>();
+        let mut server = WebServer::new();
+        server.connect_to_database(db);
+
+        let store = DatabaseSession::new();
+        let values = SessionValues::from([(String::from("data"), json!("x".repeat(1000)))]);
+        store.save(&server, "live", &values, Duration::from_secs(60));
+        store.save(&server, "expired", &values, Duration::ZERO);
+        assert_eq!(store.load(&server, "live"), Some(values.clone()));
+        assert_eq!(store.load(&server, "expired"), None);
+        assert_eq!(server.get_database().filter_table::<SessionTable>().count().unwrap(), 2);
+
+        // Once the interval passed, the next save deletes the expired session
+        *store.last_cleared.lock().unwrap() -= crate::session::CLEAR_INTERVAL;
+        store.save(&server, "live", &values, Duration::from_secs(60));
+        assert_eq!(server.get_database().filter_table::<SessionTable>().count().unwrap(), 1);
+
+        store.delete(&server, "live");
+        assert_eq!(store.load(&server, "live"), None);
+    }
+}
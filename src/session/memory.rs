@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+use crate::{
+    server::WebServer,
+    session::{clear_due, Session, SessionValues}
+};
+
+
+/// # In-memory sessions
+/// Keeps the sessions in the memory of the server, until they expire.
+/// Expired sessions are removed every few minutes, while a session is saved.
+/// Every session is lost when the server is restarted.
+pub struct MemorySession {
+    sessions: Mutex<HashMap<String, (SessionValues, Instant)>>,
+    last_cleared: Mutex<Instant>
+}
+
+
+impl MemorySession {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            last_cleared: Mutex::new(Instant::now())
+        }
+    }
+
+    /// Remove every expired session
+    pub fn clear_expired(&self) {
+        let now = Instant::now();
+        self.sessions
+            .lock()
+            .expect("Session store was poisoned")
+            .retain(|_, (_, expires)| *expires > now);
+    }
+}
+
+
+impl Default for MemorySession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Session for MemorySession {
+    fn load(&self, _server: &WebServer, key: &str) -> Option<SessionValues> {
+        let mut sessions = self.sessions.lock().expect("Session store was poisoned");
+        match sessions.get(key) {
+            Some((values, expires)) if *expires > Instant::now() => Some(values.clone()),
+            Some(_) => {
+                sessions.remove(key);
+                None
+            },
+            None => None
+        }
+    }
+
+    fn save(&self, _server: &WebServer, key: &str, values: &SessionValues, max_age: Duration) -> String {
+        if clear_due(&self.last_cleared) {
+            self.clear_expired();
+        }
+        self.sessions
+            .lock()
+            .expect("Session store was poisoned")
+            .insert(key.to_string(), (values.clone(), Instant::now() + max_age));
+        key.to_string()
+    }
+
+    fn delete(&self, _server: &WebServer, key: &str) {
+        self.sessions
+            .lock()
+            .expect("Session store was poisoned")
+            .remove(key);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn sessions_expire() {
+        let server = WebServer::new();
+        let store = MemorySession::new();
+        let values = SessionValues::from([(String::from("user"), json!(1))]);
+
+        store.save(&server, "live", &values, Duration::from_secs(60));
+        store.save(&server, "expired", &values, Duration::ZERO);
+        assert_eq!(store.load(&server, "live"), Some(values.clone()));
+        assert_eq!(store.load(&server, "expired"), None);
+
+        store.delete(&server, "live");
+        assert_eq!(store.load(&server, "live"), None);
+    }
+
+    #[test]
+    fn expired_sessions_are_cleared_while_saving() {
+        let server = WebServer::new();
+        let store = MemorySession::new();
+        let values = SessionValues::new();
+
+        store.save(&server, "a", &values, Duration::ZERO);
+        store.save(&server, "b", &values, Duration::ZERO);
+        assert_eq!(store.sessions.lock().unwrap().len(), 2);
+
+        // Once the interval passed, the next save removes them
+        *store.last_cleared.lock().unwrap() -= crate::session::CLEAR_INTERVAL;
+        store.save(&server, "c", &values, Duration::from_secs(60));
+        let sessions = store.sessions.lock().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions.contains_key("c"));
+    }
+}
@@ -0,0 +1,390 @@
+mod cookie;
+mod memory;
+#[cfg(feature = "_db_must")]
+mod database;
+
+pub use cookie::CookieSession;
+pub use memory::MemorySession;
+#[cfg(feature = "_db_must")]
+pub use database::{DatabaseSession, SessionTable};
+
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant}
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use crate::{
+    crypto::random_string,
+    http::{Cookie, Request, Response, SameSite},
+    middleware::Middleware,
+    server::WebServer
+};
+
+
+/// The values stored in a session
+pub type SessionValues = HashMap<String, Value>;
+
+
+/// # Session backend
+/// Stores the data of sessions between requests.
+/// The session cookie of the client holds a key, which the backend uses to find the data.
+/// Aerielle comes with three backends:
+/// - `CookieSession` -> keeps the data inside a signed cookie
+/// - `MemorySession` -> keeps the data in memory, until it expires
+/// - `DatabaseSession` -> keeps the data in the database connected to the server
+pub trait Session {
+    /// Load the values of a session, returning `None` if it does not exist or has expired
+    fn load(&self, server: &WebServer, key: &str) -> Option<SessionValues>;
+
+    /// Save the values of a session for `max_age`, returning the value of the session cookie
+    fn save(&self, server: &WebServer, key: &str, values: &SessionValues, max_age: Duration) -> String;
+
+    /// Delete a session
+    fn delete(&self, server: &WebServer, key: &str);
+}
+
+
+/// Generate a random session key
+pub fn generate_key() -> String {
+    random_string(32)
+}
+
+
+/// How often the backends remove expired sessions, while saving a session
+const CLEAR_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+
+/// Test whether the expired sessions are due to be removed, and if so, restart the interval
+fn clear_due(last_cleared: &Mutex<Instant>) -> bool {
+    let mut last_cleared = last_cleared.lock().expect("Session store was poisoned");
+    match last_cleared.elapsed() >= CLEAR_INTERVAL {
+        true => {
+            *last_cleared = Instant::now();
+            true
+        },
+        false => false
+    }
+}
+
+
+/// # Session data
+/// The session of a single request, available through `request.session()`.
+/// Changes are saved by the `SessionMiddleware` once the response was produced.
+/// ## Example
+/// ```rust
+/// fn visit(server: &WebServer, request: &Request) -> Response {
+///     let session = request.session();
+///     let visits: u32 = session.get("visits").unwrap_or(0);
+///     session.set("visits", visits + 1);
+///
+///     Response::new(Status::OK, format!("You have visited this page {} times", visits + 1))
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct SessionData {
+    key: RefCell<Option<String>>,
+    values: RefCell<SessionValues>,
+    stale_keys: RefCell<Vec<String>>,
+    modified: Cell<bool>
+}
+
+
+impl SessionData {
+    pub fn new(key: Option<String>, values: SessionValues) -> Self {
+        Self {
+            key: RefCell::new(key),
+            values: RefCell::new(values),
+            stale_keys: RefCell::new(Vec::new()),
+            modified: Cell::new(false)
+        }
+    }
+
+    /// The key of the session, or `None` if the session was not saved yet
+    pub fn key(&self) -> Option<String> {
+        self.key.borrow().clone()
+    }
+
+    /// Get a value, returning `None` if it does not exist or has a different type
+    pub fn get<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let value = self.values.borrow().get(name)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Set a value
+    pub fn set<T: Serialize>(&self, name: &str, value: T) {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.values.borrow_mut().insert(name.to_string(), value);
+                self.modified.set(true);
+            },
+            Err(e) => eprintln!("Unable to store \"{name}\" in the session: {e}")
+        }
+    }
+
+    /// Remove a value, returning whether it existed
+    pub fn remove(&self, name: &str) -> bool {
+        let removed = self.values.borrow_mut().remove(name).is_some();
+        if removed {
+            self.modified.set(true);
+        }
+        removed
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.borrow().contains_key(name)
+    }
+
+    /// Every value of the session
+    pub fn values(&self) -> Ref<'_, SessionValues> {
+        self.values.borrow()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.borrow().is_empty()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified.get()
+    }
+
+    /// Give the session a new key, keeping its values.
+    /// This should be done whenever the privileges of the client change (like logging in),
+    /// so a session key known by an attacker before can not be used afterward.
+    pub fn cycle_key(&self) {
+        if let Some(old) = self.key.borrow_mut().take() {
+            self.stale_keys.borrow_mut().push(old);
+        }
+        self.modified.set(true);
+    }
+
+    /// Remove every value and delete the session (like logging out)
+    pub fn flush(&self) {
+        self.values.borrow_mut().clear();
+        self.cycle_key();
+    }
+
+    /// Mark the session as modified, so it is saved even if only a nested value changed
+    pub fn mark_modified(&self) {
+        self.modified.set(true)
+    }
+}
+
+
+/// # Session middleware
+/// Loads the session of every request from a `Session` backend,
+/// and saves it after the response was produced, if it was modified.
+/// ## Example
+/// ```rust
+/// use std::time::Duration;
+/// use aerielle::{WebServer, session::{SessionMiddleware, MemorySession}};
+///
+/// fn main() {
+///     let mut server = WebServer::new();
+///     server.add_middleware(SessionMiddleware::new(MemorySession::new()));
+///     server.start().unwrap();
+/// }
+/// ```
+pub struct SessionMiddleware {
+    backend: Box<dyn Session>,
+    cookie_name: String,
+    max_age: Duration,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: SameSite
+}
+
+
+impl SessionMiddleware {
+    pub fn new(backend: impl Session + 'static) -> Self {
+        Self {
+            backend: Box::new(backend),
+            cookie_name: String::from("sessionid"),
+            max_age: Duration::from_secs(60 * 60 * 24 * 14),  // Two weeks
+            path: String::from("/"),
+            domain: None,
+            secure: false,
+            http_only: true,
+            same_site: SameSite::Lax
+        }
+    }
+
+    /* BUILDER */
+    pub fn cookie_name(mut self, name: impl ToString) -> Self {
+        self.cookie_name = name.to_string();
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn path(mut self, path: impl ToString) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    pub fn domain(mut self, domain: impl ToString) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Build the session cookie with the configured attributes
+    fn cookie(&self, value: String, max_age: Duration) -> Cookie {
+        let mut cookie = Cookie::new(&self.cookie_name, value)
+            .path(&self.path)
+            .max_age(max_age)
+            .secure(self.secure)
+            .http_only(self.http_only)
+            .same_site(self.same_site);
+        if let Some(domain) = &self.domain {
+            cookie = cookie.domain(domain);
+        }
+        cookie
+    }
+}
+
+
+impl Middleware for SessionMiddleware {
+    fn before(&self, server: &WebServer, request: &mut Request) -> Option<Response> {
+        let session = match request.cookie(&self.cookie_name) {
+            Some(key) => match self.backend.load(server, &key) {
+                Some(values) => SessionData::new(Some(key), values),
+                None => SessionData::default()
+            },
+            None => SessionData::default()
+        };
+        request.session = Some(session);
+        None
+    }
+
+    fn after(&self, server: &WebServer, request: &Request, response: &mut Response) {
+        let Some(session) = &request.session else { return };
+
+        // Delete the sessions, whose keys were rotated
+        for key in session.stale_keys.borrow().iter() {
+            self.backend.delete(server, key);
+        }
+
+        if !session.is_modified() {
+            return;
+        }
+
+        // An emptied session is deleted instead of saved
+        if session.is_empty() {
+            if let Some(key) = session.key() {
+                self.backend.delete(server, &key);
+            }
+            if request.cookie(&self.cookie_name).is_some() {
+                response.set_cookie(self.cookie(String::new(), Duration::ZERO));
+            }
+            return;
+        }
+
+        let key = match session.key() {
+            Some(key) => key,
+            None => generate_key()
+        };
+        let value = self.backend.save(server, &key, &session.values(), self.max_age);
+        response.set_cookie(self.cookie(value, self.max_age));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::http::Status;
+    use super::*;
+
+    /// Run a request with the given session cookie through the middleware and a handler,
+    /// returning the new value of the session cookie (empty when it was removed)
+    fn run(
+        server: &WebServer,
+        middleware: &SessionMiddleware,
+        cookie: Option<&str>,
+        handler: impl Fn(&SessionData)
+    ) -> Option<String> {
+        let cookie = match cookie {
+            Some(cookie) => format!("Cookie: sessionid={cookie}\r\n"),
+            None => String::new()
+        };
+        let mut request = Request::from_bytes(format!("GET / HTTP/1.1\r\nHost: localhost\r\n{cookie}\r\n").as_bytes());
+
+        assert!(middleware.before(server, &mut request).is_none());
+        handler(request.session());
+        let mut response = Response::new(Status::OK, "");
+        middleware.after(server, &request, &mut response);
+
+        let set_cookie = response.header("Set-Cookie")?;
+        let (value, _) = set_cookie.strip_prefix("sessionid=")?.split_once(';')?;
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn saves_and_loads_values() {
+        let server = WebServer::new();
+        let middleware = SessionMiddleware::new(MemorySession::new());
+
+        let key = run(&server, &middleware, None, |session| session.set("visits", 1)).unwrap();
+        run(&server, &middleware, Some(&key), |session| {
+            assert_eq!(session.key(), Some(key.clone()));
+            assert_eq!(session.get::<u32>("visits"), Some(1));
+        });
+
+        // Unmodified sessions are not sent again
+        assert_eq!(run(&server, &middleware, Some(&key), |_| ()), None);
+    }
+
+    #[test]
+    fn cycling_the_key_deletes_the_old_session() {
+        let server = WebServer::new();
+        let middleware = SessionMiddleware::new(MemorySession::new());
+
+        let old = run(&server, &middleware, None, |session| session.set("cart", vec![1, 2])).unwrap();
+        let new = run(&server, &middleware, Some(&old), |session| session.cycle_key()).unwrap();
+        assert_ne!(old, new);
+
+        run(&server, &middleware, Some(&new), |session| assert_eq!(session.get::<Vec<u32>>("cart"), Some(vec![1, 2])));
+        run(&server, &middleware, Some(&old), |session| assert!(session.is_empty()));
+    }
+
+    #[test]
+    fn flushing_removes_the_cookie() {
+        let server = WebServer::new();
+        let middleware = SessionMiddleware::new(MemorySession::new());
+
+        let key = run(&server, &middleware, None, |session| session.set("user", 1)).unwrap();
+        assert_eq!(run(&server, &middleware, Some(&key), |session| session.flush()), Some(String::new()));
+        run(&server, &middleware, Some(&key), |session| assert!(session.is_empty()));
+    }
+
+    #[test]
+    fn clearing_is_amortised() {
+        let last_cleared = Mutex::new(Instant::now());
+        assert!(!clear_due(&last_cleared));
+
+        *last_cleared.lock().unwrap() = Instant::now() - CLEAR_INTERVAL;
+        assert!(clear_due(&last_cleared));
+        assert!(!clear_due(&last_cleared));
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::{Display, Formatter};
+use crate::sql::{Dialect, MySqlDialect, PostgresDialect, SqliteDialect};
+
+
+/// # Backend
+/// The kind of database a `Database` is connected to, chosen by the scheme of the connection URL.
+/// Every backend enabled with its cargo feature can be used by the same build:
+/// - `sqlite:` -> SQLite (`database_sqlite`)
+/// - `mysql:` or `mariadb:` -> MySQL (`database_mysql`)
+/// - `postgres:` or `postgresql:` -> PostgreSQL (`database_postgres`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    MySql,
+    Postgres
+}
+
+
+impl Backend {
+    /// Get the backend of a connection URL, or `None` if the scheme is unknown
+    pub fn from_url(url: &str) -> Option<Backend> {
+        let (scheme, _) = url.split_once(':')?;
+        match scheme.to_lowercase().as_str() {
+            "sqlite" => Some(Self::Sqlite),
+            "mysql" | "mariadb" => Some(Self::MySql),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            _ => None
+        }
+    }
+
+    /// Test whether the driver of the backend was compiled in
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Self::Sqlite => cfg!(feature = "database_sqlite"),
+            Self::MySql => cfg!(feature = "database_mysql"),
+            Self::Postgres => cfg!(feature = "database_postgres")
+        }
+    }
+
+    /// The SQL dialect of the backend
+    pub fn dialect(&self) -> &'static dyn Dialect {
+        match self {
+            Self::Sqlite => &SqliteDialect,
+            Self::MySql => &MySqlDialect,
+            Self::Postgres => &PostgresDialect
+        }
+    }
+}
+
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::Sqlite => "SQLite",
+            Self::MySql => "MySQL",
+            Self::Postgres => "PostgreSQL"
+        })
+    }
+}
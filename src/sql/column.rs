@@ -1,7 +1,46 @@
-use crate::sql::sql_types::{SQLType, SQLValue};
+use serde::{Deserialize, Serialize};
+use crate::sql::{
+    sql_types::{SQLType, SQLValue},
+    Dialect
+};
 
 
-#[derive(Debug, Clone)]
+/// What happens to the rows referencing a row, which is deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnDelete {
+    /// Refuse to delete rows, which are still referenced (the default)
+    NoAction,
+    /// Refuse to delete rows, which are still referenced, checked immediately
+    Restrict,
+    /// Delete the referencing rows as well
+    Cascade,
+    /// Set the foreign key of the referencing rows to `NULL`
+    SetNull
+}
+
+
+impl std::fmt::Display for OnDelete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::NoAction => "NO ACTION",
+            Self::Restrict => "RESTRICT",
+            Self::Cascade => "CASCADE",
+            Self::SetNull => "SET NULL"
+        })
+    }
+}
+
+
+/// The row referenced by a foreign key column, set with `#[foreign_key(Table)]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub table: String,
+    pub column: String,
+    pub on_delete: OnDelete
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     // Attributes
     pub pk: bool,
@@ -11,6 +50,9 @@ pub struct Column {
     pub null: bool,
     pub fk: bool,
     pub m2m: bool,
+    pub index: bool,
+    #[serde(default)]
+    pub references: Option<ForeignKey>,
 
     pub name: String,
     pub sql_name: String,
@@ -19,53 +61,45 @@ pub struct Column {
 
 
 impl Column {
-    #[cfg(feature="database_sqlite")]
-    pub fn sql_string(&self) -> String {
-        format!(
-            "{sql_name} {sql_type}{unique}{primary_key}{auto_increment}{null}{default}",
-            sql_name=self.sql_name,
-            sql_type=self.sql_type,
-            unique=if self.unique { " UNIQUE" } else { "" },
-            primary_key=if self.pk { " PRIMARY KEY" } else { "" },
-            auto_increment=if self.auto_increment { " AUTOINCREMENT" } else { "" },
-            null=if !self.null { " NOT NULL" } else { "" },
-            default=if !self.null { format!(" DEFAULT {}", self.default) } else { String::new() }
-        )
+    /// Test whether two columns are defined the same way, ignoring their names and indexes
+    pub fn same_definition(&self, other: &Column) -> bool {
+        self.pk == other.pk
+            && self.unique == other.unique
+            && self.auto_increment == other.auto_increment
+            && self.default == other.default
+            && self.null == other.null
+            && self.fk == other.fk
+            && self.m2m == other.m2m
+            && self.references == other.references
+            && self.sql_type == other.sql_type
     }
 
-    #[cfg(feature="database_mysql")]
-    pub fn sql_string(&self) -> String {
-        format!(
-            "{sql_name} {sql_type}{auto_increment}{unique}{primary_key}{null}{default}",
-            sql_name=self.sql_name,
-            sql_type=self.sql_type,
-            auto_increment=if self.auto_increment { " AUTO_INCREMENT" } else { "" },
-            unique=if self.unique { " UNIQUE" } else { "" },
-            primary_key=if self.pk { " PRIMARY KEY" } else { "" },
-            null=if !self.null { " NOT NULL" } else { "" },
-            default=if !self.null { format!(" DEFAULT {}", self.default) } else { String::new() }
-        )
+    /// The `REFERENCES` clause of a foreign key column, or an empty string
+    pub fn references_string(&self, dialect: &dyn Dialect) -> String {
+        match &self.references {
+            Some(fk) => format!(" REFERENCES {} ({}) ON DELETE {}", dialect.quote(&fk.table), dialect.quote(&fk.column), fk.on_delete),
+            None => String::new()
+        }
     }
 
-    #[cfg(feature="database_postgres")]
-    pub fn sql_string(&self) -> String {
-        let ty = if self.auto_increment && self.pk {
-            match self.sql_type {
-                SQLType::Integer => "SERIAL".to_string(),
-                _ => format!("{}", self.sql_type)
-            }
-        } else {
-            format!("{}", self.sql_type)
-        };
+    /// The name of the foreign key constraint of the column in the given table
+    pub fn constraint_name(&self, table: &str) -> String {
+        format!("{table}_{}_fkey", self.sql_name)
+    }
 
-        format!(
-            "{sql_name} {sql_type}{unique}{primary_key}{null}{default}",
-            sql_name=self.sql_name,
-            sql_type=ty,
-            unique=if self.unique { " UNIQUE" } else { "" },
-            primary_key=if self.pk { " PRIMARY KEY" } else { "" },
-            null=if !self.null { " NOT NULL" } else { "" },
-            default=if !self.null { format!(" DEFAULT {}", self.default) } else { String::new() }
-        )
+    /// The named foreign key constraint of the column, like `CONSTRAINT post_author_id_fkey FOREIGN KEY (author_id) REFERENCES ...`
+    pub fn constraint_string(&self, table: &str, dialect: &dyn Dialect) -> Option<String> {
+        self.references.as_ref()?;
+        Some(format!(
+            "CONSTRAINT {name} FOREIGN KEY ({column}){references}",
+            name=dialect.quote(&self.constraint_name(table)),
+            column=dialect.quote(&self.sql_name),
+            references=self.references_string(dialect)
+        ))
     }
-}
\ No newline at end of file
+
+    /// The definition of the column in a `CREATE TABLE` or `ADD COLUMN` statement
+    pub fn sql_string(&self, dialect: &dyn Dialect) -> String {
+        dialect.column_definition(self)
+    }
+}
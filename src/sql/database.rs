@@ -1,118 +1,115 @@
 use sqlx;
 use futures::executor::block_on;
 use std::thread;
-use sqlx::{Error, Executor};
-use sqlx::mysql::MySqlQueryResult;
-use sqlx::postgres::PgQueryResult;
-use sqlx::sqlite::SqliteQueryResult;
-use crate::sql::Table;
-use sqlx::FromRow;
+use std::path::{Path, PathBuf};
+use sqlx::{Error, Executor, Row};
+use sqlx::any::AnyQueryResult;
+use crate::sql::{Backend, Column, Dialect, MigrationRecord, QuerySet, Renames, SQLValue, Table};
 
 
-#[cfg(any(
-    all(feature = "database_mysql", feature = "database_postgres"),
-    all(feature = "database_mysql", feature = "database_sqlite"),
-    all(feature = "database_postgres", feature = "database_sqlite")
-))]
-compile_error!("Only one database driver may be enabled!");
-
-
-#[cfg(feature = "database_mysql")]
 pub struct Database
 {
-    pool: sqlx::Pool<sqlx::MySql>,
-    tables: Vec<Table>
+    pool: sqlx::Pool<crate::sql::SQLDatabase>,
+    backend: Backend,
+    tables: Vec<Table>,
+    renames: Renames,
+    migrations: PathBuf
 }
 
 
-#[cfg(feature = "database_mysql")]
+type QueryResult = AnyQueryResult;
+
+
 impl Database {
+    /// # Connect to a database
+    /// The backend is chosen by the scheme of the URL, like `sqlite://db.sqlite3` or `postgres://localhost/app`,
+    /// so the same build can run on SQLite in tests and on PostgreSQL in production.
+    /// ## Panics
+    /// This method panics if the scheme is unknown, its driver was not enabled, or the connection fails.
     pub fn connect(url: &'static str) -> Database {
+        let backend = match Backend::from_url(url) {
+            Some(backend) if backend.is_enabled() => backend,
+            Some(backend) => panic!("Unable to connect to database: the {backend} driver is not enabled"),
+            None => panic!("Unable to connect to database: unknown scheme in {url}")
+        };
+
         // Connect to the database on a new thread
         let pool = thread::spawn(move || {
             sqlx::any::install_default_drivers();
             block_on(
-                sqlx::Pool::<sqlx::MySql>::connect(url)
+                sqlx::AnyPool::connect(url)
             )
                 .expect("Unable to connect to database")
         })
             .join()
             .expect("Thread crashed while connecting to database");
 
-        Database { pool, tables: Vec::new() }
+        Database { pool, backend, tables: Vec::new(), renames: Renames::default(), migrations: PathBuf::from("migrations") }
     }
-}
-
-#[cfg(feature = "database_postgres")]
-pub struct Database
-{
-    pool: sqlx::Pool<sqlx::Postgres>,
-    tables: Vec<Table>
-}
 
+    /// The kind of database the pool is connected to
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
 
-#[cfg(feature = "database_postgres")]
-impl Database {
-    pub fn connect(url: &'static str) -> Database {
-        // Connect to the database on a new thread
-        let pool = thread::spawn(move || {
-            sqlx::any::install_default_drivers();
-            block_on(
-                sqlx::Pool::<sqlx::Postgres>::connect(url)
-            )
-                .expect("Unable to connect to database")
-        })
-            .join()
-            .expect("Thread crashed while connecting to database");
-
-        Database { pool, tables: Vec::new() }
+    /// The SQL dialect of the backend, which renders the generated statements
+    pub fn dialect(&self) -> &'static dyn Dialect {
+        self.backend.dialect()
     }
-}
 
-#[cfg(feature = "database_sqlite")]
-pub struct Database
-{
-    pool: sqlx::Pool<sqlx::Sqlite>,
-    tables: Vec<Table>
-}
+    /// The bind parameter placeholder for the `index`-th (1-based) parameter of a query
+    pub fn placeholder(&self, index: usize) -> String {
+        self.dialect().placeholder(index)
+    }
 
+    /// The connection pool of the database
+    pub fn pool(&self) -> &sqlx::Pool<crate::sql::SQLDatabase> {
+        &self.pool
+    }
 
-#[cfg(feature = "database_sqlite")]
-impl Database {
-    pub fn connect(url: &'static str) -> Database {
-        // Connect to the database on a new thread
-        let pool = thread::spawn(move || {
-            sqlx::any::install_default_drivers();
-            block_on(
-                sqlx::Pool::<sqlx::Sqlite>::connect(url)
-            )
-                .expect("Unable to connect to database")
+    /// Run a query on a new thread, blocking until it finishes
+    /// ## Example
+    /// ```rust
+    /// let count: Result<i64, sqlx::Error> = db.run(|pool| async move {
+    ///     sqlx::query_scalar("SELECT COUNT(*) FROM users")
+    ///         .fetch_one(&pool)
+    ///         .await
+    /// });
+    /// ```
+    pub fn run<F, Fut, T>(&self, query: F) -> T
+    where
+        F: FnOnce(sqlx::Pool<crate::sql::SQLDatabase>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = T>,
+        T: Send + 'static
+    {
+        let pool = self.pool.clone();
+        thread::spawn(move || {
+            block_on(query(pool))
         })
             .join()
-            .expect("Thread crashed while connecting to database");
-
-        Database { pool, tables: Vec::new() }
+            .expect("Thread crashed while running a query")
     }
-}
-
-#[cfg(feature = "database_mysql")]
-type QueryResult = MySqlQueryResult;
 
-#[cfg(feature = "database_postgres")]
-type QueryResult = PgQueryResult;
-
-#[cfg(feature = "database_sqlite")]
-type QueryResult = SqliteQueryResult;
-
-
-#[cfg(any(
-    feature = "database_mysql",
-    feature = "database_postgres",
-    feature = "database_sqlite"
-))]
-impl Database {
     pub fn query() { todo!() }
-    pub fn insert() { todo!() }
+
+    /// # Insert a row
+    /// Inserts an instance of a table, like `add_to_table`,
+    /// and returns it with its auto-increment primary key and its `#[auto_now_add]` fields set.
+    /// Fails with `sqlx::Error::ColumnDecode`, if the generated key does not fit into the primary key field.
+    /// ## Example
+    /// ```rust
+    /// let post = db.insert(Post::new(0, String::from("Hello"), String::from("...")))?;
+    /// println!("Created post {}", post.id);
+    /// ```
+    pub fn insert<T>(&self, mut instance: T) -> Result<T, Error>
+    where
+        T: crate::sql::SQLTable + Clone + Send + 'static
+    {
+        instance.auto_now(true);
+        let id = self.insert_row(instance.clone())?;
+        instance.set_primary_key(id)?;
+        Ok(instance)
+    }
     
     pub fn execute(&mut self, query: String) -> Result<QueryResult, Error> {
         let pool = self.pool.clone();
@@ -126,69 +123,108 @@ impl Database {
             
     }
     
+    /// Drop every registered table, and the migration history
     pub fn clear_database(&self) {
-        let pool = self.pool.clone();
-        thread::spawn(move || {
-            
+        let mut queries: Vec<String> = self.tables
+            .iter()
+            .rev()
+            .map(|table| format!("DROP TABLE IF EXISTS {}", self.dialect().quote(table.name())))
+            .collect();
+        queries.push(format!("DROP TABLE IF EXISTS {}", self.dialect().quote(MigrationRecord::table_name())));
+
+        self.run(move |pool| async move {
+            for query in queries {
+                if let Err(e) = pool.execute(query.as_str()).await {
+                    eprintln!("Unable to clear database: {e}");
+                }
+            }
         })
-            .join()
-            .expect("Unable to clear database");
     }
-    
-    pub fn migrate(&self) {
-        self.clear_database();
-        for table in &self.tables {
-            let pool = self.pool.clone();
-            
-            let query = format!(
-                "CREATE TABLE {table_name}({parameters})",
-                table_name=table.name(),
-                parameters={
-                    let mut string = String::new();
-                    for column in table.columns() {
-                        string.push_str(&format!(
-                            "{name} {sql_type} {null}\n",
-                            name=&column.name,
-                            sql_type=column.sql_type,
-                            null=if column.null { "" } else { "not null" }
-                        ))
-                    }
-                    string
-                }
-            );
-            
-            println!("Executing query \"{query}\"");
-            
-            block_on(
-                pool.execute(query.as_str())
-            ).expect(&format!("Unable to migrate table {}", table.name()));
+
+    /// # Register a table
+    /// Registers a table for the migrations, without creating it.
+    /// `make_migrations` compares the registered tables with the state of the migrations,
+    /// and `migrate` creates or changes the tables in the database.
+    /// ## Example
+    /// ```rust
+    /// db.register_table::<User>();
+    /// db.register_table::<Post>();
+    /// db.make_migrations(None)?;
+    /// db.migrate()?;
+    /// ```
+    pub fn register_table<T>(&mut self)
+    where
+        T: crate::sql::SQLTable
+    {
+        for table in std::iter::once(T::table()).chain(T::junction_tables()) {
+            self.tables.retain(|registered| registered.name != table.name);
+            self.tables.push(table);
         }
     }
 
+    /// The tables registered with `register_table`
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    /// # Rename a table
+    /// Tells `make_migrations` that a registered table was renamed, so the table keeps its rows
+    /// instead of being dropped and created again.
+    /// ## Example
+    /// ```rust
+    /// db.register_table::<Article>();
+    /// db.rename_table("post", "article");
+    /// db.make_migrations(Some("rename_post"))?;
+    /// ```
+    pub fn rename_table(&mut self, old: &str, new: &str) {
+        self.renames.tables.push((old.to_string(), new.to_string()));
+    }
+
+    /// # Rename a column
+    /// Tells `make_migrations` that a column was renamed, so it keeps its values
+    /// instead of being dropped and added again. The table is named by its current name.
+    pub fn rename_column(&mut self, table: &str, old: &str, new: &str) {
+        self.renames.columns.push((table.to_string(), old.to_string(), new.to_string()));
+    }
+
+    /// The renames given with `rename_table` and `rename_column`
+    pub fn renames(&self) -> &Renames {
+        &self.renames
+    }
+
+    /// Set the directory of the migration files (`migrations` by default)
+    pub fn set_migrations_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.migrations = dir.into();
+    }
+
+    pub fn get_migrations_dir(&self) -> &Path {
+        &self.migrations
+    }
+
     /// Add a table to the database
     pub fn add_table<T>(&mut self)
     where
         T: crate::sql::SQLTable
     {
-        // Clone the pool
-        let pool = self.pool.clone();
+        for table in std::iter::once(T::table()).chain(T::junction_tables()) {
+            // Clone the pool
+            let pool = self.pool.clone();
 
-        // Spawn a new thread
-        match thread::spawn(move || {
             // Get the query
-            let binding = T::table().add_string();
-            let query = binding.as_str();
+            let query = table.add_string(self.dialect());
 
-            // Execute the query
-            block_on(
-                pool.execute(query)
-            )
-        })
-            .join()
-            .expect("Thread crashed unexpectedly while adding a table")
-        {
-            Ok(_) => println!("Table {} was successfully added", T::table().name),
-            Err(e) => eprintln!("Error while adding table {}: {}", T::table().name, e)
+            // Spawn a new thread, and execute the query
+            match thread::spawn(move || {
+                block_on(
+                    pool.execute(query.as_str())
+                )
+            })
+                .join()
+                .expect("Thread crashed unexpectedly while adding a table")
+            {
+                Ok(_) => println!("Table {} was successfully added", table.name),
+                Err(e) => eprintln!("Error while adding table {}: {}", table.name, e)
+            }
         }
     }
 
@@ -214,15 +250,16 @@ impl Database {
     /// ```
     pub fn get_table<T>(&mut self) -> Vec<T>
     where
-        for <'r> T: crate::sql::SQLTable + sqlx::FromRow<'r, crate::SQLRow>
+        for <'r> T: crate::sql::SQLTable + sqlx::FromRow<'r, crate::sql::SQLRow>
     {
         // Clone the pool for further use
         let pool = self.pool.clone();
+        let table = T::table();
+        let query = format!("SELECT {} FROM {}", table.select_list(self.dialect()), self.dialect().quote(&table.name));
 
         // Create a new thread
         let handle = thread::spawn(move || {
             // Query all the rows from the table
-            let query = format!("SELECT * FROM {}", T::table().name);
             block_on(
                 sqlx::query(&query).fetch_all(&pool)
             )
@@ -252,17 +289,203 @@ impl Database {
         }
     }
 
-    pub fn filter_table<T>(&mut self) -> Result<(), Error>
+    /// # Filter a table
+    /// Starts a query on the given table, which can be filtered, sorted and limited,
+    /// using the fields generated by `#[table]`.
+    /// ## Example
+    /// ```rust
+    /// let bob: Option<User> = db.filter_table::<User>()
+    ///     .filter(User::username().eq("bob"))
+    ///     .first()?;
+    ///
+    /// let admins: i64 = db.filter_table::<User>()
+    ///     .filter(User::is_superuser().eq(true))
+    ///     .count()?;
+    /// ```
+    pub fn filter_table<T>(&self) -> QuerySet<'_, T>
     where
-        T: crate::sql::SQLTable
+        for <'r> T: crate::sql::SQLTable + sqlx::FromRow<'r, crate::sql::SQLRow> + Send + 'static
     {
-        todo!()
+        QuerySet::new(self)
     }
 
-    pub fn add_to_table<T>(&mut self, instance: T) -> Result<(), Error>
+    /// # Add a row to a table
+    /// Inserts an instance of a table with bound parameters.
+    /// Auto-increment columns are left out, so the database generates them,
+    /// and the `#[auto_now]` and `#[auto_now_add]` fields are set to the current time.
+    /// ## Returns
+    /// This method returns the generated primary key of the new row, or `0` if the primary key is not auto-incremented.
+    /// ## Example
+    /// ```rust
+    /// let id: i64 = db.add_to_table(Post::new(0, String::from("Hello"), String::from("...")))?;
+    /// ```
+    pub fn add_to_table<T>(&self, mut instance: T) -> Result<i64, Error>
     where
-        T: crate::sql::SQLTable
+        T: crate::sql::SQLTable + Send + 'static
     {
-        todo!()
+        instance.auto_now(true);
+        self.insert_row(instance)
     }
-}
\ No newline at end of file
+
+    /// Insert an instance of a table, returning the generated primary key
+    fn insert_row<T>(&self, instance: T) -> Result<i64, Error>
+    where
+        T: crate::sql::SQLTable + Send + 'static
+    {
+        let table = T::table();
+        let columns: Vec<Column> = table.columns()
+            .into_iter()
+            .filter(|column| !column.auto_increment)
+            .collect();
+        let auto_increment = table.primary_key.auto_increment;
+        let dialect = self.dialect();
+
+        let query = format!(
+            "INSERT INTO {table_name} ({columns}) VALUES ({values})",
+            table_name=dialect.quote(table.name()),
+            columns=columns
+                .iter()
+                .map(|column| dialect.quote(&column.sql_name))
+                .collect::<Vec<String>>()
+                .join(", "),
+            values=columns
+                .iter()
+                .enumerate()
+                .map(|(index, column)| dialect.cast(&column.sql_type, self.placeholder(index + 1)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        // SQLite and PostgreSQL return the generated key, MySQL reports it as the last insert ID
+        let returning = dialect.returning(&table.primary_key.sql_name).filter(|_| auto_increment);
+        let query = format!("{query}{}", returning.as_deref().unwrap_or_default());
+        let returning = returning.is_some();
+
+        self.run(move |pool| async move {
+            let bound = instance.bind_insert(sqlx::query(&query));
+            match returning {
+                true => bound.fetch_one(&pool).await?.try_get::<i64, _>(0),
+                false => bound.execute(&pool).await.map(|result| match auto_increment {
+                    true => result.last_insert_id().unwrap_or(0),
+                    false => 0
+                })
+            }
+        })
+    }
+
+    /// # Update a row of a table
+    /// Updates the given fields of the row with the primary key of the instance.
+    /// The fields are given by their names in the struct, and every column except the primary key is updated if none are given.
+    /// The `#[auto_now]` fields are set to the current time, and always updated.
+    /// ## Returns
+    /// This method returns the number of updated rows, which is `0` if the row does not exist.
+    /// ## Example
+    /// ```rust
+    /// post.title = String::from("Hello, world");
+    /// db.update_in_table(post, &["title"])?;
+    /// ```
+    pub fn update_in_table<T>(&self, mut instance: T, fields: &[&str]) -> Result<u64, Error>
+    where
+        T: crate::sql::SQLTable + Clone + Send + 'static
+    {
+        self.save(&mut instance, fields)
+    }
+
+    /// # Save a row
+    /// Updates a row like `update_in_table`, and sets the `#[auto_now]` fields of the instance to the stored time.
+    /// Used by the `save` and `update_fields` methods generated by `#[table]`.
+    pub fn save<T>(&self, instance: &mut T, fields: &[&str]) -> Result<u64, Error>
+    where
+        T: crate::sql::SQLTable + Clone + Send + 'static
+    {
+        Self::require_primary_key(instance)?;
+        let touched = instance.auto_now(false);
+        let instance = instance.clone();
+        let table = T::table();
+        let mut columns: Vec<String> = match fields.is_empty() {
+            true => table.columns.iter().map(|column| column.sql_name.clone()).collect(),
+            false => fields
+                .iter()
+                .map(|field| table.columns
+                    .iter()
+                    .find(|column| column.name == *field)
+                    .map(|column| column.sql_name.clone())
+                    .ok_or_else(|| Error::ColumnNotFound(field.to_string()))
+                )
+                .collect::<Result<_, _>>()?
+        };
+        for column in touched {
+            if !columns.iter().any(|name| name == column) {
+                columns.push(column.to_string());
+            }
+        }
+        if columns.is_empty() {
+            return Ok(0);
+        }
+
+        let dialect = self.dialect();
+        let query = format!(
+            "UPDATE {table_name} SET {columns} WHERE {primary_key} = {p}",
+            table_name=dialect.quote(table.name()),
+            columns=columns
+                .iter()
+                .enumerate()
+                .map(|(index, name)| {
+                    let column = table.columns.iter().find(|column| column.sql_name == *name);
+                    let placeholder = match column {
+                        Some(column) => dialect.cast(&column.sql_type, self.placeholder(index + 1)),
+                        None => self.placeholder(index + 1)
+                    };
+                    format!("{} = {placeholder}", dialect.quote(name))
+                })
+                .collect::<Vec<String>>()
+                .join(", "),
+            primary_key=dialect.quote(&table.primary_key.sql_name),
+            p=dialect.cast(&table.primary_key.sql_type, self.placeholder(columns.len() + 1))
+        );
+        let primary_key = table.primary_key.sql_name;
+
+        self.run(move |pool| async move {
+            let mut bound = sqlx::query(&query);
+            for column in &columns {
+                bound = instance.bind_column(column, bound);
+            }
+            bound = instance.bind_column(&primary_key, bound);
+
+            Ok(bound.execute(&pool).await?.rows_affected())
+        })
+    }
+
+    /// # Remove a row from a table
+    /// Deletes the row with the primary key of the instance.
+    /// ## Returns
+    /// This method returns the number of deleted rows, which is `0` if the row does not exist.
+    pub fn remove_from_table<T>(&self, instance: T) -> Result<u64, Error>
+    where
+        T: crate::sql::SQLTable + Send + 'static
+    {
+        Self::require_primary_key(&instance)?;
+        let table = T::table();
+        let query = format!(
+            "DELETE FROM {table_name} WHERE {primary_key} = {p1}",
+            table_name=self.dialect().quote(table.name()),
+            primary_key=self.dialect().quote(&table.primary_key.sql_name),
+            p1=self.dialect().cast(&table.primary_key.sql_type, self.placeholder(1))
+        );
+        let primary_key = table.primary_key.sql_name;
+
+        self.run(move |pool| async move {
+            let bound = instance.bind_column(&primary_key, sqlx::query(&query));
+            Ok(bound.execute(&pool).await?.rows_affected())
+        })
+    }
+
+    /// Fail for tables without a `#[primary_key]` field, whose implicit `id` column can not be read from the instance,
+    /// so a row can not be updated or deleted by its primary key
+    fn require_primary_key<T: crate::sql::SQLTable>(instance: &T) -> Result<(), Error> {
+        match instance.primary_key_value() {
+            SQLValue::Null => Err(Error::ColumnNotFound(T::PRIMARY_KEY.to_string())),
+            _ => Ok(())
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::sql::{Column, SQLType, SQLValue};
+
+
+/// # Dialect
+/// The way a backend writes SQL: quoted identifiers, literals, type names, auto-increment columns and `RETURNING`.
+/// Every statement generated by Aerielle, from `CREATE TABLE` to the migrations and the query builder, is rendered through it.
+/// The dialect of a database is returned by `Database::dialect`.
+pub trait Dialect: Send + Sync {
+    /// Quote a table, column, index or constraint name
+    fn quote(&self, identifier: &str) -> String {
+        format!("\"{}\"", identifier.replace('"', "\"\""))
+    }
+
+    /// Quote a string literal, escaping the quotes inside it
+    fn quote_string(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+
+    /// The bind parameter placeholder for the `index`-th (1-based) parameter of a query
+    fn placeholder(&self, _index: usize) -> String {
+        String::from("?")
+    }
+
+    /// The name of the type of a column
+    fn type_name(&self, column: &Column) -> String;
+
+    /// The current date or time in UTC, as the default value of a column of the type
+    fn now(&self, sql_type: &SQLType) -> String;
+
+    /// Convert a bound parameter or a literal to a type, for backends which do not convert text to the date and time types
+    fn cast(&self, _sql_type: &SQLType, expression: String) -> String {
+        expression
+    }
+
+    /// A column in the select list of a query.
+    /// The runtime driver can not read the native date and time types, so they are read as text.
+    fn select_column(&self, column: &Column) -> String {
+        self.quote(&column.sql_name)
+    }
+
+    /// The keyword making a primary key auto-incremented, or an empty string if the type does it
+    fn auto_increment(&self) -> &'static str;
+
+    /// The clause returning a column of the inserted row, or `None` if the backend reports the last insert ID instead
+    fn returning(&self, column: &str) -> Option<String> {
+        Some(format!(" RETURNING {}", self.quote(column)))
+    }
+
+    /// An `INSERT` of a single row, which does nothing if the row would violate a unique constraint
+    fn insert_or_ignore(&self, table: &str, columns: &[&str], values: &[String]) -> String {
+        format!(
+            "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+            self.quote(table),
+            columns.iter().map(|column| self.quote(column)).collect::<Vec<String>>().join(", "),
+            values.join(", ")
+        )
+    }
+
+    /// A boolean literal
+    fn boolean(&self, value: bool) -> &'static str {
+        if value { "1" } else { "0" }
+    }
+
+    /// A binary string literal
+    fn blob(&self, value: &[u8]) -> String {
+        format!("X'{}'", hex(value))
+    }
+
+    /// A value written into a statement, like the default value of a column
+    fn literal(&self, value: &SQLValue) -> String {
+        match value {
+            SQLValue::Text(v) => self.quote_string(v),
+            SQLValue::Blob(v) => self.blob(v),
+            SQLValue::Boolean(v) | SQLValue::Bit(v) => self.boolean(*v).to_string(),
+            SQLValue::Integer(v) => v.to_string(),
+            SQLValue::Float(v) => v.to_string(),
+            SQLValue::TimeStamp(_) | SQLValue::TimeStampTz(_) | SQLValue::Date(_) | SQLValue::Time(_) => self.cast(
+                &value.sql_type().unwrap_or(SQLType::Text),
+                self.quote_string(&value.to_text().unwrap_or_default())
+            ),
+            SQLValue::Now => self.now(&SQLType::TimeStamp),
+            SQLValue::Null => String::from("NULL")
+        }
+    }
+
+    /// The default value of a column
+    fn default_value(&self, column: &Column) -> String {
+        match column.default {
+            SQLValue::Now => self.now(&column.sql_type),
+            ref value => self.literal(value)
+        }
+    }
+
+    /// A column of a `UNIQUE` constraint over several columns
+    fn key_column(&self, column: &Column) -> String {
+        self.quote(&column.sql_name)
+    }
+
+    /// The definition of a column in a `CREATE TABLE` or `ADD COLUMN` statement
+    fn column_definition(&self, column: &Column) -> String {
+        let mut definition = format!("{} {}", self.quote(&column.sql_name), self.type_name(column));
+        if column.unique {
+            definition.push_str(" UNIQUE");
+        }
+        if column.pk {
+            definition.push_str(" PRIMARY KEY");
+        }
+        if column.auto_increment && !self.auto_increment().is_empty() {
+            definition.push(' ');
+            definition.push_str(self.auto_increment());
+        }
+        // Auto-increment columns are generated, so they can not have a default value
+        if !column.null {
+            definition.push_str(" NOT NULL");
+            if !column.auto_increment {
+                definition.push_str(&format!(" DEFAULT {}", self.default_value(column)));
+            }
+        }
+        definition
+    }
+}
+
+
+/// The bytes as hexadecimal digits
+fn hex(value: &[u8]) -> String {
+    value.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+
+/// The SQL dialect of SQLite
+pub struct SqliteDialect;
+
+
+impl Dialect for SqliteDialect {
+    /// The runtime driver can not read SQLite booleans, so they are stored as integers.
+    /// SQLite has no date and time types, so they are stored as text, which sorts in chronological order.
+    fn type_name(&self, column: &Column) -> String {
+        match column.sql_type {
+            SQLType::Boolean | SQLType::Bit => String::from("INTEGER"),
+            SQLType::TimeStamp | SQLType::TimeStampTz | SQLType::Date | SQLType::Time => String::from("TEXT"),
+            _ => column.sql_type.to_string()
+        }
+    }
+
+    /// `%f` only has milliseconds, which are padded to the microseconds of the other backends
+    fn now(&self, sql_type: &SQLType) -> String {
+        String::from(match sql_type {
+            SQLType::Date => "(strftime('%Y-%m-%d', 'now'))",
+            SQLType::Time => "(strftime('%H:%M:%S', 'now') || substr(strftime('%f', 'now'), 3) || '000')",
+            _ => "(strftime('%Y-%m-%d %H:%M:%S', 'now') || substr(strftime('%f', 'now'), 3) || '000')"
+        })
+    }
+
+    fn auto_increment(&self) -> &'static str {
+        "AUTOINCREMENT"
+    }
+}
+
+
+/// The SQL dialect of MySQL and MariaDB
+pub struct MySqlDialect;
+
+
+impl Dialect for MySqlDialect {
+    fn quote(&self, identifier: &str) -> String {
+        format!("`{}`", identifier.replace('`', "``"))
+    }
+
+    /// MySQL treats backslashes in strings as escape characters
+    fn quote_string(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+    }
+
+    /// The runtime driver can not read MySQL booleans, so they are stored as integers.
+    /// Text columns are `TEXT`, unless they are a key, which MySQL can only build on `VARCHAR` with a length.
+    /// MySQL has no time zones in `DATETIME`, so `TimeStampTz` columns hold the time in UTC.
+    fn type_name(&self, column: &Column) -> String {
+        match column.sql_type {
+            SQLType::Boolean | SQLType::Bit => String::from("SMALLINT"),
+            SQLType::Text if column.pk || column.unique || column.index => String::from("VARCHAR(255)"),
+            SQLType::Text => String::from("TEXT"),
+            SQLType::Float => String::from("DOUBLE"),
+            SQLType::TimeStamp | SQLType::TimeStampTz => String::from("DATETIME(6)"),
+            SQLType::Date => String::from("DATE"),
+            SQLType::Time => String::from("TIME(6)"),
+            _ => column.sql_type.to_string()
+        }
+    }
+
+    /// MySQL only accepts expressions as default values in parentheses
+    fn now(&self, sql_type: &SQLType) -> String {
+        String::from(match sql_type {
+            SQLType::Date => "(UTC_DATE())",
+            SQLType::Time => "(UTC_TIME(6))",
+            _ => "(UTC_TIMESTAMP(6))"
+        })
+    }
+
+    /// `TEXT` and `BLOB` columns only accept expressions as default values
+    fn default_value(&self, column: &Column) -> String {
+        match (&column.default, self.type_name(column).as_str()) {
+            (SQLValue::Now, _) => self.now(&column.sql_type),
+            (value, "TEXT" | "BLOB") => format!("({})", self.literal(value)),
+            (value, _) => self.literal(value)
+        }
+    }
+
+    /// MySQL can only build keys on the first characters of `TEXT` columns
+    fn key_column(&self, column: &Column) -> String {
+        match self.type_name(column).as_str() {
+            "TEXT" => format!("{}(255)", self.quote(&column.sql_name)),
+            _ => self.quote(&column.sql_name)
+        }
+    }
+
+    fn select_column(&self, column: &Column) -> String {
+        let name = self.quote(&column.sql_name);
+        match column.sql_type {
+            SQLType::TimeStamp | SQLType::TimeStampTz => format!("DATE_FORMAT({name}, '%Y-%m-%d %H:%i:%s.%f') AS {name}"),
+            SQLType::Date => format!("DATE_FORMAT({name}, '%Y-%m-%d') AS {name}"),
+            SQLType::Time => format!("TIME_FORMAT({name}, '%H:%i:%s.%f') AS {name}"),
+            _ => name
+        }
+    }
+
+    fn auto_increment(&self) -> &'static str {
+        "AUTO_INCREMENT"
+    }
+
+    fn returning(&self, _column: &str) -> Option<String> {
+        None
+    }
+
+    /// Unlike `INSERT IGNORE`, this only ignores duplicate keys, not other errors like missing foreign keys
+    fn insert_or_ignore(&self, table: &str, columns: &[&str], values: &[String]) -> String {
+        let first = self.quote(columns.first().expect("An insert needs a column"));
+        format!(
+            "INSERT INTO {} ({}) VALUES ({}) ON DUPLICATE KEY UPDATE {first} = {first}",
+            self.quote(table),
+            columns.iter().map(|column| self.quote(column)).collect::<Vec<String>>().join(", "),
+            values.join(", ")
+        )
+    }
+}
+
+
+/// The SQL dialect of PostgreSQL
+pub struct PostgresDialect;
+
+
+impl Dialect for PostgresDialect {
+    fn placeholder(&self, index: usize) -> String {
+        format!("${index}")
+    }
+
+    /// Auto-increment primary keys are `SERIAL` columns
+    fn type_name(&self, column: &Column) -> String {
+        match column.sql_type {
+            SQLType::Integer if column.auto_increment && column.pk => String::from("SERIAL"),
+            SQLType::Blob => String::from("BYTEA"),
+            SQLType::Bit => String::from("BOOLEAN"),
+            SQLType::Float => String::from("DOUBLE PRECISION"),
+            SQLType::TimeStamp => String::from("TIMESTAMP"),
+            SQLType::TimeStampTz => String::from("TIMESTAMPTZ"),
+            SQLType::Date => String::from("DATE"),
+            SQLType::Time => String::from("TIME"),
+            _ => column.sql_type.to_string()
+        }
+    }
+
+    fn now(&self, sql_type: &SQLType) -> String {
+        String::from(match sql_type {
+            SQLType::TimeStampTz => "CURRENT_TIMESTAMP",
+            SQLType::Date => "(CAST(now() AT TIME ZONE 'UTC' AS DATE))",
+            SQLType::Time => "(CAST(now() AT TIME ZONE 'UTC' AS TIME))",
+            _ => "(now() AT TIME ZONE 'UTC')"
+        })
+    }
+
+    /// Text parameters are not converted to the date and time types, and times with a time zone are given in UTC
+    fn cast(&self, sql_type: &SQLType, expression: String) -> String {
+        match sql_type {
+            SQLType::TimeStamp => format!("CAST({expression} AS TIMESTAMP)"),
+            SQLType::TimeStampTz => format!("(CAST({expression} AS TIMESTAMP) AT TIME ZONE 'UTC')"),
+            SQLType::Date => format!("CAST({expression} AS DATE)"),
+            SQLType::Time => format!("CAST({expression} AS TIME)"),
+            _ => expression
+        }
+    }
+
+    fn select_column(&self, column: &Column) -> String {
+        let name = self.quote(&column.sql_name);
+        match column.sql_type {
+            SQLType::TimeStamp => format!("to_char({name}, 'YYYY-MM-DD HH24:MI:SS.US') AS {name}"),
+            SQLType::TimeStampTz => format!("to_char({name} AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.US') AS {name}"),
+            SQLType::Date => format!("to_char({name}, 'YYYY-MM-DD') AS {name}"),
+            SQLType::Time => format!("to_char({name}, 'HH24:MI:SS.US') AS {name}"),
+            _ => name
+        }
+    }
+
+    fn auto_increment(&self) -> &'static str {
+        ""
+    }
+
+    fn boolean(&self, value: bool) -> &'static str {
+        if value { "TRUE" } else { "FALSE" }
+    }
+
+    fn blob(&self, value: &[u8]) -> String {
+        format!("'\\x{}'::BYTEA", hex(value))
+    }
+
+    fn insert_or_ignore(&self, table: &str, columns: &[&str], values: &[String]) -> String {
+        format!(
+            "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT DO NOTHING",
+            self.quote(table),
+            columns.iter().map(|column| self.quote(column)).collect::<Vec<String>>().join(", "),
+            values.join(", ")
+        )
+    }
+}
@@ -0,0 +1,850 @@
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH}
+};
+use serde::{Deserialize, Serialize};
+use sqlx::Executor;
+use table_macro::table;
+use crate::sql::{self, Backend, Column, Database, Dialect, SQLValue, Table};
+
+
+/// The history of the applied migrations, stored in the `aerielle_migrations` table
+#[table(name = "aerielle_migrations")]
+pub struct MigrationRecord {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[unique]
+    #[not_null]
+    name: Text,
+
+    // Seconds since the UNIX epoch
+    #[not_null]
+    applied_at: Float
+}
+
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Database(sqlx::Error),
+    Unknown(String)
+}
+
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Unable to read or write migration file: {e}"),
+            Self::Json(e) => write!(f, "Invalid migration file: {e}"),
+            Self::Database(e) => write!(f, "Unable to run migration: {e}"),
+            Self::Unknown(name) => write!(f, "Unknown migration: {name}")
+        }
+    }
+}
+
+
+impl From<io::Error> for MigrationError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+
+impl From<serde_json::Error> for MigrationError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Database(value)
+    }
+}
+
+
+/// # Operation
+/// A single change of the schema. Every operation keeps enough information to be reversed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum Operation {
+    CreateTable { table: Table },
+    DropTable { table: Table },
+    RenameTable { old: String, new: String },
+    AddColumn { table: String, column: Column },
+    DropColumn { table: String, column: Column },
+    AlterColumn { table: String, old: Column, new: Column },
+    RenameColumn { table: String, old: String, new: String },
+    CreateIndex { table: String, column: String },
+    DropIndex { table: String, column: String }
+}
+
+
+/// The name of the index of a column
+fn index_name(table: &str, column: &str) -> String {
+    format!("{table}_{column}_idx")
+}
+
+
+fn create_index(dialect: &dyn Dialect, table: &str, column: &str) -> String {
+    format!(
+        "CREATE INDEX {} ON {} ({})",
+        dialect.quote(&index_name(table, column)),
+        dialect.quote(table),
+        dialect.quote(column)
+    )
+}
+
+
+/// MySQL indexes belong to their table
+fn drop_index(backend: Backend, table: &str, column: &str) -> String {
+    let dialect = backend.dialect();
+    let index = dialect.quote(&index_name(table, column));
+    match backend {
+        Backend::MySql => format!("DROP INDEX {index} ON {}", dialect.quote(table)),
+        Backend::Sqlite | Backend::Postgres => format!("DROP INDEX {index}")
+    }
+}
+
+
+/// The names of the indexed columns of a table
+fn indexed_columns(table: &Table) -> Vec<String> {
+    table.columns()
+        .into_iter()
+        .filter(|column| column.index)
+        .map(|column| column.sql_name)
+        .collect()
+}
+
+
+/// Change a column of a table, which may be its primary key
+fn column_mut<'a>(table: &'a mut Table, name: &str) -> Option<&'a mut Column> {
+    match table.primary_key.sql_name == name {
+        true => Some(&mut table.primary_key),
+        false => table.columns.iter_mut().find(|column| column.sql_name == name)
+    }
+}
+
+
+impl Operation {
+    /// The operation, which undoes this operation
+    pub fn reverse(&self) -> Operation {
+        match self.clone() {
+            Self::CreateTable { table } => Self::DropTable { table },
+            Self::DropTable { table } => Self::CreateTable { table },
+            Self::RenameTable { old, new } => Self::RenameTable { old: new, new: old },
+            Self::AddColumn { table, column } => Self::DropColumn { table, column },
+            Self::DropColumn { table, column } => Self::AddColumn { table, column },
+            Self::AlterColumn { table, old, new } => Self::AlterColumn { table, old: new, new: old },
+            Self::RenameColumn { table, old, new } => Self::RenameColumn { table, old: new, new: old },
+            Self::CreateIndex { table, column } => Self::DropIndex { table, column },
+            Self::DropIndex { table, column } => Self::CreateIndex { table, column }
+        }
+    }
+
+    /// Apply the operation to the state of the schema
+    pub fn apply(&self, schema: &mut Schema) {
+        match self {
+            Self::CreateTable { table } => schema.tables.push(table.clone()),
+            Self::DropTable { table } => schema.tables.retain(|t| t.name != table.name),
+            Self::RenameTable { old, new } => {
+                if let Some(table) = schema.table_mut(old) {
+                    table.name = new.clone();
+                }
+            },
+            Self::AddColumn { table, column } => {
+                if let Some(table) = schema.table_mut(table) {
+                    table.columns.push(column.clone());
+                }
+            },
+            Self::DropColumn { table, column } => {
+                if let Some(table) = schema.table_mut(table) {
+                    table.columns.retain(|c| c.sql_name != column.sql_name);
+                    table.unique_together.retain(|group| !group.contains(&column.sql_name));
+                }
+            },
+            Self::AlterColumn { table, old, new } => {
+                if let Some(column) = schema.table_mut(table).and_then(|table| column_mut(table, &old.sql_name)) {
+                    *column = new.clone();
+                }
+            },
+            Self::RenameColumn { table, old, new } => {
+                let Some(table) = schema.table_mut(table) else { return };
+                if let Some(column) = column_mut(table, old) {
+                    column.sql_name = new.clone();
+                }
+                for name in table.unique_together.iter_mut().flatten().filter(|name| *name == old) {
+                    *name = new.clone();
+                }
+            },
+            Self::CreateIndex { table, column } | Self::DropIndex { table, column } => {
+                let index = matches!(self, Self::CreateIndex { .. });
+                if let Some(column) = schema.table_mut(table).and_then(|table| column_mut(table, column)) {
+                    column.index = index;
+                }
+            }
+        }
+    }
+
+    /// # The SQL of the operation
+    /// Generates the statements, which apply the operation to a database of the given backend in the given state.
+    pub fn sql(&self, schema: &Schema, backend: Backend) -> Vec<String> {
+        let dialect = backend.dialect();
+        match self {
+            Self::CreateTable { table } => vec![table.add_string(dialect)],
+            Self::DropTable { table } => vec![format!("DROP TABLE {}", dialect.quote(&table.name))],
+            Self::RenameTable { old, new } => {
+                // Indexes are named after their table, so they are renamed as well
+                let indexed = schema.table(old).map(indexed_columns).unwrap_or_default();
+                let mut sql: Vec<String> = indexed.iter().map(|column| drop_index(backend, old, column)).collect();
+                sql.push(format!("ALTER TABLE {} RENAME TO {}", dialect.quote(old), dialect.quote(new)));
+                sql.extend(indexed.iter().map(|column| create_index(dialect, new, column)));
+                sql
+            },
+            Self::AddColumn { table, column } => Self::add_column(backend, schema, table, column),
+            Self::DropColumn { table, column } => Self::drop_column(backend, schema, table, column),
+            Self::AlterColumn { table, old, new } => {
+                // Rows without a value would violate the new NOT NULL constraint, so they get the default
+                let mut sql = Vec::new();
+                if old.null && !new.null {
+                    sql.push(format!(
+                        "UPDATE {table} SET {column} = {default} WHERE {column} IS NULL",
+                        table=dialect.quote(table),
+                        column=dialect.quote(&new.sql_name),
+                        default=dialect.default_value(new)
+                    ));
+                }
+                sql.extend(Self::alter_column(backend, schema, table, old, new));
+                sql
+            },
+            Self::RenameColumn { table, old, new } => {
+                let indexed = Self::find_column(schema, table, old).is_some_and(|column| column.index);
+                let mut sql = Vec::new();
+                if indexed {
+                    sql.push(drop_index(backend, table, old));
+                }
+                sql.extend(Self::rename_column(backend, schema, table, old, new));
+                if indexed {
+                    sql.push(create_index(dialect, table, new));
+                }
+                sql
+            },
+            Self::CreateIndex { table, column } => vec![create_index(dialect, table, column)],
+            Self::DropIndex { table, column } => vec![drop_index(backend, table, column)]
+        }
+    }
+
+    /// Rebuild a table with the changed columns, copying the rows of the remaining columns.
+    /// SQLite can not alter columns, or drop constrained columns, so this is the only way to change them.
+    fn rebuild(schema: &Schema, table: &str, change: impl FnOnce(&mut Table)) -> Vec<String> {
+        let Some(mut rebuilt) = schema.table(table).cloned() else { return Vec::new() };
+        change(&mut rebuilt);
+        let dialect = Backend::Sqlite.dialect();
+        let columns = rebuilt.columns()
+            .iter()
+            .filter(|column| Self::find_column(schema, table, &column.sql_name).is_some())
+            .map(|column| dialect.quote(&column.sql_name))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        rebuilt.name = format!("_aerielle_new_{table}");
+        let (temporary, quoted) = (dialect.quote(&rebuilt.name), dialect.quote(table));
+        let mut sql = vec![
+            rebuilt.add_string(dialect),
+            format!("INSERT INTO {temporary} ({columns}) SELECT {columns} FROM {quoted}"),
+            format!("DROP TABLE {quoted}"),
+            format!("ALTER TABLE {temporary} RENAME TO {quoted}")
+        ];
+        sql.extend(indexed_columns(&rebuilt).iter().map(|column| create_index(dialect, table, column)));
+        sql
+    }
+
+    /// SQLite can only add foreign keys with the column, the others add them as a named constraint
+    fn add_column(backend: Backend, schema: &Schema, table: &str, column: &Column) -> Vec<String> {
+        let dialect = backend.dialect();
+        let quoted = dialect.quote(table);
+        // SQLite can only add columns with a constant default value
+        if backend == Backend::Sqlite && column.default == SQLValue::Now && !column.null {
+            return Self::rebuild(schema, table, |rebuilt| rebuilt.columns.push(column.clone()));
+        }
+        if backend == Backend::Sqlite {
+            return vec![format!("ALTER TABLE {quoted} ADD COLUMN {}{}", column.sql_string(dialect), column.references_string(dialect))];
+        }
+
+        let mut sql = vec![format!("ALTER TABLE {quoted} ADD COLUMN {}", column.sql_string(dialect))];
+        if let Some(constraint) = column.constraint_string(table, dialect) {
+            sql.push(format!("ALTER TABLE {quoted} ADD {constraint}"));
+        }
+        sql
+    }
+
+    fn drop_column(backend: Backend, schema: &Schema, table: &str, column: &Column) -> Vec<String> {
+        let dialect = backend.dialect();
+        let drop = format!("ALTER TABLE {} DROP COLUMN {}", dialect.quote(table), dialect.quote(&column.sql_name));
+        match backend {
+            // SQLite can not drop constrained columns
+            Backend::Sqlite => {
+                let constrained = schema.table(table)
+                    .is_some_and(|t| t.unique_together.iter().flatten().any(|name| *name == column.sql_name));
+                match column.unique || column.references.is_some() || constrained {
+                    true => Self::rebuild(schema, table, |rebuilt| {
+                        rebuilt.columns.retain(|c| c.sql_name != column.sql_name);
+                        rebuilt.unique_together.retain(|group| !group.contains(&column.sql_name));
+                    }),
+                    false => vec![drop]
+                }
+            },
+            // MySQL can not drop columns with a foreign key constraint
+            Backend::MySql if column.references.is_some() => vec![
+                format!("ALTER TABLE {} DROP FOREIGN KEY {}", dialect.quote(table), dialect.quote(&column.constraint_name(table))),
+                drop
+            ],
+            Backend::MySql | Backend::Postgres => vec![drop]
+        }
+    }
+
+    fn alter_column(backend: Backend, schema: &Schema, table: &str, old: &Column, new: &Column) -> Vec<String> {
+        match backend {
+            Backend::Sqlite => Self::rebuild(schema, table, |rebuilt| {
+                if let Some(column) = column_mut(rebuilt, &old.sql_name) {
+                    *column = new.clone();
+                }
+            }),
+            Backend::MySql => {
+                let dialect = backend.dialect();
+                let quoted = dialect.quote(table);
+                let mut sql = Vec::new();
+                let references_changed = old.references != new.references;
+                if references_changed && old.references.is_some() {
+                    sql.push(format!("ALTER TABLE {quoted} DROP FOREIGN KEY {}", dialect.quote(&old.constraint_name(table))));
+                }
+                sql.push(format!("ALTER TABLE {quoted} MODIFY COLUMN {}", new.sql_string(dialect)));
+                if let Some(constraint) = new.constraint_string(table, dialect).filter(|_| references_changed) {
+                    sql.push(format!("ALTER TABLE {quoted} ADD {constraint}"));
+                }
+                sql
+            },
+            Backend::Postgres => Self::alter_postgres_column(table, old, new)
+        }
+    }
+
+    /// PostgreSQL changes every property of a column separately
+    fn alter_postgres_column(table: &str, old: &Column, new: &Column) -> Vec<String> {
+        let dialect = Backend::Postgres.dialect();
+        let (name, table) = (table, dialect.quote(table));
+        let column = dialect.quote(&new.sql_name);
+        // The name PostgreSQL gives to unique constraints
+        let unique = dialect.quote(&format!("{name}_{}_key", new.sql_name));
+        let mut sql = Vec::new();
+
+        // The old default may not be convertible to the new type, so it is replaced after the conversion
+        let retyped = old.sql_type != new.sql_type;
+        if retyped {
+            let type_name = dialect.type_name(new);
+            if !old.null {
+                sql.push(format!("ALTER TABLE {table} ALTER COLUMN {column} DROP DEFAULT"));
+            }
+            sql.push(format!("ALTER TABLE {table} ALTER COLUMN {column} TYPE {type_name} USING CAST({column} AS {type_name})"));
+        }
+        if retyped || old.null != new.null || old.default != new.default {
+            sql.push(match new.null {
+                true => format!("ALTER TABLE {table} ALTER COLUMN {column} DROP DEFAULT"),
+                false => format!("ALTER TABLE {table} ALTER COLUMN {column} SET DEFAULT {}", dialect.default_value(new))
+            });
+        }
+        if old.null != new.null {
+            sql.push(format!(
+                "ALTER TABLE {table} ALTER COLUMN {column} {} NOT NULL",
+                if new.null { "DROP" } else { "SET" }
+            ));
+        }
+        if old.unique != new.unique {
+            sql.push(match new.unique {
+                true => format!("ALTER TABLE {table} ADD CONSTRAINT {unique} UNIQUE ({column})"),
+                false => format!("ALTER TABLE {table} DROP CONSTRAINT {unique}")
+            });
+        }
+        if old.references != new.references {
+            if old.references.is_some() {
+                sql.push(format!("ALTER TABLE {table} DROP CONSTRAINT {}", dialect.quote(&old.constraint_name(name))));
+            }
+            if let Some(constraint) = new.constraint_string(name, dialect) {
+                sql.push(format!("ALTER TABLE {table} ADD {constraint}"));
+            }
+        }
+        sql
+    }
+
+    /// The column of a table in the given state
+    fn find_column(schema: &Schema, table: &str, name: &str) -> Option<Column> {
+        schema.table(table)?.columns().into_iter().find(|column| column.sql_name == name)
+    }
+
+    fn rename_column(backend: Backend, schema: &Schema, table: &str, old: &str, new: &str) -> Vec<String> {
+        let dialect = backend.dialect();
+        let quoted = dialect.quote(table);
+        let rename = format!("ALTER TABLE {quoted} RENAME COLUMN {} TO {}", dialect.quote(old), dialect.quote(new));
+        let Some(column) = Self::find_column(schema, table, old).filter(|column| column.references.is_some()) else {
+            return vec![rename];
+        };
+        let renamed = Column { sql_name: new.to_string(), ..column.clone() };
+
+        match backend {
+            // SQLite updates the foreign key constraints of the renamed column by itself
+            Backend::Sqlite => vec![rename],
+            // MySQL can not rename foreign key constraints, so they are dropped and added again
+            Backend::MySql => {
+                let mut sql = vec![
+                    format!("ALTER TABLE {quoted} DROP FOREIGN KEY {}", dialect.quote(&column.constraint_name(table))),
+                    rename
+                ];
+                sql.extend(renamed.constraint_string(table, dialect).map(|constraint| format!("ALTER TABLE {quoted} ADD {constraint}")));
+                sql
+            },
+            Backend::Postgres => vec![
+                rename,
+                format!(
+                    "ALTER TABLE {quoted} RENAME CONSTRAINT {} TO {}",
+                    dialect.quote(&column.constraint_name(table)),
+                    dialect.quote(&renamed.constraint_name(table))
+                )
+            ]
+        }
+    }
+}
+
+
+/// # Renames
+/// The tables and columns, which `make_migrations` renames instead of dropping the old and adding the new one.
+/// Renamed columns are named by the new name of their table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Renames {
+    pub tables: Vec<(String, String)>,
+    pub columns: Vec<(String, String, String)>
+}
+
+
+impl Renames {
+    fn table(&self, old: &str, new: &str) -> bool {
+        self.tables.iter().any(|(o, n)| o == old && n == new)
+    }
+
+    fn column(&self, table: &str, old: &str, new: &str) -> bool {
+        self.columns.iter().any(|(t, o, n)| t == table && o == old && n == new)
+    }
+}
+
+
+/// # Schema
+/// The state of the tables, as the migrations leave them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<Table>
+}
+
+
+impl Schema {
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    fn table_mut(&mut self, name: &str) -> Option<&mut Table> {
+        self.tables.iter_mut().find(|table| table.name == name)
+    }
+
+    /// # Compare schemas
+    /// Creates the operations, which change this schema into the given tables.
+    /// A table or column is only renamed if the renames contain it, otherwise the old one is dropped and the new one added.
+    pub fn diff(&self, tables: &[Table], renames: &Renames) -> Vec<Operation> {
+        let mut operations = Vec::new();
+        let mut removed: Vec<&Table> = self.tables
+            .iter()
+            .filter(|old| !tables.iter().any(|new| new.name == old.name))
+            .collect();
+
+        for new in tables {
+            let old = match self.table(&new.name) {
+                Some(old) => old,
+                None => {
+                    let renamed = removed.iter().position(|old| renames.table(&old.name, &new.name));
+                    match renamed {
+                        Some(position) => {
+                            let old = removed.remove(position);
+                            operations.push(Operation::RenameTable { old: old.name.clone(), new: new.name.clone() });
+                            old
+                        },
+                        None => {
+                            let mut table = new.clone();
+                            table.columns.iter_mut().for_each(|column| column.index = false);
+                            table.primary_key.index = false;
+                            operations.push(Operation::CreateTable { table });
+                            operations.extend(indexed_columns(new).into_iter().map(|column| Operation::CreateIndex {
+                                table: new.name.clone(),
+                                column
+                            }));
+                            continue;
+                        }
+                    }
+                }
+            };
+            operations.extend(Self::diff_columns(&new.name, old, new, renames));
+        }
+
+        for table in removed {
+            // Drop the indexes first, so rolling back recreates them
+            operations.extend(indexed_columns(table).into_iter().map(|column| Operation::DropIndex {
+                table: table.name.clone(),
+                column
+            }));
+            let mut table = table.clone();
+            table.columns.iter_mut().for_each(|column| column.index = false);
+            table.primary_key.index = false;
+            operations.push(Operation::DropTable { table });
+        }
+        operations
+    }
+
+    /// The operations, which change the columns of a table
+    fn diff_columns(table: &str, old: &Table, new: &Table, renames: &Renames) -> Vec<Operation> {
+        let (old_columns, new_columns) = (old.columns(), new.columns());
+        let mut removed: Vec<&Column> = old_columns
+            .iter()
+            .filter(|column| !new_columns.iter().any(|c| c.sql_name == column.sql_name))
+            .collect();
+
+        let mut renamed = Vec::new();
+        let mut drop_indexes = Vec::new();
+        let mut drops = Vec::new();
+        let mut adds = Vec::new();
+        let mut alters = Vec::new();
+        let mut create_indexes = Vec::new();
+
+        for column in &new_columns {
+            let previous = match old_columns.iter().find(|c| c.sql_name == column.sql_name) {
+                Some(previous) => previous,
+                None => match removed.iter().position(|c| renames.column(table, &c.sql_name, &column.sql_name)) {
+                    Some(position) => {
+                        let previous = removed.remove(position);
+                        renamed.push(Operation::RenameColumn {
+                            table: table.to_string(),
+                            old: previous.sql_name.clone(),
+                            new: column.sql_name.clone()
+                        });
+                        previous
+                    },
+                    None => {
+                        adds.push(Operation::AddColumn {
+                            table: table.to_string(),
+                            column: Column { index: false, ..column.clone() }
+                        });
+                        if column.index {
+                            create_indexes.push(Operation::CreateIndex { table: table.to_string(), column: column.sql_name.clone() });
+                        }
+                        continue;
+                    }
+                }
+            };
+
+            if !previous.same_definition(column) {
+                alters.push(Operation::AlterColumn {
+                    table: table.to_string(),
+                    old: Column { sql_name: column.sql_name.clone(), index: false, ..previous.clone() },
+                    new: Column { index: false, ..column.clone() }
+                });
+            }
+            match (previous.index, column.index) {
+                (false, true) => create_indexes.push(Operation::CreateIndex { table: table.to_string(), column: column.sql_name.clone() }),
+                (true, false) => drop_indexes.push(Operation::DropIndex { table: table.to_string(), column: column.sql_name.clone() }),
+                _ => ()
+            }
+        }
+
+        for column in removed {
+            // Indexed columns can not be dropped on every database
+            if column.index {
+                drop_indexes.push(Operation::DropIndex { table: table.to_string(), column: column.sql_name.clone() });
+            }
+            drops.push(Operation::DropColumn {
+                table: table.to_string(),
+                column: Column { index: false, ..column.clone() }
+            });
+        }
+
+        let mut operations = renamed;
+        operations.extend(drop_indexes);
+        operations.extend(drops);
+        operations.extend(adds);
+        operations.extend(alters);
+        operations.extend(create_indexes);
+        operations
+    }
+}
+
+
+/// # Migration
+/// A named list of operations, stored as a JSON file in the migrations directory, like `migrations/0001_initial.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Migration {
+    pub name: String,
+    pub operations: Vec<Operation>
+}
+
+
+impl Migration {
+    /// Apply the migration to the state of the schema
+    pub fn apply(&self, schema: &mut Schema) {
+        for operation in &self.operations {
+            operation.apply(schema);
+        }
+    }
+
+    /// The statements, which apply the migration to a database of the backend in the given state
+    pub fn forwards_sql(&self, schema: &Schema, backend: Backend) -> Vec<String> {
+        let mut state = schema.clone();
+        let mut sql = Vec::new();
+        for operation in &self.operations {
+            sql.extend(operation.sql(&state, backend));
+            operation.apply(&mut state);
+        }
+        sql
+    }
+
+    /// The statements, which undo the migration, if it was applied to a database of the backend in the given state
+    pub fn backwards_sql(&self, schema: &Schema, backend: Backend) -> Vec<String> {
+        // The state after every operation
+        let mut states = Vec::with_capacity(self.operations.len());
+        let mut state = schema.clone();
+        for operation in &self.operations {
+            operation.apply(&mut state);
+            states.push(state.clone());
+        }
+
+        self.operations
+            .iter()
+            .zip(states.iter())
+            .rev()
+            .flat_map(|(operation, state)| operation.reverse().sql(state, backend))
+            .collect()
+    }
+
+    /// Read every migration of a directory, in the order of their names
+    pub fn load_all(dir: &Path) -> Result<Vec<Migration>, MigrationError> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        paths.sort();
+
+        paths
+            .iter()
+            .map(|path| Ok(serde_json::from_str(&fs::read_to_string(path)?)?))
+            .collect()
+    }
+}
+
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+
+impl Database {
+    /// Create the migration history table, unless it exists
+    fn create_history_table(&self) -> Result<(), MigrationError> {
+        let query = <MigrationRecord as sql::SQLTable>::table().add_if_not_exists_string(self.dialect());
+        self.run(move |pool| async move {
+            pool.execute(query.as_str()).await.map(|_| ())
+        })?;
+        Ok(())
+    }
+
+    /// The names of the applied migrations
+    pub fn applied_migrations(&self) -> Result<Vec<String>, MigrationError> {
+        self.create_history_table()?;
+        let query = format!("SELECT name FROM {} ORDER BY id", MigrationRecord::table_name());
+        let names = self.run(move |pool| async move {
+            sqlx::query_scalar::<_, String>(&query).fetch_all(&pool).await
+        })?;
+        Ok(names)
+    }
+
+    /// # Show the migrations
+    /// Lists every migration file, and whether it was applied.
+    pub fn show_migrations(&self) -> Result<Vec<(String, bool)>, MigrationError> {
+        let applied = self.applied_migrations()?;
+        Ok(Migration::load_all(self.get_migrations_dir())?
+            .into_iter()
+            .map(|migration| {
+                let is_applied = applied.contains(&migration.name);
+                (migration.name, is_applied)
+            })
+            .collect())
+    }
+
+    /// # Make migrations
+    /// Compares the tables registered with `register_table` with the state of the existing migrations,
+    /// and writes the differences into a new migration file.
+    /// ## Returns
+    /// This method returns the new migration, or `None` if nothing changed.
+    /// ## Example
+    /// ```rust
+    /// if let Some(migration) = db.make_migrations(Some("add_post_slug"))? {
+    ///     println!("Created migration {}", migration.name);
+    /// }
+    /// ```
+    pub fn make_migrations(&self, name: Option<&str>) -> Result<Option<Migration>, MigrationError> {
+        let dir = self.get_migrations_dir();
+        let migrations = Migration::load_all(dir)?;
+
+        let mut schema = Schema::default();
+        for migration in &migrations {
+            migration.apply(&mut schema);
+        }
+
+        let operations = schema.diff(self.tables(), self.renames());
+        if operations.is_empty() {
+            return Ok(None);
+        }
+
+        let name = format!(
+            "{:04}_{}",
+            migrations.len() + 1,
+            name.unwrap_or(if migrations.is_empty() { "initial" } else { "auto" })
+        );
+        let migration = Migration { name, operations };
+
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(format!("{}.json", migration.name)), serde_json::to_string_pretty(&migration)?)?;
+        Ok(Some(migration))
+    }
+
+    /// # Migrate
+    /// Applies every migration, which was not applied yet, recording them in the `aerielle_migrations` table.
+    /// Each migration runs in a transaction, on databases which support it.
+    /// ## Returns
+    /// This method returns the names of the applied migrations.
+    pub fn migrate(&self) -> Result<Vec<String>, MigrationError> {
+        let applied = self.applied_migrations()?;
+        let mut schema = Schema::default();
+        let mut names = Vec::new();
+
+        for migration in Migration::load_all(self.get_migrations_dir())? {
+            if !applied.contains(&migration.name) {
+                self.run_migration(migration.forwards_sql(&schema, self.backend()), &migration.name, true)?;
+                names.push(migration.name.clone());
+            }
+            migration.apply(&mut schema);
+        }
+        Ok(names)
+    }
+
+    /// # Roll back
+    /// Undoes the last applied migration.
+    /// ## Returns
+    /// This method returns the name of the migration, which was rolled back, or `None` if no migration was applied.
+    pub fn rollback(&self) -> Result<Option<String>, MigrationError> {
+        let Some(last) = self.applied_migrations()?.pop() else { return Ok(None) };
+
+        let mut schema = Schema::default();
+        for migration in Migration::load_all(self.get_migrations_dir())? {
+            if migration.name == last {
+                self.run_migration(migration.backwards_sql(&schema, self.backend()), &last, false)?;
+                return Ok(Some(last));
+            }
+            migration.apply(&mut schema);
+        }
+        Err(MigrationError::Unknown(last))
+    }
+
+    /// # Show the SQL of a migration
+    /// Returns the statements, which `migrate` runs for the given migration, without running them.
+    pub fn migration_sql(&self, name: &str) -> Result<Vec<String>, MigrationError> {
+        let mut schema = Schema::default();
+        for migration in Migration::load_all(self.get_migrations_dir())? {
+            if migration.name == name {
+                return Ok(migration.forwards_sql(&schema, self.backend()));
+            }
+            migration.apply(&mut schema);
+        }
+        Err(MigrationError::Unknown(name.to_string()))
+    }
+
+    /// Run the statements of a migration, and record or remove it in the history
+    fn run_migration(&self, statements: Vec<String>, name: &str, forwards: bool) -> Result<(), MigrationError> {
+        let record = match forwards {
+            true => format!(
+                "INSERT INTO {table} (name, applied_at) VALUES ({p1}, {p2})",
+                table=MigrationRecord::table_name(),
+                p1=self.placeholder(1),
+                p2=self.placeholder(2)
+            ),
+            false => format!(
+                "DELETE FROM {table} WHERE name = {p1}",
+                table=MigrationRecord::table_name(),
+                p1=self.placeholder(1)
+            )
+        };
+        let name = name.to_string();
+        let sqlite = self.backend() == Backend::Sqlite;
+
+        self.run(move |pool| async move {
+            let mut connection = pool.acquire().await?;
+
+            // Rebuilding a table must not cascade to the rows referencing it,
+            // and SQLite ignores this pragma inside of transactions
+            if sqlite {
+                connection.execute("PRAGMA foreign_keys = OFF").await?;
+            }
+
+            let result = async {
+                let mut transaction = sqlx::Connection::begin(&mut *connection).await?;
+                for statement in &statements {
+                    transaction.execute(statement.as_str()).await?;
+                }
+
+                let query = sqlx::query(&record).bind(name);
+                match forwards {
+                    true => query.bind(now()).execute(&mut *transaction).await?,
+                    false => query.execute(&mut *transaction).await?
+                };
+                transaction.commit().await
+            }.await;
+
+            // The connection goes back to the pool, so the pragma is restored even if a statement failed
+            let restored = match sqlite {
+                true => connection.execute("PRAGMA foreign_keys = ON").await.map(|_| ()),
+                false => Ok(())
+            };
+            drop(connection);
+            result.and(restored)?;
+
+            // The other connections notice the changed schema only after preparing a statement,
+            // and SQLite reads the quoted names of unknown columns as strings, so they are closed
+            if sqlite {
+                let mut idle = Vec::new();
+                for _ in 0..pool.num_idle() {
+                    idle.push(pool.acquire().await?);
+                }
+                for connection in idle {
+                    connection.close().await?;
+                }
+            }
+            Ok::<(), sqlx::Error>(())
+        })?;
+        Ok(())
+    }
+}
@@ -1,19 +1,33 @@
+mod backend;
+mod dialect;
 mod database;
 mod table;
 mod sql_types;
 mod column;
+mod query;
+mod migration;
+mod relation;
+#[cfg(all(test, feature = "database_sqlite"))]
+pub(crate) mod tests;
 
+pub use backend::Backend;
+pub use dialect::{Dialect, MySqlDialect, PostgresDialect, SqliteDialect};
 pub use database::Database;
 pub use table::{Table, SQLTable};
-pub use column::Column;
+pub use column::{Column, ForeignKey, OnDelete};
+pub use migration::{Migration, MigrationError, MigrationRecord, Operation, Renames, Schema};
+pub use relation::{ManyToMany, RelatedSet, Relation};
+pub use query::{Assignment, Field, FieldValue, Filter, Order, QuerySet, bind_value};
 pub use sql_types::SQLType;
 pub use sql_types::SQLValue;
+pub use sql_types::{FromColumn, Now, ToColumn};
+pub use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 
-#[cfg(feature = "database_mysql")]
-pub type SQLRow = sqlx::mysql::MySqlRow;
+/// A row of any enabled driver
+pub type SQLRow = sqlx::any::AnyRow;
 
-#[cfg(feature = "database_postgres")]
-pub type SQLRow = sqlx::postgres::PgRow;
+/// The driver of the enabled backends, which dispatches to the backend chosen at runtime
+pub type SQLDatabase = sqlx::Any;
 
-#[cfg(feature = "database_sqlite")]
-pub type SQLRow = sqlx::sqlite::SqliteRow;
+/// A query of the enabled driver, with its parameters bound one by one
+pub type SQLQuery<'q> = sqlx::query::Query<'q, SQLDatabase, <SQLDatabase as sqlx::Database>::Arguments<'q>>;
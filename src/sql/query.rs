@@ -0,0 +1,608 @@
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData
+};
+use sqlx::Error;
+use crate::sql::{
+    Database, DateTime, Dialect, NaiveDate, NaiveDateTime, NaiveTime, Now,
+    Relation, SQLQuery, SQLRow, SQLTable, SQLValue, Utc
+};
+
+
+/// # Field
+/// A column of a table, generated by `#[table]` for every field of the struct, like `User::username()`.
+/// The methods of the field create the filters of a `QuerySet`, and only accept values of the type of the field.
+/// ## Example
+/// ```rust
+/// let filter = User::username().eq("bob").and(User::is_active().eq(true));
+/// ```
+pub struct Field<T, V> {
+    column: &'static str,
+    types: PhantomData<(T, V)>
+}
+
+
+impl<T, V: Into<SQLValue>> Field<T, V> {
+    pub fn new(column: &'static str) -> Self {
+        Self { column, types: PhantomData }
+    }
+
+    /// The name of the column in the database
+    pub fn column(&self) -> &'static str {
+        self.column
+    }
+
+    fn compare(&self, operator: &'static str, value: impl FieldValue<V>) -> Filter<T> {
+        Filter::new(Condition::Compare(self.column, operator, value.into_value()))
+    }
+
+    /// `column = value`, or `column IS NULL` for `None`
+    pub fn eq(&self, value: impl FieldValue<V>) -> Filter<T> {
+        match value.into_value() {
+            SQLValue::Null => self.is_null(),
+            value => Filter::new(Condition::Compare(self.column, "=", value))
+        }
+    }
+
+    /// `column <> value`, or `column IS NOT NULL` for `None`
+    pub fn ne(&self, value: impl FieldValue<V>) -> Filter<T> {
+        match value.into_value() {
+            SQLValue::Null => self.is_not_null(),
+            value => Filter::new(Condition::Compare(self.column, "<>", value))
+        }
+    }
+
+    /// `column > value`
+    pub fn gt(&self, value: impl FieldValue<V>) -> Filter<T> {
+        self.compare(">", value)
+    }
+
+    /// `column >= value`
+    pub fn ge(&self, value: impl FieldValue<V>) -> Filter<T> {
+        self.compare(">=", value)
+    }
+
+    /// `column < value`
+    pub fn lt(&self, value: impl FieldValue<V>) -> Filter<T> {
+        self.compare("<", value)
+    }
+
+    /// `column <= value`
+    pub fn le(&self, value: impl FieldValue<V>) -> Filter<T> {
+        self.compare("<=", value)
+    }
+
+    /// `column LIKE pattern`, where `%` matches any text and `_` matches a single character
+    pub fn like(&self, pattern: &str) -> Filter<T> {
+        Filter::new(Condition::Compare(self.column, "LIKE", SQLValue::Text(pattern.to_string())))
+    }
+
+    /// `column IN (values)`. No row matches an empty list.
+    pub fn in_<I, U>(&self, values: I) -> Filter<T>
+    where
+        I: IntoIterator<Item = U>,
+        U: FieldValue<V>
+    {
+        let values = values
+            .into_iter()
+            .map(FieldValue::into_value)
+            .collect();
+        Filter::new(Condition::In(self.column, values))
+    }
+
+    /// `column IS NULL`
+    pub fn is_null(&self) -> Filter<T> {
+        Filter::new(Condition::Null(self.column))
+    }
+
+    /// `column IS NOT NULL`
+    pub fn is_not_null(&self) -> Filter<T> {
+        self.is_null().not()
+    }
+
+    /// Set the column to a value in `QuerySet::update`
+    pub fn set(&self, value: impl FieldValue<V>) -> Assignment<T> {
+        Assignment { column: self.column, value: value.into_value(), table: PhantomData }
+    }
+
+    /// Sort the rows by the column, from the smallest value
+    pub fn asc(&self) -> Order<T> {
+        Order { column: self.column, descending: false, table: PhantomData }
+    }
+
+    /// Sort the rows by the column, from the largest value
+    pub fn desc(&self) -> Order<T> {
+        Order { column: self.column, descending: true, table: PhantomData }
+    }
+}
+
+
+/// # Field value
+/// A value, which can be compared with or assigned to a `Field` of type `V`.
+/// Fields accept anything convertible into their type, and nullable fields (`Option<V>`)
+/// also accept plain values, so `Post::subtitle().eq("Hello")` works like `.eq(Some(String::from("Hello")))`.
+pub trait FieldValue<V> {
+    fn into_value(self) -> SQLValue;
+}
+
+
+macro_rules! field_value {
+    ($($ty:ty),*) => {
+        $(
+            impl<U: Into<$ty>> FieldValue<$ty> for U {
+                fn into_value(self) -> SQLValue {
+                    self.into().into()
+                }
+            }
+
+            impl FieldValue<Option<$ty>> for Option<$ty> {
+                fn into_value(self) -> SQLValue {
+                    self.into()
+                }
+            }
+
+            impl FieldValue<Option<$ty>> for $ty {
+                fn into_value(self) -> SQLValue {
+                    self.into()
+                }
+            }
+        )*
+    };
+}
+
+field_value!(String, Vec<u8>, bool, i32, f64, NaiveDateTime, DateTime<Utc>, NaiveDate, NaiveTime);
+
+
+impl FieldValue<Option<String>> for &str {
+    fn into_value(self) -> SQLValue {
+        SQLValue::Text(self.to_string())
+    }
+}
+
+enum Condition {
+    Compare(&'static str, &'static str, SQLValue),
+    In(&'static str, Vec<SQLValue>),
+    /// `column IN (SELECT selected FROM table WHERE key = value)`
+    InSelect {
+        column: &'static str,
+        selected: &'static str,
+        table: &'static str,
+        key: &'static str,
+        value: SQLValue
+    },
+    Null(&'static str),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>)
+}
+
+
+impl Condition {
+    /// Write the condition into the SQL string, collecting the values to bind
+    fn write(&self, dialect: &dyn Dialect, sql: &mut String, values: &mut Vec<SQLValue>) {
+        match self {
+            Self::Compare(column, operator, value) => {
+                values.push(value.clone());
+                sql.push_str(&format!("{} {operator} {}", dialect.quote(column), parameter(dialect, value, values.len())));
+            },
+            Self::In(_, list) if list.is_empty() => sql.push_str("1 = 0"),
+            Self::In(column, list) => {
+                let mut placeholders = Vec::with_capacity(list.len());
+                for value in list {
+                    values.push(value.clone());
+                    placeholders.push(parameter(dialect, value, values.len()));
+                }
+                sql.push_str(&format!("{} IN ({})", dialect.quote(column), placeholders.join(", ")));
+            },
+            Self::InSelect { column, selected, table, key, value } => {
+                values.push(value.clone());
+                sql.push_str(&format!(
+                    "{} IN (SELECT {} FROM {} WHERE {} = {})",
+                    dialect.quote(column),
+                    dialect.quote(selected),
+                    dialect.quote(table),
+                    dialect.quote(key),
+                    parameter(dialect, value, values.len())
+                ));
+            },
+            Self::Null(column) => sql.push_str(&format!("{} IS NULL", dialect.quote(column))),
+            Self::And(left, right) | Self::Or(left, right) => {
+                sql.push('(');
+                left.write(dialect, sql, values);
+                sql.push_str(if matches!(self, Self::And(..)) { " AND " } else { " OR " });
+                right.write(dialect, sql, values);
+                sql.push(')');
+            },
+            Self::Not(condition) => {
+                sql.push_str("NOT (");
+                condition.write(dialect, sql, values);
+                sql.push(')');
+            }
+        }
+    }
+}
+
+
+/// The placeholder of the `index`-th bound value, converted to the type of the value if the backend needs it
+fn parameter(dialect: &dyn Dialect, value: &SQLValue, index: usize) -> String {
+    match value.sql_type() {
+        Some(sql_type) => dialect.cast(&sql_type, dialect.placeholder(index)),
+        None => dialect.placeholder(index)
+    }
+}
+
+
+/// # Filter
+/// A condition on the rows of a table, created by the methods of a `Field`.
+/// Filters can be combined with `and`, `or` and `not`.
+pub struct Filter<T> {
+    condition: Condition,
+    table: PhantomData<T>
+}
+
+
+impl<T> Filter<T> {
+    fn new(condition: Condition) -> Self {
+        Self { condition, table: PhantomData }
+    }
+
+    /// `column = value`, for columns without a `Field`
+    pub(crate) fn equals(column: &'static str, value: SQLValue) -> Self {
+        Self::new(Condition::Compare(column, "=", value))
+    }
+
+    /// `column IN (values)`, for columns without a `Field`
+    pub(crate) fn within(column: &'static str, values: Vec<SQLValue>) -> Self {
+        Self::new(Condition::In(column, values))
+    }
+
+    /// `column IN (SELECT selected FROM table WHERE key = value)`, to filter through a junction table
+    pub(crate) fn through(column: &'static str, table: &'static str, selected: &'static str, key: &'static str, value: SQLValue) -> Self {
+        Self::new(Condition::InSelect { column, selected, table, key, value })
+    }
+
+    /// Match the rows matching both filters
+    pub fn and(self, other: Filter<T>) -> Self {
+        Self::new(Condition::And(Box::new(self.condition), Box::new(other.condition)))
+    }
+
+    /// Match the rows matching either filter
+    pub fn or(self, other: Filter<T>) -> Self {
+        Self::new(Condition::Or(Box::new(self.condition), Box::new(other.condition)))
+    }
+
+    /// Match the rows not matching the filter
+    pub fn not(self) -> Self {
+        Self::new(Condition::Not(Box::new(self.condition)))
+    }
+}
+
+
+/// The order of the rows returned by a `QuerySet`, created with `Field::asc` or `Field::desc`
+pub struct Order<T> {
+    column: &'static str,
+    descending: bool,
+    table: PhantomData<T>
+}
+
+
+/// A new value of a column, created with `Field::set`
+pub struct Assignment<T> {
+    column: &'static str,
+    value: SQLValue,
+    table: PhantomData<T>
+}
+
+
+/// Bind a value to a query, with the Rust type matching its SQL type
+pub fn bind_value<'q>(query: SQLQuery<'q>, value: &'q SQLValue) -> SQLQuery<'q> {
+    match value {
+        SQLValue::Text(v) => query.bind(v),
+        SQLValue::Blob(v) => query.bind(v),
+        SQLValue::Boolean(v) | SQLValue::Bit(v) => query.bind(*v),
+        SQLValue::Integer(v) => query.bind(*v),
+        SQLValue::Float(v) => query.bind(*v),
+        SQLValue::TimeStamp(_) | SQLValue::TimeStampTz(_) | SQLValue::Date(_) | SQLValue::Time(_) => query.bind(value.to_text()),
+        SQLValue::Now => query.bind(SQLValue::TimeStamp(NaiveDateTime::now()).to_text()),
+        SQLValue::Null => query.bind(None::<i64>)
+    }
+}
+
+
+/// # Query set
+/// A chainable query on a table, created with `Database::filter_table`.
+/// Nothing is sent to the database until the rows are requested with `all`, `first`, `count` or `exists`.
+/// ## Example
+/// ```rust
+/// let users: Vec<User> = db.filter_table::<User>()
+///     .filter(User::username().like("b%").or(User::is_superuser().eq(true)))
+///     .exclude(User::is_active().eq(false))
+///     .order_by(User::username().asc())
+///     .limit(10)
+///     .all()?;
+/// ```
+pub struct QuerySet<'a, T> {
+    database: &'a Database,
+    filter: Option<Condition>,
+    order: Vec<(&'static str, bool)>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+    table: PhantomData<T>
+}
+
+
+impl<'a, T> QuerySet<'a, T>
+where
+    for<'r> T: SQLTable + sqlx::FromRow<'r, SQLRow> + Send + 'static
+{
+    pub fn new(database: &'a Database) -> Self {
+        Self {
+            database,
+            filter: None,
+            order: Vec::new(),
+            limit: None,
+            offset: None,
+            table: PhantomData
+        }
+    }
+
+    /// Only keep the rows matching the filter. Multiple filters are combined with `AND`.
+    pub fn filter(mut self, filter: Filter<T>) -> Self {
+        self.filter = Some(match self.filter.take() {
+            Some(condition) => Condition::And(Box::new(condition), Box::new(filter.condition)),
+            None => filter.condition
+        });
+        self
+    }
+
+    /// Remove the rows matching the filter
+    pub fn exclude(self, filter: Filter<T>) -> Self {
+        self.filter(filter.not())
+    }
+
+    /// Sort the rows by a column. Further calls sort the rows with equal values.
+    pub fn order_by(mut self, order: Order<T>) -> Self {
+        self.order.push((order.column, order.descending));
+        self
+    }
+
+    /// Return at most the given number of rows
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip the given number of rows
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// The `WHERE` clause of the query, adding its values to the values to bind
+    fn where_clause(&self, values: &mut Vec<SQLValue>) -> String {
+        let mut sql = String::new();
+        if let Some(condition) = &self.filter {
+            sql.push_str(" WHERE ");
+            condition.write(self.database.dialect(), &mut sql, values);
+        }
+        sql
+    }
+
+    /// The `SELECT` query of the query set, with the values to bind
+    fn select(&self, columns: &str) -> (String, Vec<SQLValue>) {
+        let mut values = Vec::new();
+        let where_clause = self.where_clause(&mut values);
+        let dialect = self.database.dialect();
+        let mut sql = format!("SELECT {columns} FROM {}{where_clause}", dialect.quote(&T::table().name));
+
+        if !self.order.is_empty() {
+            let order: Vec<String> = self.order
+                .iter()
+                .map(|(column, descending)| format!("{} {}", dialect.quote(column), if *descending { "DESC" } else { "ASC" }))
+                .collect();
+            sql.push_str(&format!(" ORDER BY {}", order.join(", ")));
+        }
+
+        // MySQL and SQLite only accept an offset after a limit
+        match (self.limit, self.offset) {
+            (Some(limit), _) => sql.push_str(&format!(" LIMIT {limit}")),
+            (None, Some(_)) => sql.push_str(&format!(" LIMIT {}", i64::MAX)),
+            (None, None) => ()
+        }
+        if let Some(offset) = self.offset {
+            sql.push_str(&format!(" OFFSET {offset}"));
+        }
+
+        (sql, values)
+    }
+
+    /// Fetch every matching row
+    pub fn all(&self) -> Result<Vec<T>, Error> {
+        let (sql, values) = self.select(&T::table().select_list(self.database.dialect()));
+        self.database.run(move |pool| async move {
+            let mut query = sqlx::query(&sql);
+            for value in &values {
+                query = bind_value(query, value);
+            }
+
+            let rows = query.fetch_all(&pool).await?;
+            rows.iter().map(T::from_row).collect()
+        })
+    }
+
+    /// Fetch the first matching row
+    pub fn first(self) -> Result<Option<T>, Error> {
+        Ok(self.limit(1).all()?.pop())
+    }
+
+    /// Count the matching rows
+    pub fn count(&self) -> Result<i64, Error> {
+        let (select, values) = self.select("*");
+        let sql = format!("SELECT COUNT(*) FROM ({select}) AS counted");
+        self.database.run(move |pool| async move {
+            let mut query = sqlx::query(&sql);
+            for value in &values {
+                query = bind_value(query, value);
+            }
+
+            let row = query.fetch_one(&pool).await?;
+            sqlx::Row::try_get::<i64, _>(&row, 0)
+        })
+    }
+
+    /// Test whether any row matches
+    pub fn exists(self) -> Result<bool, Error> {
+        let database = self.database;
+        let (sql, values) = self.limit(1).select("1");
+        database.run(move |pool| async move {
+            let mut query = sqlx::query(&sql);
+            for value in &values {
+                query = bind_value(query, value);
+            }
+
+            Ok(query.fetch_optional(&pool).await?.is_some())
+        })
+    }
+
+    /// Run a query, which changes the matching rows, returning the number of affected rows
+    fn execute(&self, sql: String, values: Vec<SQLValue>) -> Result<u64, Error> {
+        self.database.run(move |pool| async move {
+            let mut query = sqlx::query(&sql);
+            for value in &values {
+                query = bind_value(query, value);
+            }
+
+            Ok(query.execute(&pool).await?.rows_affected())
+        })
+    }
+
+    /// # Update the matching rows
+    /// Sets the given columns of every matching row, ignoring the order, the limit and the offset.
+    /// ## Returns
+    /// This method returns the number of updated rows.
+    /// ## Example
+    /// ```rust
+    /// let deactivated: u64 = db.filter_table::<User>()
+    ///     .filter(User::email().like("%@example.com"))
+    ///     .update([User::is_active().set(false)])?;
+    /// ```
+    pub fn update<I>(&self, assignments: I) -> Result<u64, Error>
+    where
+        I: IntoIterator<Item = Assignment<T>>
+    {
+        let mut values = Vec::new();
+        let columns: Vec<String> = assignments
+            .into_iter()
+            .map(|assignment| {
+                let column = self.database.dialect().quote(assignment.column);
+                // NULL is written into the query, as a bound NULL has a type, which may not match the column
+                if assignment.value == SQLValue::Null {
+                    return format!("{column} = NULL");
+                }
+                let placeholder = parameter(self.database.dialect(), &assignment.value, values.len() + 1);
+                values.push(assignment.value);
+                format!("{column} = {placeholder}")
+            })
+            .collect();
+        if columns.is_empty() {
+            return Ok(0);
+        }
+
+        let where_clause = self.where_clause(&mut values);
+        let sql = format!("UPDATE {} SET {}{where_clause}", self.database.dialect().quote(&T::table().name), columns.join(", "));
+        self.execute(sql, values)
+    }
+
+    /// # Delete the matching rows
+    /// Deletes every matching row, ignoring the order, the limit and the offset.
+    /// ## Returns
+    /// This method returns the number of deleted rows.
+    pub fn delete(&self) -> Result<u64, Error> {
+        let mut values = Vec::new();
+        let where_clause = self.where_clause(&mut values);
+        let sql = format!("DELETE FROM {}{where_clause}", self.database.dialect().quote(&T::table().name));
+        self.execute(sql, values)
+    }
+
+    /// # Load the referenced rows
+    /// Fetches the matching rows, and the rows of `R` referenced by their foreign key, in two queries instead of one per row.
+    /// The foreign key is chosen by its marker `K`, which is inferred with `_` if there is only one to `R`.
+    /// ## Example
+    /// ```rust
+    /// for (post, author) in db.filter_table::<Post>().select_related::<User, _>()? {
+    ///     println!("{} by {}", post.title, author.map(|user| user.username).unwrap_or_default());
+    /// }
+    /// let edited = db.filter_table::<Post>().select_related::<User, post::editor_id>()?;
+    /// ```
+    pub fn select_related<R, K>(&self) -> Result<Vec<(T, Option<R>)>, Error>
+    where
+        T: Relation<R, K>,
+        for<'r> R: SQLTable + sqlx::FromRow<'r, SQLRow> + Clone + Send + 'static
+    {
+        let rows = self.all()?;
+        let mut keys = HashSet::new();
+        let values: Vec<SQLValue> = rows
+            .iter()
+            .map(|row| row.foreign_key_value())
+            .filter(|value| *value != SQLValue::Null && keys.insert(key(value)))
+            .collect();
+
+        let related: HashMap<String, R> = match values.is_empty() {
+            true => HashMap::new(),
+            false => self.database
+                .filter_table::<R>()
+                .filter(Filter::within(R::PRIMARY_KEY, values))
+                .all()?
+                .into_iter()
+                .map(|row| (key(&row.primary_key_value()), row))
+                .collect()
+        };
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let referenced = related.get(&key(&row.foreign_key_value())).cloned();
+                (row, referenced)
+            })
+            .collect())
+    }
+
+    /// # Load the referencing rows
+    /// Fetches the matching rows, and the rows of `R` referencing each of them with a foreign key, in two queries instead of one per row.
+    /// The foreign key is chosen by its marker `K`, which is inferred with `_` if `R` has only one to this table.
+    /// ## Example
+    /// ```rust
+    /// for (user, posts) in db.filter_table::<User>().prefetch_related::<Post, post::author_id>()? {
+    ///     println!("{} wrote {} posts", user.username, posts.len());
+    /// }
+    /// ```
+    pub fn prefetch_related<R, K>(&self) -> Result<Vec<(T, Vec<R>)>, Error>
+    where
+        for<'r> R: Relation<T, K> + sqlx::FromRow<'r, SQLRow> + Send + 'static
+    {
+        let rows = self.all()?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values = rows.iter().map(|row| row.primary_key_value()).collect();
+        let mut related: HashMap<String, Vec<R>> = HashMap::new();
+        for row in self.database.filter_table::<R>().filter(Filter::within(R::FOREIGN_KEY, values)).all()? {
+            related.entry(key(&row.foreign_key_value())).or_default().push(row);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let referencing = related.remove(&key(&row.primary_key_value())).unwrap_or_default();
+                (row, referencing)
+            })
+            .collect())
+    }
+}
+
+
+/// A hashable key of a value, to match foreign keys with primary keys
+fn key(value: &SQLValue) -> String {
+    format!("{value:?}")
+}
@@ -0,0 +1,226 @@
+use std::marker::PhantomData;
+use sqlx::Error;
+use crate::sql::{
+    bind_value,
+    Column, Database, Filter, ForeignKey, OnDelete, QuerySet,
+    SQLRow, SQLTable, SQLType, SQLValue, Table
+};
+
+
+/// # Relation
+/// Implemented by `#[table]` for every `#[foreign_key(T)]` column, on the table holding the foreign key.
+/// It loads the referenced row, and lets the referenced row find the rows referencing it with `SQLTable::related`.
+/// `K` is the marker of the column, like `post::author_id`, so a table can reference another table more than once.
+pub trait Relation<T: SQLTable, K>: SQLTable {
+    /// The SQL name of the foreign key column
+    const FOREIGN_KEY: &'static str;
+
+    /// The value of the foreign key
+    fn foreign_key_value(&self) -> SQLValue;
+
+    /// Load the referenced row, or `None` if the foreign key is `NULL` or the row does not exist
+    fn load(&self, database: &Database) -> Result<Option<T>, Error>
+    where
+        for<'r> T: sqlx::FromRow<'r, SQLRow> + Send + 'static
+    {
+        match self.foreign_key_value() {
+            SQLValue::Null => Ok(None),
+            value => database.filter_table::<T>().filter(Filter::equals(T::PRIMARY_KEY, value)).first()
+        }
+    }
+}
+
+
+/// # Many-to-many relation
+/// Implemented by `#[table]` for every `#[many_to_many(R)]` field, on the table declaring the field.
+/// The related rows are linked through a junction table, which has a foreign key to both tables,
+/// and is created with the declaring table by `Database::add_table` and `Database::register_table`.
+pub trait ManyToMany<R: SQLTable>: SQLTable {
+    /// The name of the junction table
+    const JUNCTION: &'static str;
+
+    /// The column of the junction table referencing this table
+    const SOURCE: &'static str;
+
+    /// The column of the junction table referencing the related table
+    const TARGET: &'static str;
+
+    /// The junction table, with a foreign key to both tables, and a unique pair of them
+    fn junction_table() -> Table {
+        let source = Self::table().primary_key;
+        let target = R::table().primary_key;
+        Table {
+            name: String::from(Self::JUNCTION),
+            primary_key: Column {
+                pk: true,
+                unique: false,
+                auto_increment: true,
+                default: SQLValue::Null,
+                null: true,
+                fk: false,
+                m2m: false,
+                index: false,
+                references: None,
+                name: String::from("id"),
+                sql_name: String::from("id"),
+                sql_type: SQLType::Integer
+            },
+            columns: vec![
+                junction_column(Self::SOURCE, Self::table().name, source),
+                junction_column(Self::TARGET, R::table().name, target)
+            ],
+            unique_together: vec![vec![String::from(Self::SOURCE), String::from(Self::TARGET)]]
+        }
+    }
+
+    /// The rows of `R` linked to this row
+    fn forward_set<'a>(&self, database: &'a Database) -> RelatedSet<'a, R> {
+        RelatedSet::new(database, Self::JUNCTION, Self::SOURCE, Self::TARGET, self.primary_key_value())
+    }
+
+    /// The rows of this table linked to a row of `R`
+    fn reverse_set<'a>(row: &R, database: &'a Database) -> RelatedSet<'a, Self>
+    where
+        Self: Sized
+    {
+        RelatedSet::new(database, Self::JUNCTION, Self::TARGET, Self::SOURCE, row.primary_key_value())
+    }
+
+    /// # Filter through the relation
+    /// Matches the rows linked to the given row of `R`.
+    /// ## Example
+    /// ```rust
+    /// let posts: Vec<Post> = db.filter_table::<Post>()
+    ///     .filter(Post::related_to(&rust).or(Post::related_to(&python)))
+    ///     .all()?;
+    /// ```
+    fn related_to(row: &R) -> Filter<Self>
+    where
+        Self: Sized
+    {
+        Filter::through(Self::PRIMARY_KEY, Self::JUNCTION, Self::SOURCE, Self::TARGET, row.primary_key_value())
+    }
+}
+
+
+/// A foreign key column of a junction table, deleted with the row it references
+fn junction_column(name: &str, table: String, primary_key: Column) -> Column {
+    Column {
+        pk: false,
+        unique: false,
+        auto_increment: false,
+        default: primary_key.default,
+        null: false,
+        fk: true,
+        m2m: true,
+        index: false,
+        references: Some(ForeignKey {
+            table,
+            column: primary_key.sql_name,
+            on_delete: OnDelete::Cascade
+        }),
+        name: name.to_string(),
+        sql_name: name.to_string(),
+        sql_type: primary_key.sql_type
+    }
+}
+
+
+/// # Related set
+/// The rows of `R` linked to a row through a junction table, returned by the many-to-many accessors,
+/// like `post.tags(db)`, and by `SQLTable::related_set` on the other side of the relation.
+/// ## Example
+/// ```rust
+/// post.tags(db).add(&rust)?;
+/// post.tags(db).remove(&python)?;
+/// let tags: Vec<Tag> = post.tags(db).all()?;
+/// let posts: Vec<Post> = rust.related_set::<Post>(db).query().order_by(Post::id().desc()).all()?;
+/// ```
+pub struct RelatedSet<'a, R> {
+    database: &'a Database,
+    junction: &'static str,
+    source: &'static str,
+    target: &'static str,
+    value: SQLValue,
+    related: PhantomData<R>
+}
+
+
+impl<'a, R> RelatedSet<'a, R> {
+    fn new(database: &'a Database, junction: &'static str, source: &'static str, target: &'static str, value: SQLValue) -> Self {
+        Self { database, junction, source, target, value, related: PhantomData }
+    }
+}
+
+
+impl<'a, R> RelatedSet<'a, R>
+where
+    for<'r> R: SQLTable + sqlx::FromRow<'r, SQLRow> + Send + 'static
+{
+    /// Start a query on the linked rows
+    pub fn query(&self) -> QuerySet<'a, R> {
+        self.database
+            .filter_table::<R>()
+            .filter(Filter::through(R::PRIMARY_KEY, self.junction, self.target, self.source, self.value.clone()))
+    }
+
+    /// Get every linked row
+    pub fn all(&self) -> Result<Vec<R>, Error> {
+        self.query().all()
+    }
+
+    /// Link a row, unless it is already linked
+    pub fn add(&self, row: &R) -> Result<(), Error> {
+        // The pair is unique, so concurrent links of the same row insert it only once
+        let insert = self.database.dialect().insert_or_ignore(
+            self.junction,
+            &[self.source, self.target],
+            &[self.database.placeholder(1), self.database.placeholder(2)]
+        );
+        let (value, related) = (self.value.clone(), row.primary_key_value());
+
+        self.database.run(move |pool| async move {
+            let query = bind_value(sqlx::query(&insert), &value);
+            bind_value(query, &related).execute(&pool).await.map(|_| ())
+        })
+    }
+
+    /// Unlink a row, returning the number of removed links
+    pub fn remove(&self, row: &R) -> Result<u64, Error> {
+        let delete = format!(
+            "DELETE FROM {junction} WHERE {source} = {p1} AND {target} = {p2}",
+            junction=self.database.dialect().quote(self.junction),
+            source=self.database.dialect().quote(self.source),
+            target=self.database.dialect().quote(self.target),
+            p1=self.database.placeholder(1),
+            p2=self.database.placeholder(2)
+        );
+        let (value, related) = (self.value.clone(), row.primary_key_value());
+
+        self.database.run(move |pool| async move {
+            let query = bind_value(sqlx::query(&delete), &value);
+            bind_value(query, &related)
+                .execute(&pool)
+                .await
+                .map(|result| result.rows_affected())
+        })
+    }
+
+    /// Unlink every row, returning the number of removed links
+    pub fn clear(&self) -> Result<u64, Error> {
+        let delete = format!(
+            "DELETE FROM {junction} WHERE {source} = {p1}",
+            junction=self.database.dialect().quote(self.junction),
+            source=self.database.dialect().quote(self.source),
+            p1=self.database.placeholder(1)
+        );
+        let value = self.value.clone();
+
+        self.database.run(move |pool| async move {
+            bind_value(sqlx::query(&delete), &value)
+                .execute(&pool)
+                .await
+                .map(|result| result.rows_affected())
+        })
+    }
+}
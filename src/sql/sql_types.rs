@@ -1,3 +1,19 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, ValueRef};
+use crate::sql::{SQLQuery, SQLRow};
+
+
+/// The text format of timestamps in the database, which sorts in chronological order
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
+
+/// The text format of dates in the database
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The text format of times in the database
+pub const TIME_FORMAT: &str = "%H:%M:%S%.6f";
+
+
 pub enum RawSQLType {
     Char(usize),
     Varchar(usize),
@@ -31,7 +47,7 @@ pub enum RawSQLType {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SQLType {
     Text,
     Blob,
@@ -40,11 +56,12 @@ pub enum SQLType {
     Integer,
     Float,
     TimeStamp,
+    TimeStampTz,
     Date,
     Time
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SQLValue {
     Text(String),
     Blob(Vec<u8>),
@@ -52,13 +69,47 @@ pub enum SQLValue {
     Bit(bool),
     Integer(i64),
     Float(f64),
-    TimeStamp(u128),
-    Date(u128),
-    Time(u128),
+    TimeStamp(NaiveDateTime),
+    TimeStampTz(DateTime<Utc>),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    /// The current date or time, as the default value of a column: `#[default = "now"]`
+    Now,
     Null
 }
 
 
+impl SQLValue {
+    /// The type of the value, or `None` for `NULL`
+    pub fn sql_type(&self) -> Option<SQLType> {
+        Some(match self {
+            Self::Text(_) => SQLType::Text,
+            Self::Blob(_) => SQLType::Blob,
+            Self::Boolean(_) => SQLType::Boolean,
+            Self::Bit(_) => SQLType::Bit,
+            Self::Integer(_) => SQLType::Integer,
+            Self::Float(_) => SQLType::Float,
+            Self::TimeStamp(_) | Self::Now => SQLType::TimeStamp,
+            Self::TimeStampTz(_) => SQLType::TimeStampTz,
+            Self::Date(_) => SQLType::Date,
+            Self::Time(_) => SQLType::Time,
+            Self::Null => return None
+        })
+    }
+
+    /// The value as text, for the date and time values, which are bound as text
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Self::TimeStamp(v) => Some(v.format(TIMESTAMP_FORMAT).to_string()),
+            Self::TimeStampTz(v) => Some(v.naive_utc().format(TIMESTAMP_FORMAT).to_string()),
+            Self::Date(v) => Some(v.format(DATE_FORMAT).to_string()),
+            Self::Time(v) => Some(v.format(TIME_FORMAT).to_string()),
+            _ => None
+        }
+    }
+}
+
+
 impl std::fmt::Display for SQLType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match *self {
@@ -70,25 +121,283 @@ impl std::fmt::Display for SQLType {
             Self::Float => "FLOAT".to_string(),
             Self::Date => "DATE".to_string(),
             Self::TimeStamp => "TIMESTAMP".to_string(),
+            Self::TimeStampTz => "TIMESTAMP WITH TIME ZONE".to_string(),
             Self::Time => "TIME".to_string(),
         })
     }
 }
 
 
+/// The value as a standard SQL literal. Statements for a database are written with `Dialect::literal` instead.
 impl std::fmt::Display for SQLValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
-            Self::Text(v) => format!("\"{v}\""),
-            Self::Blob(v) => format!("{v:?}"),
-            Self::Boolean(v) => format!("\"{}\"", if *v { "TRUE" } else { "FALSE" }),
-            Self::Bit(v) => format!("{}", if *v { "1" } else { "0" }),
+            Self::Text(v) => format!("'{}'", v.replace('\'', "''")),
+            Self::Blob(v) => format!("X'{}'", v.iter().map(|byte| format!("{byte:02x}")).collect::<String>()),
+            Self::Boolean(v) => String::from(if *v { "TRUE" } else { "FALSE" }),
+            Self::Bit(v) => String::from(if *v { "1" } else { "0" }),
             Self::Integer(v) => format!("{v}"),
             Self::Float(v) => format!("{v}"),
-            Self::Date(v) => format!("{v}"),
-            Self::TimeStamp(v) => format!("{v}"),
-            Self::Time(v) => format!("{v}"),
+            Self::TimeStamp(_) | Self::TimeStampTz(_) | Self::Date(_) | Self::Time(_) => {
+                format!("'{}'", self.to_text().unwrap_or_default())
+            },
+            Self::Now => String::from("CURRENT_TIMESTAMP"),
             Self::Null => String::from("NULL")
         })
     }
-}
\ No newline at end of file
+}
+
+
+impl From<String> for SQLValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+
+impl From<Vec<u8>> for SQLValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Blob(value)
+    }
+}
+
+
+impl From<bool> for SQLValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+
+impl From<i32> for SQLValue {
+    fn from(value: i32) -> Self {
+        Self::Integer(value as i64)
+    }
+}
+
+
+impl From<f64> for SQLValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+
+impl From<NaiveDateTime> for SQLValue {
+    fn from(value: NaiveDateTime) -> Self {
+        Self::TimeStamp(value)
+    }
+}
+
+
+impl From<DateTime<Utc>> for SQLValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        Self::TimeStampTz(value)
+    }
+}
+
+
+impl From<NaiveDate> for SQLValue {
+    fn from(value: NaiveDate) -> Self {
+        Self::Date(value)
+    }
+}
+
+
+impl From<NaiveTime> for SQLValue {
+    fn from(value: NaiveTime) -> Self {
+        Self::Time(value)
+    }
+}
+
+
+impl<T: Into<SQLValue>> From<Option<T>> for SQLValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+
+/// # From column
+/// Reads a value from a column of a row, used by the `FromRow` implementations generated by `#[table]`.
+/// Booleans are stored as integers on SQLite and MySQL, so they are read from either,
+/// and dates and times are parsed from the text they are selected as (see `Dialect::select_column`).
+pub trait FromColumn: Sized {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error>;
+}
+
+
+macro_rules! from_column {
+    ($($ty:ty),*) => {
+        $(
+            impl FromColumn for $ty {
+                fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+                    row.try_get(column)
+                }
+            }
+        )*
+    };
+}
+
+from_column!(String, Vec<u8>, i32, i64, f64);
+
+
+impl FromColumn for bool {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+        row.try_get::<bool, _>(column)
+            .or_else(|_| row.try_get::<i64, _>(column).map(|value| value != 0))
+    }
+}
+
+
+/// Parse a date or time column
+fn parse<T>(row: &SQLRow, column: &str, parse: impl Fn(&str) -> Result<T, chrono::ParseError>) -> Result<T, sqlx::Error> {
+    let text: String = row.try_get(column)?;
+    parse(text.trim()).map_err(|e| sqlx::Error::ColumnDecode { index: column.to_string(), source: Box::new(e) })
+}
+
+
+impl FromColumn for NaiveDateTime {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+        parse(row, column, |text| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").or_else(|_| text.parse()))
+    }
+}
+
+
+/// Timestamps with a time zone are selected in UTC
+impl FromColumn for DateTime<Utc> {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+        NaiveDateTime::from_column(row, column).map(|value| value.and_utc())
+    }
+}
+
+
+impl FromColumn for NaiveDate {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+        parse(row, column, |text| NaiveDate::parse_from_str(text, DATE_FORMAT))
+    }
+}
+
+
+impl FromColumn for NaiveTime {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+        parse(row, column, |text| NaiveTime::parse_from_str(text, "%H:%M:%S%.f"))
+    }
+}
+
+
+/// Nullable columns are read as `None` if they are `NULL`
+impl<T: FromColumn> FromColumn for Option<T> {
+    fn from_column(row: &SQLRow, column: &str) -> Result<Self, sqlx::Error> {
+        match row.try_get_raw(column)?.is_null() {
+            true => Ok(None),
+            false => T::from_column(row, column).map(Some)
+        }
+    }
+}
+
+
+/// # To column
+/// Binds a value to a query, used by the `INSERT` and `UPDATE` queries generated by `#[table]`.
+/// Dates and times are bound as text, which the runtime driver can send to every backend.
+pub trait ToColumn {
+    fn bind<'q>(&'q self, query: SQLQuery<'q>) -> SQLQuery<'q>;
+
+    /// Bind a `NULL` of the type
+    fn bind_null<'q>(query: SQLQuery<'q>) -> SQLQuery<'q>;
+}
+
+
+macro_rules! to_column {
+    ($($ty:ty),*) => {
+        $(
+            impl ToColumn for $ty {
+                fn bind<'q>(&'q self, query: SQLQuery<'q>) -> SQLQuery<'q> {
+                    query.bind(self)
+                }
+
+                fn bind_null<'q>(query: SQLQuery<'q>) -> SQLQuery<'q> {
+                    query.bind(None::<$ty>)
+                }
+            }
+        )*
+    };
+}
+
+to_column!(String, Vec<u8>, bool, i32, i64, f64);
+
+
+macro_rules! to_text_column {
+    ($($ty:ty),*) => {
+        $(
+            impl ToColumn for $ty {
+                fn bind<'q>(&'q self, query: SQLQuery<'q>) -> SQLQuery<'q> {
+                    query.bind(SQLValue::from(*self).to_text().unwrap_or_default())
+                }
+
+                fn bind_null<'q>(query: SQLQuery<'q>) -> SQLQuery<'q> {
+                    query.bind(None::<String>)
+                }
+            }
+        )*
+    };
+}
+
+to_text_column!(NaiveDateTime, DateTime<Utc>, NaiveDate, NaiveTime);
+
+
+impl<T: ToColumn> ToColumn for Option<T> {
+    fn bind<'q>(&'q self, query: SQLQuery<'q>) -> SQLQuery<'q> {
+        match self {
+            Some(value) => value.bind(query),
+            None => T::bind_null(query)
+        }
+    }
+
+    fn bind_null<'q>(query: SQLQuery<'q>) -> SQLQuery<'q> {
+        T::bind_null(query)
+    }
+}
+
+
+/// # Now
+/// The current date or time, set by `#[auto_now]` and `#[auto_now_add]`.
+/// It is truncated to microseconds, like the stored value.
+pub trait Now {
+    fn now() -> Self;
+}
+
+
+impl Now for NaiveDateTime {
+    fn now() -> Self {
+        Utc::now().naive_utc().trunc_subsecs(6)
+    }
+}
+
+
+impl Now for DateTime<Utc> {
+    fn now() -> Self {
+        Utc::now().trunc_subsecs(6)
+    }
+}
+
+
+impl Now for NaiveDate {
+    fn now() -> Self {
+        Utc::now().date_naive()
+    }
+}
+
+
+impl Now for NaiveTime {
+    fn now() -> Self {
+        Utc::now().time().trunc_subsecs(6)
+    }
+}
+
+
+impl<T: Now> Now for Option<T> {
+    fn now() -> Self {
+        Some(T::now())
+    }
+}
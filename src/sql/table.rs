@@ -1,18 +1,21 @@
-use sqlx::Executor;
-use crate::sql::{Column, Database};
+use serde::{Deserialize, Serialize};
+use crate::sql::{Column, Dialect, Database, Filter, ManyToMany, QuerySet, RelatedSet, Relation, SQLQuery, SQLRow, SQLValue};
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     pub name: String,
     pub primary_key: Column,
-    pub columns: Vec<Column>
+    pub columns: Vec<Column>,
+    /// Groups of columns, whose values have to be unique together
+    #[serde(default)]
+    pub unique_together: Vec<Vec<String>>
 }
 
 
 impl Table {
     pub fn new(name: String, primary_key: Column, columns: Vec<Column>) -> Self {
-        Self { name, primary_key, columns }
+        Self { name, primary_key, columns, unique_together: Vec::new() }
     }
     pub fn name(&self) -> &str {
         &self.name
@@ -44,25 +47,117 @@ impl Table {
     /// Aerielle will automatically translate this, and create a table addition string.
     /// For `MySQL`, this string would be:
     /// ```sql
-    /// CREATE TABLE usertable(
-    ///     id INTEGER PRIMARY KEY,
-    ///     username VARCHAR NOT NULL
+    /// CREATE TABLE `usertable`(
+    ///     `id` INTEGER PRIMARY KEY,
+    ///     `username` TEXT NOT NULL DEFAULT ('String')
     /// )
     /// ```
-    pub fn add_string(&self) -> String {
+    pub fn add_string(&self, dialect: &dyn Dialect) -> String {
+        let columns = self.columns();
+        let unique = self.unique_together
+            .iter()
+            .map(|group| format!(
+                "UNIQUE ({})",
+                group
+                    .iter()
+                    .map(|name| match columns.iter().find(|column| column.sql_name == *name) {
+                        Some(column) => dialect.key_column(column),
+                        None => dialect.quote(name)
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        let constraints = columns
+            .iter()
+            .filter_map(|c| c.constraint_string(&self.name, dialect))
+            .chain(unique);
+
         format!(
             "CREATE TABLE {table_name}({columns})",
-            table_name=self.name,
-            columns=self.columns()
+            table_name=dialect.quote(&self.name),
+            columns=columns
                 .iter()
-                .map(|c| c.sql_string())
+                .map(|c| c.sql_string(dialect))
+                .chain(constraints)
                 .collect::<Vec<String>>()
                 .join(",")
         )
     }
+
+    /// The columns of the table for the select list of a query, which can be read into the struct
+    pub fn select_list(&self, dialect: &dyn Dialect) -> String {
+        self.columns()
+            .iter()
+            .map(|column| dialect.select_column(column))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// The table creation string, which does nothing if the table already exists
+    pub fn add_if_not_exists_string(&self, dialect: &dyn Dialect) -> String {
+        self.add_string(dialect).replacen("CREATE TABLE", "CREATE TABLE IF NOT EXISTS", 1)
+    }
 }
 
 
 pub trait SQLTable {
+    /// The SQL name of the primary key column
+    const PRIMARY_KEY: &'static str;
+
     fn table() -> Table;
+
+    /// The junction tables of the many-to-many fields, created and migrated with the table
+    fn junction_tables() -> Vec<Table> {
+        Vec::new()
+    }
+
+    /// The value of the primary key, or `SQLValue::Null` if the table has no `#[primary_key]` field
+    fn primary_key_value(&self) -> SQLValue;
+
+    /// Bind the values of the columns to an `INSERT` query, skipping auto-increment columns.
+    /// The primary key comes first, followed by the other columns in the order of `Table::columns`.
+    fn bind_insert<'q>(&'q self, query: SQLQuery<'q>) -> SQLQuery<'q>;
+
+    /// Bind the value of a column to a query, by the SQL name of the column
+    fn bind_column<'q>(&'q self, column: &str, query: SQLQuery<'q>) -> SQLQuery<'q>;
+
+    /// Set the primary key after the row was inserted, if it is an auto-increment integer.
+    /// Fails if the generated key does not fit into the type of the field.
+    fn set_primary_key(&mut self, id: i64) -> Result<(), sqlx::Error>;
+
+    /// Set the `#[auto_now]` fields, and the `#[auto_now_add]` fields of a new row, to the current time.
+    /// Returns the SQL names of the columns, which were set.
+    fn auto_now(&mut self, _inserting: bool) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// # Get the related rows
+    /// Starts a query on the rows of another table, which reference this row with a foreign key (the one-to-many side).
+    /// The foreign key is chosen by its marker `K`, which is inferred with `_` if `R` has only one to this table.
+    /// ## Example
+    /// ```rust
+    /// let posts: Vec<Post> = user.related::<Post, _>(db).order_by(Post::id().desc()).all()?;
+    /// let edited: Vec<Post> = user.related::<Post, post::editor_id>(db).all()?;
+    /// ```
+    fn related<'a, R, K>(&self, database: &'a Database) -> QuerySet<'a, R>
+    where
+        Self: Sized,
+        for<'r> R: Relation<Self, K> + sqlx::FromRow<'r, SQLRow> + Send + 'static
+    {
+        database.filter_table::<R>().filter(Filter::equals(R::FOREIGN_KEY, self.primary_key_value()))
+    }
+
+    /// # Get the linked rows
+    /// The rows of another table, which declares a many-to-many field to this table.
+    /// ## Example
+    /// ```rust
+    /// let posts: Vec<Post> = tag.related_set::<Post>(db).all()?;
+    /// ```
+    fn related_set<'a, R>(&self, database: &'a Database) -> RelatedSet<'a, R>
+    where
+        Self: Sized,
+        for<'r> R: ManyToMany<Self> + sqlx::FromRow<'r, SQLRow> + Send + 'static
+    {
+        R::reverse_set(self, database)
+    }
 }
\ No newline at end of file
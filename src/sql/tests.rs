@@ -0,0 +1,727 @@
+use sqlx::Error;
+use table_macro::table;
+use tempfile::TempDir;
+use crate::sql::{self, Backend, Database, ManyToMany, Migration, Operation, Renames, Schema, SQLTable};
+
+
+/// Connect to a new SQLite database in a temporary directory, which is deleted with the returned `TempDir`
+pub(crate) fn database() -> (Database, TempDir) {
+    let dir = TempDir::new().expect("Unable to create a temporary directory");
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+    (Database::connect(Box::leak(url.into_boxed_str())), dir)
+}
+
+
+/// A table without a `#[primary_key]` field, which gets an implicit `id` column
+#[table]
+struct Note {
+    #[not_null]
+    body: Text
+}
+
+
+#[test]
+fn table_without_primary_key() {
+    let (mut db, _dir) = database();
+    db.add_table::<Note>();
+
+    let mut note = Note::new(String::from("Hello"));
+    assert_eq!(db.add_to_table(note.clone()).unwrap(), 1);
+
+    assert!(matches!(note.save(&db), Err(Error::ColumnNotFound(column)) if column == "id"));
+    assert!(matches!(note.update_fields(&db, &["body"]), Err(Error::ColumnNotFound(column)) if column == "id"));
+    assert!(matches!(note.delete(&db), Err(Error::ColumnNotFound(column)) if column == "id"));
+    assert_eq!(db.get_table::<Note>().len(), 1);
+}
+
+
+/// A table with nullable columns
+#[table]
+struct Article {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    title: Text,
+
+    subtitle: Text,
+    rating: Integer
+}
+
+
+/// The titles of the articles matching the filter, in the order of insertion
+fn titles(db: &Database, filter: sql::Filter<Article>) -> Vec<String> {
+    db.filter_table::<Article>()
+        .filter(filter)
+        .order_by(Article::id().asc())
+        .all()
+        .unwrap()
+        .into_iter()
+        .map(|article| article.title)
+        .collect()
+}
+
+
+fn articles() -> (Database, TempDir) {
+    let (mut db, dir) = database();
+    db.add_table::<Article>();
+    db.insert(Article::new(0, String::from("first"), Some(String::from("Hello")), Some(5))).unwrap();
+    db.insert(Article::new(0, String::from("second"), None, Some(3))).unwrap();
+    db.insert(Article::new(0, String::from("third"), Some(String::from("World")), None)).unwrap();
+    (db, dir)
+}
+
+
+#[test]
+fn nullable_fields_compare_with_plain_values() {
+    let (db, _dir) = articles();
+
+    assert_eq!(titles(&db, Article::subtitle().eq("Hello")), ["first"]);
+    assert_eq!(titles(&db, Article::subtitle().eq(String::from("World"))), ["third"]);
+    assert_eq!(titles(&db, Article::rating().eq(3)), ["second"]);
+    assert_eq!(titles(&db, Article::rating().ne(3)), ["first"]);
+    assert_eq!(titles(&db, Article::rating().gt(4)), ["first"]);
+    assert_eq!(titles(&db, Article::subtitle().in_(["Hello", "World"])), ["first", "third"]);
+    assert_eq!(titles(&db, Article::rating().in_([3, 5])), ["first", "second"]);
+}
+
+
+#[test]
+fn nullable_fields_compare_with_options() {
+    let (db, _dir) = articles();
+
+    assert_eq!(titles(&db, Article::subtitle().eq(Some(String::from("Hello")))), ["first"]);
+    assert_eq!(titles(&db, Article::subtitle().eq(None)), ["second"]);
+    assert_eq!(titles(&db, Article::subtitle().ne(None)), ["first", "third"]);
+    assert_eq!(titles(&db, Article::rating().in_([Some(3), Some(5)])), ["first", "second"]);
+}
+
+
+#[test]
+fn nullable_fields_is_null() {
+    let (db, _dir) = articles();
+
+    assert_eq!(titles(&db, Article::subtitle().is_null()), ["second"]);
+    assert_eq!(titles(&db, Article::rating().is_null()), ["third"]);
+    assert_eq!(titles(&db, Article::rating().is_not_null()), ["first", "second"]);
+}
+
+
+#[test]
+fn nullable_fields_set_plain_values_and_null() {
+    let (db, _dir) = articles();
+
+    db.filter_table::<Article>()
+        .filter(Article::title().eq("second"))
+        .update([Article::subtitle().set("Again"), Article::rating().set(None)])
+        .unwrap();
+
+    let second = db.filter_table::<Article>().filter(Article::title().eq("second")).first().unwrap().unwrap();
+    assert_eq!(second.subtitle.as_deref(), Some("Again"));
+    assert_eq!(second.rating, None);
+}
+
+
+/// A table with every date and time type, set to the current time
+#[table]
+struct Event {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    #[auto_now_add]
+    created_at: TimeStamp,
+
+    #[not_null]
+    #[auto_now]
+    updated_at: TimeStampTz,
+
+    #[not_null]
+    #[auto_now]
+    day: Date,
+
+    #[not_null]
+    #[auto_now]
+    time: Time
+}
+
+
+/// Read the event back from the database
+fn stored(db: &Database, event: &Event) -> Event {
+    db.filter_table::<Event>()
+        .filter(Event::id().eq(event.id))
+        .first()
+        .unwrap()
+        .expect("The event was not stored")
+}
+
+
+fn assert_same_times(event: &Event, stored: &Event) {
+    assert_eq!(event.created_at, stored.created_at);
+    assert_eq!(event.updated_at, stored.updated_at);
+    assert_eq!(event.day, stored.day);
+    assert_eq!(event.time, stored.time);
+}
+
+
+#[test]
+fn auto_now_round_trip() {
+    let (mut db, _dir) = database();
+    db.add_table::<Event>();
+
+    let mut event = db.insert(Event::new(0, Default::default(), Default::default(), Default::default(), Default::default())).unwrap();
+    assert_same_times(&event, &stored(&db, &event));
+
+    event.save(&db).unwrap();
+    assert_same_times(&event, &stored(&db, &event));
+}
+
+
+/// A table with the current time as default values
+#[table]
+struct Stamp {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    #[default = "now"]
+    at: TimeStamp,
+
+    #[not_null]
+    #[default = "now"]
+    time: Time
+}
+
+
+#[test]
+fn now_defaults_have_microseconds() {
+    let (mut db, _dir) = database();
+    db.add_table::<Stamp>();
+
+    let (at, time): (String, String) = db.run(|pool| async move {
+        sqlx::query("INSERT INTO \"stamp\" DEFAULT VALUES").execute(&pool).await?;
+        sqlx::query_as("SELECT \"at\", \"time\" FROM \"stamp\"").fetch_one(&pool).await
+    }).unwrap();
+
+    let pattern = regex::Regex::new(r"^(\d{4}-\d{2}-\d{2} )?\d{2}:\d{2}:\d{2}\.\d{6}$").unwrap();
+    assert!(pattern.is_match(&at), "{at}");
+    assert!(pattern.is_match(&time), "{time}");
+    assert!(at.ends_with(&time[8..]), "{at} {time}");
+}
+
+
+#[table(name = "author")]
+struct Author {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    name: Text
+}
+
+
+/// The author table, after it was renamed
+#[table(name = "writer")]
+struct Writer {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    name: Text
+}
+
+
+/// The first version of the book table
+#[table(name = "book")]
+struct Book {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    title: Text,
+
+    #[index]
+    slug: Text,
+
+    #[foreign_key(Author)]
+    author_id: Integer
+}
+
+
+/// The second version of the book table, with a renamed, an altered and an added column
+#[table(name = "book")]
+struct BookV2 {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    name: Text,
+
+    #[index]
+    slug: Text,
+
+    #[not_null]
+    #[foreign_key(Author)]
+    author_id: Integer,
+
+    pages: Integer
+}
+
+
+/// The schema after the first migration
+fn first_schema() -> Schema {
+    let mut schema = Schema::default();
+    Migration { name: String::from("0001_initial"), operations: first_migration() }.apply(&mut schema);
+    schema
+}
+
+
+fn first_migration() -> Vec<Operation> {
+    Schema::default().diff(&[Author::table(), Book::table()], &Renames::default())
+}
+
+
+/// The renames from the first to the second version of the book table
+fn book_renames() -> Renames {
+    Renames { columns: vec![(String::from("book"), String::from("title"), String::from("name"))], ..Renames::default() }
+}
+
+
+fn second_migration() -> Migration {
+    Migration {
+        name: String::from("0002_auto"),
+        operations: first_schema().diff(&[Author::table(), BookV2::table()], &book_renames())
+    }
+}
+
+
+#[test]
+fn diff_creates_tables_and_indexes() {
+    let operations = first_migration();
+    assert_eq!(operations.len(), 3);
+    assert!(matches!(&operations[0], Operation::CreateTable { table } if table.name == "author"));
+    assert!(matches!(&operations[1], Operation::CreateTable { table } if table.name == "book" && table.columns().iter().all(|c| !c.index)));
+    assert_eq!(operations[2], Operation::CreateIndex { table: String::from("book"), column: String::from("slug") });
+
+    let mut schema = first_schema();
+    assert!(schema.diff(&[Author::table(), Book::table()], &Renames::default()).is_empty());
+    schema.tables.retain(|table| table.name == "author");
+    assert!(matches!(&schema.diff(&[Author::table()], &Renames::default())[..], []));
+}
+
+
+#[test]
+fn diff_does_not_guess_renames() {
+    let schema = first_schema();
+
+    let operations = schema.diff(&[Writer::table(), Book::table()], &Renames::default());
+    assert!(matches!(&operations[0], Operation::CreateTable { table } if table.name == "writer"));
+    assert!(matches!(&operations[1], Operation::DropTable { table } if table.name == "author"));
+
+    let operations = schema.diff(&[Author::table(), BookV2::table()], &Renames::default());
+    assert!(!operations.iter().any(|operation| matches!(operation, Operation::RenameColumn { .. })));
+    assert!(operations.iter().any(|operation| matches!(operation, Operation::DropColumn { column, .. } if column.sql_name == "title")));
+    assert!(operations.iter().any(|operation| matches!(operation, Operation::AddColumn { column, .. } if column.sql_name == "name")));
+}
+
+
+#[test]
+fn diff_renames_given_tables_and_columns() {
+    let schema = first_schema();
+    let renames = Renames { tables: vec![(String::from("author"), String::from("writer"))], ..Renames::default() };
+    assert_eq!(
+        schema.diff(&[Writer::table(), Book::table()], &renames),
+        vec![Operation::RenameTable { old: String::from("author"), new: String::from("writer") }]
+    );
+
+    let operations = second_migration().operations;
+    assert_eq!(operations[0], Operation::RenameColumn {
+        table: String::from("book"),
+        old: String::from("title"),
+        new: String::from("name")
+    });
+    assert!(matches!(&operations[1], Operation::AddColumn { column, .. } if column.sql_name == "pages"));
+    assert!(matches!(&operations[2], Operation::AlterColumn { old, new, .. } if old.null && !new.null && new.sql_name == "author_id"));
+    assert_eq!(operations.len(), 3);
+}
+
+
+#[test]
+fn migration_sql_of_mysql() {
+    let migration = second_migration();
+    assert_eq!(migration.forwards_sql(&first_schema(), Backend::MySql), vec![
+        "ALTER TABLE `book` RENAME COLUMN `title` TO `name`",
+        "ALTER TABLE `book` ADD COLUMN `pages` INTEGER",
+        "UPDATE `book` SET `author_id` = 0 WHERE `author_id` IS NULL",
+        "ALTER TABLE `book` MODIFY COLUMN `author_id` INTEGER NOT NULL DEFAULT 0"
+    ]);
+    assert_eq!(migration.backwards_sql(&first_schema(), Backend::MySql), vec![
+        "ALTER TABLE `book` MODIFY COLUMN `author_id` INTEGER",
+        "ALTER TABLE `book` DROP COLUMN `pages`",
+        "ALTER TABLE `book` RENAME COLUMN `name` TO `title`"
+    ]);
+}
+
+
+#[test]
+fn migration_sql_of_postgres() {
+    let migration = second_migration();
+    assert_eq!(migration.forwards_sql(&first_schema(), Backend::Postgres), vec![
+        "ALTER TABLE \"book\" RENAME COLUMN \"title\" TO \"name\"",
+        "ALTER TABLE \"book\" ADD COLUMN \"pages\" INTEGER",
+        "UPDATE \"book\" SET \"author_id\" = 0 WHERE \"author_id\" IS NULL",
+        "ALTER TABLE \"book\" ALTER COLUMN \"author_id\" SET DEFAULT 0",
+        "ALTER TABLE \"book\" ALTER COLUMN \"author_id\" SET NOT NULL"
+    ]);
+    assert_eq!(migration.backwards_sql(&first_schema(), Backend::Postgres), vec![
+        "ALTER TABLE \"book\" ALTER COLUMN \"author_id\" DROP DEFAULT",
+        "ALTER TABLE \"book\" ALTER COLUMN \"author_id\" DROP NOT NULL",
+        "ALTER TABLE \"book\" DROP COLUMN \"pages\"",
+        "ALTER TABLE \"book\" RENAME COLUMN \"name\" TO \"title\""
+    ]);
+}
+
+
+#[test]
+fn migration_sql_rebuilds_sqlite_tables() {
+    let sql = second_migration().forwards_sql(&first_schema(), Backend::Sqlite);
+    assert_eq!(sql[0], "ALTER TABLE \"book\" RENAME COLUMN \"title\" TO \"name\"");
+    assert_eq!(sql[1], "ALTER TABLE \"book\" ADD COLUMN \"pages\" INTEGER");
+    assert!(sql[3].starts_with("CREATE TABLE \"_aerielle_new_book\""));
+    assert_eq!(sql[4], "INSERT INTO \"_aerielle_new_book\" (\"id\", \"name\", \"slug\", \"author_id\", \"pages\") \
+        SELECT \"id\", \"name\", \"slug\", \"author_id\", \"pages\" FROM \"book\"");
+    assert_eq!(sql[5..], [
+        "DROP TABLE \"book\"",
+        "ALTER TABLE \"_aerielle_new_book\" RENAME TO \"book\"",
+        "CREATE INDEX \"book_slug_idx\" ON \"book\" (\"slug\")"
+    ]);
+}
+
+
+#[test]
+fn migrate_and_roll_back() {
+    let (mut db, dir) = database();
+    db.set_migrations_dir(dir.path().join("migrations"));
+    db.register_table::<Author>();
+    db.register_table::<Book>();
+    assert_eq!(db.make_migrations(None).unwrap().unwrap().name, "0001_initial");
+    assert_eq!(db.migrate().unwrap(), vec!["0001_initial"]);
+
+    let author = db.insert(Author::new(0, String::from("Ursula"))).unwrap().id;
+    db.insert(Book::new(0, String::from("Lathe"), Some(String::from("lathe")), Some(author))).unwrap();
+    db.insert(Book::new(0, String::from("Dispossessed"), None, None)).unwrap();
+
+    db.register_table::<BookV2>();
+    db.rename_column("book", "title", "name");
+    assert_eq!(db.make_migrations(None).unwrap().unwrap().name, "0002_auto");
+    assert!(db.make_migrations(None).unwrap().is_none());
+    assert_eq!(db.migrate().unwrap(), vec!["0002_auto"]);
+    assert_eq!(db.show_migrations().unwrap(), vec![(String::from("0001_initial"), true), (String::from("0002_auto"), true)]);
+
+    // The rebuilt table keeps its rows, and the foreign keys are enforced again
+    let books = db.filter_table::<BookV2>().order_by(BookV2::id().asc()).all().unwrap();
+    assert_eq!(books.iter().map(|book| book.name.as_str()).collect::<Vec<_>>(), ["Lathe", "Dispossessed"]);
+    assert_eq!((books[0].author_id, books[1].author_id), (author, 0));
+    assert!(db.insert(BookV2::new(0, String::from("Missing"), None, 404, None)).is_err());
+
+    assert_eq!(db.rollback().unwrap().as_deref(), Some("0002_auto"));
+    let books = db.filter_table::<Book>().order_by(Book::id().asc()).all().unwrap();
+    assert_eq!(books.iter().map(|book| book.title.as_str()).collect::<Vec<_>>(), ["Lathe", "Dispossessed"]);
+    assert_eq!(db.applied_migrations().unwrap(), vec!["0001_initial"]);
+
+    assert_eq!(db.rollback().unwrap().as_deref(), Some("0001_initial"));
+    assert_eq!(db.rollback().unwrap(), None);
+    assert!(db.filter_table::<Author>().count().is_err());
+}
+
+
+#[test]
+fn failed_migration_enables_foreign_keys() {
+    let (mut db, dir) = database();
+    let migrations = dir.path().join("migrations");
+    db.set_migrations_dir(&migrations);
+
+    // The book table does not exist, so the migration fails after creating the author table
+    let migration = Migration {
+        name: String::from("0001_broken"),
+        operations: vec![Operation::CreateTable { table: Author::table() }, Operation::DropTable { table: Book::table() }]
+    };
+    std::fs::create_dir_all(&migrations).unwrap();
+    std::fs::write(migrations.join("0001_broken.json"), serde_json::to_string(&migration).unwrap()).unwrap();
+    assert!(db.migrate().is_err());
+    assert!(db.applied_migrations().unwrap().is_empty());
+
+    // Connections go back to the pool on a background task
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let enabled = db.run(|pool| async move {
+        let mut connections = Vec::new();
+        for _ in 0..pool.num_idle() {
+            connections.push(pool.acquire().await.unwrap());
+        }
+        let mut enabled = Vec::new();
+        for connection in &mut connections {
+            enabled.push(sqlx::query_scalar::<_, i32>("PRAGMA foreign_keys").fetch_one(&mut **connection).await.unwrap());
+        }
+        enabled
+    });
+    assert!(!enabled.is_empty());
+    assert!(enabled.iter().all(|enabled| *enabled == 1));
+}
+
+
+/// A table with two foreign keys to the same table
+#[table]
+struct Review {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    #[foreign_key(Book, on_delete = "cascade")]
+    book_id: Integer,
+
+    #[foreign_key(Author)]
+    author_id: Integer,
+
+    #[foreign_key(Author)]
+    editor_id: Integer
+}
+
+
+/// Two authors, a book, and a review of it by the first author, edited by the second one, and an anonymous review
+fn reviews() -> (Database, TempDir, Vec<Author>) {
+    let (mut db, dir) = database();
+    db.add_table::<Author>();
+    db.add_table::<Book>();
+    db.add_table::<Review>();
+
+    let authors = vec![
+        db.insert(Author::new(0, String::from("Ursula"))).unwrap(),
+        db.insert(Author::new(0, String::from("Octavia"))).unwrap()
+    ];
+    let book = db.insert(Book::new(0, String::from("Kindred"), None, Some(authors[1].id))).unwrap();
+    db.insert(Review::new(0, book.id, Some(authors[0].id), Some(authors[1].id))).unwrap();
+    db.insert(Review::new(0, book.id, None, None)).unwrap();
+    (db, dir, authors)
+}
+
+
+#[test]
+fn load_referenced_rows() {
+    let (db, _dir, _) = reviews();
+    let reviews = db.filter_table::<Review>().order_by(Review::id().asc()).all().unwrap();
+
+    assert_eq!(reviews[0].author(&db).unwrap().unwrap().name, "Ursula");
+    assert_eq!(reviews[0].editor(&db).unwrap().unwrap().name, "Octavia");
+    assert_eq!(reviews[0].book(&db).unwrap().unwrap().title, "Kindred");
+    assert!(reviews[1].author(&db).unwrap().is_none());
+    assert!(reviews[1].editor(&db).unwrap().is_none());
+}
+
+
+#[test]
+fn select_related_follows_the_chosen_foreign_key() {
+    let (db, _dir, _) = reviews();
+    let names = |related: Vec<(Review, Option<Author>)>| -> Vec<Option<String>> {
+        related.into_iter().map(|(_, author)| author.map(|author| author.name)).collect()
+    };
+    let reviews = db.filter_table::<Review>().order_by(Review::id().asc());
+
+    assert_eq!(names(reviews.select_related::<Author, review::author_id>().unwrap()), [Some(String::from("Ursula")), None]);
+    assert_eq!(names(reviews.select_related::<Author, review::editor_id>().unwrap()), [Some(String::from("Octavia")), None]);
+
+    let books = reviews.select_related::<Book, _>().unwrap();
+    assert!(books.iter().all(|(review, book)| book.as_ref().is_some_and(|book| book.id == review.book_id)));
+}
+
+
+#[test]
+fn prefetch_related_follows_the_chosen_foreign_key() {
+    let (db, _dir, authors) = reviews();
+    let counts = |related: Vec<(Author, Vec<Review>)>| -> Vec<(String, usize)> {
+        related.into_iter().map(|(author, reviews)| (author.name, reviews.len())).collect()
+    };
+    let query = db.filter_table::<Author>().order_by(Author::id().asc());
+
+    assert_eq!(
+        counts(query.prefetch_related::<Review, review::author_id>().unwrap()),
+        [(String::from("Ursula"), 1), (String::from("Octavia"), 0)]
+    );
+    assert_eq!(
+        counts(query.prefetch_related::<Review, review::editor_id>().unwrap()),
+        [(String::from("Ursula"), 0), (String::from("Octavia"), 1)]
+    );
+    assert_eq!(query.prefetch_related::<Book, _>().unwrap()[1].1.len(), 1);
+
+    assert_eq!(authors[1].related::<Review, review::editor_id>(&db).count().unwrap(), 1);
+    assert_eq!(authors[1].related::<Review, review::author_id>(&db).count().unwrap(), 0);
+    assert!(db.filter_table::<Author>().filter(Author::id().eq(404)).prefetch_related::<Review, review::author_id>().unwrap().is_empty());
+}
+
+
+#[table]
+struct Tag {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    label: Text
+}
+
+
+/// A table with a many-to-many relation, linked through the `shelf_tags` junction table
+#[table]
+struct Shelf {
+    #[primary_key]
+    #[auto_increment]
+    id: Integer,
+
+    #[not_null]
+    label: Text,
+
+    #[many_to_many(Tag)]
+    tags: ManyToMany
+}
+
+
+/// Two shelves and three tags, without links
+fn shelves() -> (Database, TempDir, Vec<Shelf>, Vec<Tag>) {
+    let (mut db, dir) = database();
+    db.add_table::<Tag>();
+    db.add_table::<Shelf>();
+
+    let shelves = ["fiction", "poetry"]
+        .map(|label| db.insert(Shelf::new(0, String::from(label))).unwrap())
+        .to_vec();
+    let tags = ["new", "used", "signed"]
+        .map(|label| db.insert(Tag::new(0, String::from(label))).unwrap())
+        .to_vec();
+    (db, dir, shelves, tags)
+}
+
+
+fn labels(tags: Vec<Tag>) -> Vec<String> {
+    let mut labels: Vec<String> = tags.into_iter().map(|tag| tag.label).collect();
+    labels.sort();
+    labels
+}
+
+
+#[test]
+fn related_set_adds_each_row_once() {
+    let (db, _dir, shelves, tags) = shelves();
+    shelves[0].tags(&db).add(&tags[0]).unwrap();
+    shelves[0].tags(&db).add(&tags[1]).unwrap();
+    shelves[0].tags(&db).add(&tags[0]).unwrap();
+    shelves[1].tags(&db).add(&tags[0]).unwrap();
+
+    assert_eq!(labels(shelves[0].tags(&db).all().unwrap()), ["new", "used"]);
+    assert_eq!(labels(shelves[1].tags(&db).all().unwrap()), ["new"]);
+    assert_eq!(shelves[0].tags(&db).query().filter(Tag::label().eq("used")).count().unwrap(), 1);
+
+    // The other side of the relation
+    let linked: Vec<String> = tags[0].related_set::<Shelf>(&db).all().unwrap().into_iter().map(|shelf| shelf.label).collect();
+    assert_eq!(linked, ["fiction", "poetry"]);
+    assert!(tags[2].related_set::<Shelf>(&db).all().unwrap().is_empty());
+}
+
+
+#[test]
+fn related_set_removes_and_clears_links() {
+    let (db, _dir, shelves, tags) = shelves();
+    for tag in &tags {
+        shelves[0].tags(&db).add(tag).unwrap();
+    }
+    shelves[1].tags(&db).add(&tags[0]).unwrap();
+
+    assert_eq!(shelves[0].tags(&db).remove(&tags[1]).unwrap(), 1);
+    assert_eq!(shelves[0].tags(&db).remove(&tags[1]).unwrap(), 0);
+    assert_eq!(labels(shelves[0].tags(&db).all().unwrap()), ["new", "signed"]);
+
+    assert_eq!(shelves[0].tags(&db).clear().unwrap(), 2);
+    assert!(shelves[0].tags(&db).all().unwrap().is_empty());
+    // The links of the other shelf and the tags themselves are kept
+    assert_eq!(labels(shelves[1].tags(&db).all().unwrap()), ["new"]);
+    assert_eq!(db.filter_table::<Tag>().count().unwrap(), 3);
+
+    // Deleting a row deletes its links
+    tags[0].delete(&db).unwrap();
+    assert!(shelves[1].tags(&db).all().unwrap().is_empty());
+}
+
+
+#[test]
+fn related_to_filters_through_the_junction_table() {
+    let (db, _dir, shelves, tags) = shelves();
+    shelves[0].tags(&db).add(&tags[0]).unwrap();
+    shelves[0].tags(&db).add(&tags[1]).unwrap();
+    shelves[1].tags(&db).add(&tags[1]).unwrap();
+
+    let matching = |filter: sql::Filter<Shelf>| -> Vec<String> {
+        db.filter_table::<Shelf>()
+            .filter(filter)
+            .order_by(Shelf::id().asc())
+            .all()
+            .unwrap()
+            .into_iter()
+            .map(|shelf| shelf.label)
+            .collect()
+    };
+    assert_eq!(matching(Shelf::related_to(&tags[0])), ["fiction"]);
+    assert_eq!(matching(Shelf::related_to(&tags[1])), ["fiction", "poetry"]);
+    assert!(matching(Shelf::related_to(&tags[2])).is_empty());
+    assert_eq!(matching(Shelf::related_to(&tags[0]).or(Shelf::related_to(&tags[2]))), ["fiction"]);
+    assert_eq!(matching(Shelf::related_to(&tags[1]).and(Shelf::label().eq("poetry"))), ["poetry"]);
+}
+
+
+/// A table with text keys and ordinary text columns
+#[table(name = "profile", unique_together = "site, handle")]
+struct Profile {
+    #[primary_key]
+    #[not_null]
+    username: Text,
+
+    #[unique]
+    email: Text,
+
+    #[index]
+    city: Text,
+
+    #[not_null]
+    biography: Text,
+
+    site: Text,
+    handle: Text
+}
+
+
+#[test]
+fn mysql_text_columns() {
+    let dialect = Backend::MySql.dialect();
+    assert_eq!(Profile::table().add_string(dialect), "CREATE TABLE `profile`(\
+        `username` VARCHAR(255) PRIMARY KEY NOT NULL DEFAULT 'String',\
+        `email` VARCHAR(255) UNIQUE,\
+        `city` VARCHAR(255),\
+        `biography` TEXT NOT NULL DEFAULT ('String'),\
+        `site` TEXT,\
+        `handle` TEXT,\
+        UNIQUE (`site`(255), `handle`(255)))");
+
+    // Sessions do not fit into 255 characters
+    let sessions = crate::session::SessionTable::table().add_string(dialect);
+    assert!(sessions.contains("`session_key` VARCHAR(255) PRIMARY KEY"));
+    assert!(sessions.contains("`session_data` TEXT NOT NULL DEFAULT ('String')"));
+
+    // The other backends have no length limit on text
+    assert!(Profile::table().add_string(Backend::Postgres.dialect()).contains("\"site\", \"handle\""));
+    assert!(Profile::table().add_string(Backend::Sqlite.dialect()).contains("\"biography\" VARCHAR NOT NULL DEFAULT 'String'"));
+}
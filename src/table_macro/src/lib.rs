@@ -2,9 +2,11 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
     parse_macro_input,
+    punctuated::Punctuated,
     ItemStruct,
     Meta,
-    Lit
+    Lit,
+    Token
 };
 
 
@@ -13,16 +15,49 @@ fn to_snake_case(text: String) -> String {
 }
 
 
+/// The type inside of an `Option`, like `Text` in `Option<Text>`
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let syn::Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => match arguments.args.first()? {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+
 #[proc_macro_attribute]
-pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
+pub fn table(attribute: TokenStream, item: TokenStream) -> TokenStream {
     // Parse the input
     let input = parse_macro_input!(item as ItemStruct);
 
     // Get the struct name
     let struct_name = &input.ident;
 
-    // Convert the struct name to snake case
-    let table_name = to_snake_case(struct_name.to_string());
+    // Convert the struct name to snake case, unless a name was given: #[table(name = "name")]
+    // Collect the groups of columns, which have to be unique together: #[table(unique_together = "user_id, group_id")]
+    let mut table_name = to_snake_case(struct_name.to_string());
+    let mut unique_together: Vec<Vec<String>> = Vec::new();
+    let attribute_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            let name: syn::LitStr = meta.value()?.parse()?;
+            table_name = name.value();
+            Ok(())
+        } else if meta.path.is_ident("unique_together") {
+            let columns: syn::LitStr = meta.value()?.parse()?;
+            unique_together.push(columns.value().split(',').map(|column| column.trim().to_string()).collect());
+            Ok(())
+        } else {
+            Err(meta.error("Unknown table attribute. Expected: #[table(name = \"name\", unique_together = \"column, column\")]"))
+        }
+    });
+    parse_macro_input!(attribute with attribute_parser);
 
     // Get the visibility
     let vis = &input.vis;
@@ -42,6 +77,8 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
             null: false,
             fk: false,
             m2m: false,
+            index: false,
+            references: None,
             name: String::from("id"),
             sql_name: String::from("id"),
             sql_type: sql::SQLType::Integer
@@ -53,35 +90,110 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
     let mut fields = Vec::new();  // Fields of the struct
     let mut field_names = Vec::new();  // Field names of the struct, without the type
     let mut get_variables = Vec::new();  // Helper for the From<sql::Row> method
+    let mut primary_key_name = String::from("id");  // The SQL name of the primary key
+    let mut primary_key_value = quote! { sql::SQLValue::Null };
+    let mut relations = Vec::new();  // The implementations of sql::Relation and sql::ManyToMany for the related tables
+    let mut foreign_keys = Vec::new();  // The marker types of the foreign keys, which tell the relations apart
+    let module = syn::Ident::new(&to_snake_case(struct_name.to_string()), struct_name.span());
+    let mut junctions = Vec::new();  // The junction tables of the many-to-many fields
+    let mut accessors = Vec::new();  // The fields of the query builder, like `User::username()`
+    let mut column_binds = Vec::new();  // Bind the value of a column by its SQL name
+    let mut binds = Vec::new();  // Bind the values of the columns to an INSERT query
+    let mut touch = Vec::new();  // Set the auto_now and auto_now_add fields
+    let mut set_primary_key = quote! {  // Set the auto-increment primary key after an INSERT
+        fn set_primary_key(&mut self, _id: i64) -> Result<(), sqlx::Error> {
+            Ok(())
+        }
+    };
     for field in &input.fields {
         // Field name
         let name =  field.ident
             .clone()
             .expect("Unable to get name of field");
 
-        // SQL type
-        let sql_type = &field.ty;
+        // SQL type, which may be wrapped in an Option to make the column nullable: Option<Text>
+        let (sql_type, explicit_option) = match option_inner(&field.ty) {
+            Some(inner) => (inner, true),
+            None => (&field.ty, false)
+        };
+
+        // Many-to-many field: #[many_to_many(Table)] name: ManyToMany
+        // It is stored in a junction table instead of a column, and only generates the accessor of the related rows
+        if quote! { #sql_type }.to_string() == "ManyToMany" {
+            let attr = field.attrs
+                .iter()
+                .find(|attr| attr.path().is_ident("many_to_many"))
+                .expect("A ManyToMany field requires the related table: #[many_to_many(Table)]");
+            let arguments = attr
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .expect("The many-to-many field has to be given in the following format: #[many_to_many(Table, table = \"junction_table\")]");
+
+            let mut target: Option<syn::Path> = None;
+            let mut junction = format!("{table_name}_{name}");
+            for argument in arguments {
+                match argument {
+                    Meta::Path(path) => target = Some(path),
+                    Meta::NameValue(nv) if nv.path.is_ident("table") => match &nv.value {
+                        syn::Expr::Lit(syn::ExprLit { lit: Lit::Str(lit_str), .. }) => junction = lit_str.value(),
+                        _ => panic!("Expected string literal for the junction table")
+                    },
+                    _ => panic!("Unknown many-to-many attribute. Expected: #[many_to_many(Table, table = \"junction_table\")]")
+                }
+            }
+            let target = target.expect("A ManyToMany field requires the related table: #[many_to_many(Table)]");
+
+            // The columns of the junction table, like `post_id` and `tag_id`, or `from_user_id` and `to_user_id`
+            let source_column = format!("{}_id", to_snake_case(struct_name.to_string()));
+            let target_column = format!(
+                "{}_id",
+                to_snake_case(target.segments.last().expect("Empty table path").ident.to_string())
+            );
+            let (source_column, target_column) = match source_column == target_column {
+                true => (format!("from_{source_column}"), format!("to_{target_column}")),
+                false => (source_column, target_column)
+            };
+
+            accessors.push(
+                quote! {
+                    /// The rows linked through the many-to-many field
+                    pub fn #name<'a>(&self, database: &'a sql::Database) -> sql::RelatedSet<'a, #target> {
+                        <Self as sql::ManyToMany<#target>>::forward_set(self, database)
+                    }
+                }
+            );
+            relations.push(
+                quote! {
+                    impl sql::ManyToMany<#target> for #struct_name {
+                        const JUNCTION: &'static str = #junction;
+                        const SOURCE: &'static str = #source_column;
+                        const TARGET: &'static str = #target_column;
+                    }
+                }
+            );
+            junctions.push(
+                quote! {
+                    <Self as sql::ManyToMany<#target>>::junction_table()
+                }
+            );
+            continue;
+        }
 
         // Rust representation of SQL type
-        let ty = match quote! { #sql_type }.to_string().as_str() {
+        let base_ty = match quote! { #sql_type }.to_string().as_str() {
             "Text" => quote! { String },
             "Blob" => quote! { Vec<u8> },
             "Boolean" => quote! { bool },
             "Bit" => quote! { bool },
             "Integer" => quote! { i32 },
             "Float" => quote! { f64 },
-            "TimeStamp" => quote! { u128 },
-            "Date" => quote! { u128 },
-            "Time" => quote! { u128 },
+            "TimeStamp" => quote! { sql::NaiveDateTime },
+            "TimeStampTz" => quote! { sql::DateTime<sql::Utc> },
+            "Date" => quote! { sql::NaiveDate },
+            "Time" => quote! { sql::NaiveTime },
             _ => panic!("Unknown type")
         };
+        let is_date_time = matches!(quote! { #sql_type }.to_string().as_str(), "TimeStamp" | "TimeStampTz" | "Date" | "Time");
 
-        // Add the Rust representations of the SQL columns
-        fields.push(
-            quote! {
-                #name: #ty
-            }
-        );
 
         // Add the field name
         field_names.push(
@@ -95,9 +207,15 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
         let mut unique = false;
         let mut auto_increment = false;
         let mut default = quote! {};
+        let mut default_now = false;
+        let mut auto_now = false;
+        let mut auto_now_add = false;
         let mut null = true;
         let mut fk = false;
-        let mut m2m = false;
+        let mut index = false;
+        let mut target: Option<syn::Path> = None;  // The table referenced by the foreign key
+        let mut on_delete = quote! { sql::OnDelete::NoAction };
+        let mut set_null = false;
         let mut sql_name = to_snake_case(name.to_string());
         let column_name = name.to_string();
 
@@ -127,6 +245,12 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                             if let Lit::Str(lit_str) = &expr_lit.lit {
                                 let value = lit_str.value();
 
+                                // The current date or time: #[default = "now"]
+                                if value == "now" && is_date_time {
+                                    default_now = true;
+                                    continue;
+                                }
+
                                 // Parse the default value
                                 default = match quote! { #sql_type }.to_string().as_str() {
                                     // String
@@ -167,22 +291,12 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                                         Err(e) => panic!("Unable to parse float: {e}")
                                     },
 
-                                    // Timestamp
-                                    "TimeStamp" => match value.parse::<u128>() {
-                                        Ok(i) => quote! { #i },
-                                        Err(e) => panic!("Unable to parse timestamp: {e}")
-                                    },
-
-                                    // Date
-                                    "Date" => match value.parse::<u128>() {
-                                        Ok(i) => quote! { #i },
-                                        Err(e) => panic!("Unable to parse date: {e}")
-                                    },
-
-                                    // Time
-                                    "Time" => match value.parse::<u128>() {
-                                        Ok(i) => quote! { #i },
-                                        Err(e) => panic!("Unable to parse time: {e}")
+                                    // Timestamp, like "2024-01-31T12:00:00", or "2024-01-31T12:00:00Z" with a time zone
+                                    // Date, like "2024-01-31"
+                                    // Time, like "12:00:00"
+                                    "TimeStamp" | "TimeStampTz" | "Date" | "Time" => {
+                                        let message = format!("Unable to parse the default value of {name}: {value}");
+                                        quote! { #value.parse().expect(#message) }
                                     },
 
                                     // Unknown
@@ -223,17 +337,98 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                 null = false;
             }
 
-            // Foreign key
+            // Foreign key: #[foreign_key] or #[foreign_key(Table, on_delete = "cascade")]
             if attr.path().is_ident("foreign_key") {
                 fk = true;
+
+                if let Meta::List(_) = &attr.meta {
+                    let arguments = attr
+                        .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                        .expect("The foreign key has to be given in the following format: #[foreign_key(Table, on_delete = \"cascade\")]");
+
+                    for argument in arguments {
+                        match argument {
+                            Meta::Path(path) => target = Some(path),
+                            Meta::NameValue(nv) if nv.path.is_ident("on_delete") => {
+                                let policy = match &nv.value {
+                                    syn::Expr::Lit(syn::ExprLit { lit: Lit::Str(lit_str), .. }) => lit_str.value(),
+                                    _ => panic!("Expected string literal for on_delete")
+                                };
+                                on_delete = match policy.to_lowercase().replace(' ', "_").as_str() {
+                                    "cascade" => quote! { sql::OnDelete::Cascade },
+                                    "restrict" => quote! { sql::OnDelete::Restrict },
+                                    "set_null" => quote! { sql::OnDelete::SetNull },
+                                    "no_action" => quote! { sql::OnDelete::NoAction },
+                                    _ => panic!("on_delete must be either \"cascade\", \"restrict\", \"set_null\" or \"no_action\"")
+                                };
+                                set_null = policy.to_lowercase().replace(' ', "_") == "set_null";
+                            },
+                            _ => panic!("Unknown foreign key attribute. Expected: #[foreign_key(Table, on_delete = \"cascade\")]")
+                        }
+                    }
+                }
             }
 
             // Many-to-many field
             if attr.path().is_ident("many_to_many") {
-                m2m = true;
+                panic!("A many-to-many field is not a column, so it has to be declared as #[many_to_many(Table)] {name}: ManyToMany")
+            }
+
+            // Index
+            if attr.path().is_ident("index") {
+                index = true;
+            }
+
+            // Set to the current time whenever the row is saved
+            if attr.path().is_ident("auto_now") {
+                auto_now = true;
+            }
+
+            // Set to the current time when the row is inserted
+            if attr.path().is_ident("auto_now_add") {
+                auto_now_add = true;
             }
         }
 
+        if (auto_now || auto_now_add) && !is_date_time {
+            panic!("#[auto_now] and #[auto_now_add] require a TimeStamp, TimeStampTz, Date or Time column, but {name} is not one")
+        }
+
+        if set_null && !null {
+            panic!("on_delete = \"set_null\" requires a nullable column, so remove #[not_null] from {name}")
+        }
+        if explicit_option && !null {
+            panic!("An Option column is nullable, so remove #[not_null] from {name}")
+        }
+        if explicit_option && pk {
+            panic!("A primary key can not be NULL, so it can not be an Option")
+        }
+
+        // Nullable columns are Options, except for the primary key
+        let ty = match null && !pk {
+            true => quote! { Option<#base_ty> },
+            false => base_ty
+        };
+
+        // Add the Rust representations of the SQL columns
+        fields.push(
+            quote! {
+                #name: #ty
+            }
+        );
+
+        // The REFERENCES constraint of the foreign key
+        let references = match &target {
+            Some(target) => quote! {
+                Some(sql::ForeignKey {
+                    table: String::from(#target::table_name()),
+                    column: String::from(<#target as sql::SQLTable>::PRIMARY_KEY),
+                    on_delete: #on_delete
+                })
+            },
+            None => quote! { None }
+        };
+
         // If the default value is empty, provide pre-defined default values
         if default.is_empty() {
             default = match quote! { #sql_type }.to_string().as_str() {
@@ -243,12 +438,14 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                 "Bit" => quote! { false },
                 "Integer" => quote! { 0 },
                 "Float" => quote! { 0.0 },
-                "TimeStamp" => quote! { 0 },
-                "Date" => quote! { 0 },
-                "Time" => quote! { 0 },
+                "TimeStamp" | "TimeStampTz" | "Date" | "Time" => quote! { Default::default() },
                 _ => panic!("Unknown type")
             };
         }
+        let default = match default_now {
+            true => quote! { sql::SQLValue::Now },
+            false => quote! { sql::SQLValue::#sql_type(#default) }
+        };
 
         // Create the SQL column
         let column = quote! {
@@ -256,10 +453,12 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                 pk: #pk,
                 unique: #unique,
                 auto_increment: #auto_increment,
-                default: sql::SQLValue::#sql_type(#default),
+                default: #default,
                 null: #null,
                 fk: #fk,
-                m2m: #m2m,
+                m2m: false,
+                index: #index,
+                references: #references,
                 name: String::from(#column_name),
                 sql_name: String::from(#sql_name),
                 sql_type: sql::SQLType::#sql_type
@@ -271,13 +470,128 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
             false => columns.push(column)
         }
 
+        column_binds.push(
+            quote! {
+                #sql_name => sql::ToColumn::bind(&self.#name, query)
+            }
+        );
+
+        // Auto-increment columns are generated by the database, so they are not inserted
+        if !auto_increment {
+            let bind = quote! {
+                let query = sql::ToColumn::bind(&self.#name, query);
+            };
+            match pk {
+                true => binds.insert(0, bind),
+                false => binds.push(bind)
+            }
+        }
+        if auto_now || auto_now_add {
+            let inserting = match auto_now {
+                true => quote! { true },
+                false => quote! { inserting }
+            };
+            touch.push(
+                quote! {
+                    if #inserting {
+                        self.#name = sql::Now::now();
+                        touched.push(#sql_name);
+                    }
+                }
+            );
+        }
+        if pk {
+            primary_key_name = sql_name.clone();
+            primary_key_value = quote! { self.#name.clone().into() };
+        }
+        if pk && auto_increment && quote! { #sql_type }.to_string() == "Integer" {
+            set_primary_key = quote! {
+                fn set_primary_key(&mut self, id: i64) -> Result<(), sqlx::Error> {
+                    self.#name = i32::try_from(id).map_err(|e| sqlx::Error::ColumnDecode {
+                        index: #sql_name.to_string(),
+                        source: Box::new(e)
+                    })?;
+                    Ok(())
+                }
+            };
+        }
+
         get_variables.push(
             quote! {
-                #name: row.try_get(#sql_name)?
+                #name: <#ty as sql::FromColumn>::from_column(row, #sql_name)?
+            }
+        );
+
+        accessors.push(
+            quote! {
+                pub fn #name() -> sql::Field<Self, #ty> {
+                    sql::Field::new(#sql_name)
+                }
             }
-        )
+        );
+
+        // Load the referenced row with `post.author(db)` for `author_id`, or `post.get_author(db)` for `author`
+        if let Some(target) = &target {
+            let field = name.to_string();
+            let loader = syn::Ident::new(
+                &match field.strip_suffix("_id") {
+                    Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+                    _ => format!("get_{field}")
+                },
+                name.span()
+            );
+
+            accessors.push(
+                quote! {
+                    /// Load the row referenced by the foreign key
+                    pub fn #loader(&self, database: &sql::Database) -> Result<Option<#target>, sqlx::Error> {
+                        <Self as sql::Relation<#target, #module::#name>>::load(self, database)
+                    }
+                }
+            );
+            relations.push(
+                quote! {
+                    impl sql::Relation<#target, #module::#name> for #struct_name {
+                        const FOREIGN_KEY: &'static str = #sql_name;
+
+                        fn foreign_key_value(&self) -> sql::SQLValue {
+                            self.#name.clone().into()
+                        }
+                    }
+                }
+            );
+            foreign_keys.push(name.clone());
+        }
     }
 
+    // Set the auto_now and auto_now_add fields before saving the row
+    let auto_now = match touch.is_empty() {
+        true => quote! {},
+        false => quote! {
+            #[allow(unused_variables)]
+            fn auto_now(&mut self, inserting: bool) -> Vec<&'static str> {
+                let mut touched = Vec::new();
+                #(#touch)*
+                touched
+            }
+        }
+    };
+
+    // The foreign keys are told apart by a marker type named after the field, like `post::author_id`
+    let markers = match foreign_keys.is_empty() {
+        true => quote! {},
+        false => {
+            let doc = format!("The foreign keys of `{struct_name}`, which choose the relation of `select_related`, `prefetch_related` and `related`");
+            quote! {
+                #[doc = #doc]
+                #[allow(non_camel_case_types)]
+                #vis mod #module {
+                    #(pub struct #foreign_keys;)*
+                }
+            }
+        }
+    };
+
     // Write the struct and it's implementations
     let expanded = quote! {
         #[derive(Debug, Clone)]
@@ -296,18 +610,31 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                 #table_name
             }
 
-            /*pub fn remove(&self, db: &mut sql::Database) {
-                db.execute(
-                    format!("DELETE FROM {table_name} WHERE {pk_column} = {pk}",
-                        table_name=Self::table_name(),
-                        pk_column=Self::pk_column(),
-                        pk=self.pk()
-                    )
-                );
-            }*/
+            #(#accessors)*
+
+            /// Update every column of the row with the primary key of the instance
+            pub fn save(&mut self, database: &sql::Database) -> Result<u64, sqlx::Error> {
+                database.save(self, &[])
+            }
+
+            /// Update the given fields of the row with the primary key of the instance, like `post.update_fields(db, &["title"])`
+            pub fn update_fields(&mut self, database: &sql::Database, fields: &[&str]) -> Result<u64, sqlx::Error> {
+                database.save(self, fields)
+            }
+
+            /// Delete the row with the primary key of the instance
+            pub fn delete(&self, database: &sql::Database) -> Result<u64, sqlx::Error> {
+                database.remove_from_table(self.clone())
+            }
         }
 
         impl sql::SQLTable for #struct_name {
+            const PRIMARY_KEY: &'static str = #primary_key_name;
+
+            fn primary_key_value(&self) -> sql::SQLValue {
+                #primary_key_value
+            }
+
             fn table() -> sql::Table {
                 sql::Table {
                     name: String::from(#table_name ),
@@ -316,11 +643,36 @@ pub fn table(_attribute: TokenStream, item: TokenStream) -> TokenStream {
                         let mut vector = Vec::new();
                         #(vector.push(#columns));* ;
                         vector
-                    }
+                    },
+                    unique_together: vec![#(vec![#(String::from(#unique_together)),*]),*]
                 }
             }
+
+            fn junction_tables() -> Vec<sql::Table> {
+                vec![#(#junctions),*]
+            }
+
+            fn bind_insert<'q>(&'q self, query: sql::SQLQuery<'q>) -> sql::SQLQuery<'q> {
+                #(#binds)*
+                query
+            }
+
+            fn bind_column<'q>(&'q self, column: &str, query: sql::SQLQuery<'q>) -> sql::SQLQuery<'q> {
+                match column {
+                    #(#column_binds,)*
+                    _ => panic!("Unknown column {column} of table {}", #table_name)
+                }
+            }
+
+            #set_primary_key
+
+            #auto_now
         }
 
+        #markers
+
+        #(#relations)*
+
         impl<'r> sqlx::FromRow<'r, sql::SQLRow> for #struct_name {
             fn from_row(row: &'r sql::SQLRow) -> Result<Self, sqlx::Error> {
                 Ok(